@@ -0,0 +1,113 @@
+use chrono::Utc;
+use serenity::model::id::{GuildId, UserId};
+use serenity::prelude::*;
+use tracing::{error, info};
+
+use kekw_db::models::audit_entry::AuditEntry;
+use kekw_db::{audit_entries, KekPool};
+
+use crate::settings;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Sync + Send>>;
+
+// A change someone made to movie night
+pub struct Entry {
+    pub actor: UserId,
+    // The command that made the change, like deletesub
+    pub action: &'static str,
+    // What was changed, like a submission or a period
+    pub target: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+impl Entry {
+    pub fn new(actor: UserId, action: &'static str, target: impl Into<String>) -> Self {
+        Entry {
+            actor,
+            action,
+            target: target.into(),
+            before: None,
+            after: None,
+        }
+    }
+
+    pub fn before(mut self, before: impl Into<String>) -> Self {
+        self.before = Some(before.into());
+        self
+    }
+
+    pub fn after(mut self, after: impl Into<String>) -> Self {
+        self.after = Some(after.into());
+        self
+    }
+}
+
+// One line describing a stored entry
+pub fn describe(entry: &AuditEntry) -> String {
+    let change = match (&entry.before, &entry.after) {
+        (Some(before), Some(after)) => format!(": {} → {}", before, after),
+        (Some(before), None) => format!(": was {}", before),
+        (None, Some(after)) => format!(": now {}", after),
+        (None, None) => String::new(),
+    };
+
+    format!("<@{}> {} {}{}", entry.actor, entry.action, entry.target, change)
+}
+
+// Store the entry and post it to the guild's log channel, if it has one.
+// The change already happened, so failures are only logged and never fail the command.
+pub async fn record(ctx: &Context, db_pool: &KekPool, guild_id: GuildId, entry: Entry) {
+    let created = Utc::now().naive_utc();
+
+    let stored = match audit_entries::create_entry(
+        db_pool,
+        &guild_id.to_string(),
+        &entry.actor.to_string(),
+        entry.action,
+        &entry.target,
+        entry.before.as_deref(),
+        entry.after.as_deref(),
+        created,
+    ) {
+        Ok(stored) => stored,
+        Err(why) => {
+            error!("Failed to store audit entry for {} in guild {}: {:?}", entry.action, guild_id, why);
+            return;
+        }
+    };
+    info!("Audit: {} in guild {}", describe(&stored), guild_id);
+
+    if let Err(why) = mirror(ctx, db_pool, guild_id, &stored).await {
+        error!("Failed to post audit entry in guild {}: {:?}", guild_id, why);
+    }
+}
+
+async fn mirror(ctx: &Context, db_pool: &KekPool, guild_id: GuildId, entry: &AuditEntry) -> Result<()> {
+    let log_channel = match settings::load(db_pool, guild_id)?.log_channel {
+        Some(log_channel) => log_channel,
+        None => return Ok(()),
+    };
+
+    log_channel
+        .send_message(&ctx.http, |m| {
+            m.content(describe(entry));
+            // The actor is named, not pinged
+            m.allowed_mentions(|am| am.empty_parse());
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+// The most recent entries for the guild, newest first
+pub fn recent(db_pool: &KekPool, guild_id: GuildId, count: usize) -> Result<Vec<AuditEntry>> {
+    Ok(audit_entries::get_recent_entries(db_pool, &guild_id.to_string(), count as i64)?)
+}
+
+// When the entry was made, for display
+pub fn timestamp(entry: &AuditEntry) -> String {
+    // Stored as UTC
+    entry.created.format("%Y-%m-%d %H:%M UTC").to_string()
+}
@@ -0,0 +1,140 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use tracing::{info, warn};
+
+use kekw_db::KekPool;
+
+use crate::config::Config;
+use crate::{links, omdb_cache};
+use crate::tmdb::{TmdbClient, WatchProvider};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+// Where a movie can be watched in a region
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Availability {
+    pub region: String,
+    // Page listing every offer
+    pub link: Option<String>,
+    // Subscription services
+    pub stream: Vec<String>,
+    // Free, with or without ads
+    pub free: Vec<String>,
+    pub rent: Vec<String>,
+    pub buy: Vec<String>,
+}
+
+impl Availability {
+    pub fn is_empty(&self) -> bool {
+        self.stream.is_empty() && self.free.is_empty() && self.rent.is_empty() && self.buy.is_empty()
+    }
+}
+
+#[async_trait]
+pub trait AvailabilityProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    // None if the provider doesn't know the movie
+    async fn availability(&self, imdb_id: &str, region: &str) -> Result<Option<Availability>>;
+}
+
+fn provider_names(providers: &[WatchProvider]) -> Vec<String> {
+    providers.iter().map(|p| p.provider_name.clone()).collect()
+}
+
+#[async_trait]
+impl AvailabilityProvider for TmdbClient {
+    fn name(&self) -> &'static str {
+        "tmdb"
+    }
+
+    async fn availability(&self, imdb_id: &str, region: &str) -> Result<Option<Availability>> {
+        let tmdb_id = match self.find_by_imdb_id(imdb_id).await? {
+            Some(found) => found.id,
+            None => return Ok(None),
+        };
+
+        let mut regions = self.watch_providers(tmdb_id).await?;
+
+        Ok(Some(match regions.remove(&region.to_uppercase()) {
+            Some(offers) => {
+                let mut free = provider_names(&offers.free);
+                free.extend(provider_names(&offers.ads));
+
+                Availability {
+                    region: region.to_uppercase(),
+                    link: offers.link,
+                    stream: provider_names(&offers.flatrate),
+                    free,
+                    rent: provider_names(&offers.rent),
+                    buy: provider_names(&offers.buy),
+                }
+            }
+            // Known movie that isn't offered anywhere in the region
+            None => Availability {
+                region: region.to_uppercase(),
+                link: None,
+                stream: Vec::new(),
+                free: Vec::new(),
+                rent: Vec::new(),
+                buy: Vec::new(),
+            },
+        }))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProviderKind {
+    Tmdb,
+    // Don't look up availability
+    Disabled,
+}
+
+impl FromStr for ProviderKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "tmdb" => Ok(ProviderKind::Tmdb),
+            "none" | "off" => Ok(ProviderKind::Disabled),
+            _ => Err(format!("Unknown availability provider {}, expected tmdb or none", s)),
+        }
+    }
+}
+
+fn provider(config: &Config) -> Option<Box<dyn AvailabilityProvider>> {
+    match config.availability.provider {
+        ProviderKind::Tmdb => Some(Box::new(TmdbClient::from_config(&config.metadata))),
+        ProviderKind::Disabled => None,
+    }
+}
+
+// Where a submission can be watched, cached per movie and region.
+// Failures only mean the embed goes without it, so they are logged and ignored.
+pub async fn lookup(db_pool: &KekPool, config: &Config, imdb_id: &str) -> Option<Availability> {
+    if !links::is_imdb_id(imdb_id) {
+        return None;
+    }
+
+    let provider = provider(config)?;
+    let region = &config.availability.region;
+    let key = omdb_cache::availability_key(region, imdb_id);
+
+    if let Some(availability) = omdb_cache::cached::<Availability>(db_pool, config, &key) {
+        return availability;
+    }
+
+    info!("Availability cache miss for {}", key);
+    match provider.availability(imdb_id, region).await {
+        Ok(availability) => {
+            omdb_cache::store(db_pool, &key, &availability);
+            availability
+        }
+        Err(why) => {
+            warn!("{} availability lookup of {} failed: {}", provider.name(), imdb_id, why);
+            None
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use std::sync::Arc;
+
+use serenity::async_trait;
+
+use kekw_db::KekPool;
+
+use crate::availability::{self, Availability};
+use crate::config::Config;
+use crate::links::{self, MovieLink};
+use crate::metadata::{FallbackProvider, MovieDetails, SearchMatch};
+use crate::{movie_info, omdb_cache};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Sync + Send>>;
+
+// Movie details and where to watch, None when the lookup fails
+#[async_trait]
+pub trait Catalog: Send + Sync {
+    async fn details(&self, imdb_id: &str) -> Option<MovieDetails>;
+    async fn availability(&self, imdb_id: &str) -> Option<Availability>;
+    // The title and IMDb id of the movie a link points to, None if it can't be found
+    async fn resolve(&self, link: MovieLink) -> Result<Option<(String, String)>>;
+    async fn search(&self, title: &str) -> Result<Vec<SearchMatch>>;
+}
+
+// The configured metadata and availability providers, cached in the database
+pub struct LiveCatalog {
+    db_pool: KekPool,
+    config: Arc<Config>,
+    metadata: Arc<FallbackProvider>,
+}
+
+impl LiveCatalog {
+    pub fn new(db_pool: KekPool, config: Arc<Config>, metadata: Arc<FallbackProvider>) -> Self {
+        LiveCatalog {
+            db_pool,
+            config,
+            metadata,
+        }
+    }
+}
+
+#[async_trait]
+impl Catalog for LiveCatalog {
+    async fn details(&self, imdb_id: &str) -> Option<MovieDetails> {
+        movie_info::lookup(&self.db_pool, &self.config, self.metadata.as_ref(), imdb_id).await
+    }
+
+    async fn availability(&self, imdb_id: &str) -> Option<Availability> {
+        availability::lookup(&self.db_pool, &self.config, imdb_id).await
+    }
+
+    async fn resolve(&self, link: MovieLink) -> Result<Option<(String, String)>> {
+        links::resolve(&self.db_pool, &self.config, self.metadata.as_ref(), link).await
+    }
+
+    async fn search(&self, title: &str) -> Result<Vec<SearchMatch>> {
+        omdb_cache::search(&self.db_pool, &self.config, self.metadata.as_ref(), title, None).await
+    }
+}
@@ -0,0 +1,223 @@
+use serenity::async_trait;
+use serenity::client::Context;
+use serenity::model::channel::ReactionType;
+use serenity::model::id::{ChannelId, GuildId, MessageId, RoleId, UserId};
+
+use crate::utils::{self, Confirmation};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Sync + Send>>;
+
+// An embed as plain data, so fakes can check what would have been sent
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Embed {
+    pub title: String,
+    pub description: Option<String>,
+    pub thumbnail: Option<String>,
+    // Name and value, one per line
+    pub fields: Vec<(String, String)>,
+}
+
+// The Discord operations movie night uses
+#[async_trait]
+pub trait Discord: Send + Sync {
+    // Send a message, pinging the role if there is one and nobody else
+    async fn send_message(&self, channel_id: ChannelId, content: &str, ping: Option<RoleId>) -> Result<MessageId>;
+    async fn send_embed(&self, channel_id: ChannelId, embed: Embed) -> Result<MessageId>;
+    async fn react(&self, channel_id: ChannelId, message_id: MessageId, emoji: ReactionType) -> Result<()>;
+    // How many reactions each of the emoji has on the message, in the same order
+    async fn reaction_counts(
+        &self,
+        channel_id: ChannelId,
+        message_id: MessageId,
+        emojis: &[ReactionType],
+    ) -> Result<Vec<u64>>;
+    async fn user_name(&self, user_id: UserId) -> Result<String>;
+    // The user's nickname in the guild, None if they don't have one
+    async fn nick(&self, guild_id: GuildId, user_id: UserId) -> Option<String>;
+    // The guild's custom emoji along with their names
+    async fn guild_emojis(&self, guild_id: GuildId) -> Result<Vec<(String, ReactionType)>>;
+    // Like reaction_counts, but only counting members of the guild with the role
+    async fn role_reaction_counts(
+        &self,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        message_id: MessageId,
+        role_id: RoleId,
+        emojis: &[ReactionType],
+    ) -> Result<Vec<u64>>;
+    // Ask the user a yes or no question, false if they say no or don't answer in time.
+    // Then the question is replaced with the yes or no message.
+    async fn confirm(
+        &self,
+        channel_id: ChannelId,
+        user_id: UserId,
+        question: &str,
+        yes: &str,
+        no: &str,
+    ) -> Result<bool>;
+    // Let the user pick one of the options, by name and value, None if they don't pick in time
+    async fn choose(
+        &self,
+        channel_id: ChannelId,
+        user_id: UserId,
+        title: &str,
+        options: Vec<(String, String)>,
+        thumbnail: Option<String>,
+    ) -> Result<Option<usize>>;
+}
+
+pub struct SerenityDiscord<'a> {
+    ctx: &'a Context,
+}
+
+impl<'a> SerenityDiscord<'a> {
+    pub fn new(ctx: &'a Context) -> Self {
+        SerenityDiscord { ctx }
+    }
+}
+
+#[async_trait]
+impl<'a> Discord for SerenityDiscord<'a> {
+    async fn send_message(&self, channel_id: ChannelId, content: &str, ping: Option<RoleId>) -> Result<MessageId> {
+        let msg = channel_id
+            .send_message(&self.ctx.http, |m| {
+                m.content(content);
+                if let Some(role_id) = ping {
+                    m.allowed_mentions(|am| am.roles(vec![role_id]));
+                }
+                m
+            })
+            .await?;
+
+        Ok(msg.id)
+    }
+
+    async fn send_embed(&self, channel_id: ChannelId, embed: Embed) -> Result<MessageId> {
+        let msg = channel_id
+            .send_message(&self.ctx.http, |m| {
+                m.embed(|e| {
+                    e.title(&embed.title);
+                    if let Some(description) = &embed.description {
+                        e.description(description);
+                    }
+                    if let Some(thumbnail) = &embed.thumbnail {
+                        e.thumbnail(thumbnail);
+                    }
+                    for (name, value) in &embed.fields {
+                        e.field(name, value, false);
+                    }
+                    e
+                });
+                m
+            })
+            .await?;
+
+        Ok(msg.id)
+    }
+
+    async fn react(&self, channel_id: ChannelId, message_id: MessageId, emoji: ReactionType) -> Result<()> {
+        self.ctx.http.create_reaction(channel_id.0, message_id.0, &emoji).await?;
+        Ok(())
+    }
+
+    async fn reaction_counts(
+        &self,
+        channel_id: ChannelId,
+        message_id: MessageId,
+        emojis: &[ReactionType],
+    ) -> Result<Vec<u64>> {
+        let msg = channel_id.message(&self.ctx.http, message_id).await?;
+
+        Ok(emojis
+            .iter()
+            .map(|emoji| {
+                msg.reactions
+                    .iter()
+                    .find(|reaction| &reaction.reaction_type == emoji)
+                    .map(|reaction| reaction.count)
+                    .unwrap_or(0)
+            })
+            .collect())
+    }
+
+    async fn user_name(&self, user_id: UserId) -> Result<String> {
+        Ok(user_id.to_user(&self.ctx.http).await?.name)
+    }
+
+    async fn nick(&self, guild_id: GuildId, user_id: UserId) -> Option<String> {
+        match user_id.to_user(&self.ctx.http).await {
+            Ok(user) => user.nick_in(&self.ctx.http, guild_id).await,
+            Err(_) => None,
+        }
+    }
+
+    async fn guild_emojis(&self, guild_id: GuildId) -> Result<Vec<(String, ReactionType)>> {
+        Ok(guild_id
+            .emojis(&self.ctx.http)
+            .await?
+            .into_iter()
+            .map(|emoji| (emoji.name.clone(), ReactionType::from(emoji)))
+            .collect())
+    }
+
+    async fn role_reaction_counts(
+        &self,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        message_id: MessageId,
+        role_id: RoleId,
+        emojis: &[ReactionType],
+    ) -> Result<Vec<u64>> {
+        let mut counts = Vec::new();
+
+        for emoji in emojis {
+            let users = channel_id
+                .reaction_users(&self.ctx.http, message_id, emoji.clone(), Some(100), None::<UserId>)
+                .await?;
+
+            let mut count = 0;
+            for user in users.iter().filter(|user| !user.bot) {
+                let member = guild_id.member(&self.ctx.http, user.id).await?;
+                if member.roles.contains(&role_id) {
+                    count += 1;
+                }
+            }
+            counts.push(count);
+        }
+
+        Ok(counts)
+    }
+
+    async fn confirm(
+        &self,
+        channel_id: ChannelId,
+        user_id: UserId,
+        question: &str,
+        yes: &str,
+        no: &str,
+    ) -> Result<bool> {
+        let confirmation = utils::ask_confirmation(
+            self.ctx,
+            user_id,
+            channel_id,
+            String::from(question),
+            String::from(yes),
+            String::from(no),
+        )
+        .await?;
+
+        Ok(matches!(confirmation, Confirmation::Yes))
+    }
+
+    async fn choose(
+        &self,
+        channel_id: ChannelId,
+        user_id: UserId,
+        title: &str,
+        options: Vec<(String, String)>,
+        thumbnail: Option<String>,
+    ) -> Result<Option<usize>> {
+        utils::ask_choice(self.ctx, user_id, channel_id, String::from(title), options, thumbnail).await
+    }
+}
+
@@ -0,0 +1,703 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
+
+use chrono::{NaiveDateTime, Utc};
+use serenity::async_trait;
+use serenity::model::channel::ReactionType;
+use serenity::model::id::{ChannelId, GuildId, MessageId, RoleId, UserId};
+
+use kekw_db::models::period::Period;
+use kekw_db::models::roll::Roll;
+use kekw_db::models::selection::Selection;
+use kekw_db::models::submission::Submission;
+
+use crate::availability::Availability;
+use crate::backend::{Backend, Catalog, Discord, Embed, Store};
+use crate::links::MovieLink;
+use crate::metadata::{MovieDetails, SearchMatch};
+use crate::timed::tie_break::TieBreak;
+use crate::voting::VotingMode;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Sync + Send>>;
+
+// Something the bot posted
+#[derive(Clone, Debug, PartialEq)]
+pub enum Sent {
+    Message {
+        content: String,
+        ping: Option<RoleId>,
+    },
+    Embed(Embed),
+}
+
+#[derive(Clone, Debug)]
+pub struct Posted {
+    pub channel_id: ChannelId,
+    pub message_id: MessageId,
+    pub sent: Sent,
+}
+
+// Discord without the network. Everything sent is kept so tests can look at it,
+// and reactions are added by the test standing in for the members.
+#[derive(Default)]
+pub struct FakeDiscord {
+    // Name and nickname of everyone the bot can look up
+    users: Mutex<HashMap<UserId, (String, Option<String>)>>,
+    posted: Mutex<Vec<Posted>>,
+    reactions: Mutex<HashMap<MessageId, Vec<(ReactionType, u64)>>>,
+    // What a member with the counted role reacted with
+    role_reaction: Mutex<Option<ReactionType>>,
+    // Which messages and emoji the bot reacted with, reacting twice counts once like on Discord
+    bot_reactions: Mutex<HashSet<(MessageId, String)>>,
+    // How many more messages and reactions go through before Discord goes down, None if it stays up
+    failing: Mutex<Option<usize>>,
+    // How the member answers the next questions, as if they didn't answer once these run out
+    confirmations: Mutex<VecDeque<bool>>,
+    choices: Mutex<VecDeque<usize>>,
+}
+
+impl FakeDiscord {
+    pub fn add_user(&self, user_id: UserId, name: &str, nick: Option<&str>) {
+        self.users
+            .lock()
+            .unwrap()
+            .insert(user_id, (String::from(name), nick.map(String::from)));
+    }
+
+    // Someone reacting to a message, counted along with the bot's own reaction
+    pub fn add_reaction(&self, message_id: MessageId, emoji: &ReactionType) {
+        let mut reactions = self.reactions.lock().unwrap();
+        let message = reactions.entry(message_id).or_default();
+        match message.iter_mut().find(|(reacted, _)| reacted == emoji) {
+            Some((_, count)) => *count += 1,
+            None => message.push((emoji.clone(), 1)),
+        }
+    }
+
+    pub fn set_role_reaction(&self, emoji: Option<ReactionType>) {
+        *self.role_reaction.lock().unwrap() = emoji;
+    }
+
+    pub fn set_failing(&self, failing: bool) {
+        *self.failing.lock().unwrap() = if failing { Some(0) } else { None };
+    }
+
+    pub fn will_confirm(&self, yes: bool) {
+        self.confirmations.lock().unwrap().push_back(yes);
+    }
+
+    pub fn will_choose(&self, choice: usize) {
+        self.choices.lock().unwrap().push_back(choice);
+    }
+
+    pub fn fail_after(&self, sent: usize) {
+        *self.failing.lock().unwrap() = Some(sent);
+    }
+
+    fn reach(&self) -> Result<()> {
+        match self.failing.lock().unwrap().as_mut() {
+            Some(0) => Err("Discord is unreachable".into()),
+            Some(left) => {
+                *left -= 1;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub fn posted(&self) -> Vec<Posted> {
+        self.posted.lock().unwrap().clone()
+    }
+
+    pub fn messages(&self) -> Vec<String> {
+        self.posted()
+            .into_iter()
+            .filter_map(|posted| match posted.sent {
+                Sent::Message { content, .. } => Some(content),
+                Sent::Embed(_) => None,
+            })
+            .collect()
+    }
+
+    pub fn embeds(&self) -> Vec<Embed> {
+        self.posted()
+            .into_iter()
+            .filter_map(|posted| match posted.sent {
+                Sent::Embed(embed) => Some(embed),
+                Sent::Message { .. } => None,
+            })
+            .collect()
+    }
+
+    fn post(&self, channel_id: ChannelId, sent: Sent) -> Result<MessageId> {
+        self.reach()?;
+
+        let mut posted = self.posted.lock().unwrap();
+        let message_id = MessageId(posted.len() as u64 + 1);
+        posted.push(Posted {
+            channel_id,
+            message_id,
+            sent,
+        });
+
+        Ok(message_id)
+    }
+}
+
+#[async_trait]
+impl Discord for FakeDiscord {
+    async fn send_message(&self, channel_id: ChannelId, content: &str, ping: Option<RoleId>) -> Result<MessageId> {
+        self.post(
+            channel_id,
+            Sent::Message {
+                content: String::from(content),
+                ping,
+            },
+        )
+    }
+
+    async fn send_embed(&self, channel_id: ChannelId, embed: Embed) -> Result<MessageId> {
+        self.post(channel_id, Sent::Embed(embed))
+    }
+
+    async fn react(&self, _: ChannelId, message_id: MessageId, emoji: ReactionType) -> Result<()> {
+        self.reach()?;
+        if self.bot_reactions.lock().unwrap().insert((message_id, emoji.to_string())) {
+            self.add_reaction(message_id, &emoji);
+        }
+        Ok(())
+    }
+
+    async fn reaction_counts(&self, _: ChannelId, message_id: MessageId, emojis: &[ReactionType]) -> Result<Vec<u64>> {
+        let reactions = self.reactions.lock().unwrap();
+        let message = reactions.get(&message_id).ok_or("Unknown message")?;
+
+        Ok(emojis
+            .iter()
+            .map(|emoji| {
+                message
+                    .iter()
+                    .find(|(reacted, _)| reacted == emoji)
+                    .map(|(_, count)| *count)
+                    .unwrap_or(0)
+            })
+            .collect())
+    }
+
+    async fn user_name(&self, user_id: UserId) -> Result<String> {
+        match self.users.lock().unwrap().get(&user_id) {
+            Some((name, _)) => Ok(name.clone()),
+            None => Err(format!("Unknown user {}", user_id).into()),
+        }
+    }
+
+    async fn nick(&self, _: GuildId, user_id: UserId) -> Option<String> {
+        self.users
+            .lock()
+            .unwrap()
+            .get(&user_id)
+            .and_then(|(_, nick)| nick.clone())
+    }
+
+    // No custom emoji, so votes use the fallback letters
+    async fn guild_emojis(&self, _: GuildId) -> Result<Vec<(String, ReactionType)>> {
+        Ok(Vec::new())
+    }
+
+    async fn role_reaction_counts(
+        &self,
+        _: GuildId,
+        _: ChannelId,
+        _: MessageId,
+        _: RoleId,
+        emojis: &[ReactionType],
+    ) -> Result<Vec<u64>> {
+        let picked = self.role_reaction.lock().unwrap().clone();
+
+        Ok(emojis
+            .iter()
+            .map(|emoji| if picked.as_ref() == Some(emoji) { 1 } else { 0 })
+            .collect())
+    }
+
+    async fn confirm(&self, channel_id: ChannelId, _: UserId, question: &str, yes: &str, no: &str) -> Result<bool> {
+        let message_id = self.post(
+            channel_id,
+            Sent::Message {
+                content: String::from(question),
+                ping: None,
+            },
+        )?;
+        let confirmed = self.confirmations.lock().unwrap().pop_front().unwrap_or(false);
+
+        // The question is edited to the answer on Discord
+        let answer = if confirmed { yes } else { no };
+        if let Some(posted) = self.posted.lock().unwrap().iter_mut().find(|p| p.message_id == message_id) {
+            posted.sent = Sent::Message {
+                content: String::from(answer),
+                ping: None,
+            };
+        }
+
+        Ok(confirmed)
+    }
+
+    async fn choose(
+        &self,
+        channel_id: ChannelId,
+        _: UserId,
+        title: &str,
+        options: Vec<(String, String)>,
+        thumbnail: Option<String>,
+    ) -> Result<Option<usize>> {
+        self.post(
+            channel_id,
+            Sent::Embed(Embed {
+                title: String::from(title),
+                thumbnail,
+                fields: options,
+                ..Embed::default()
+            }),
+        )?;
+
+        Ok(self.choices.lock().unwrap().pop_front())
+    }
+}
+
+#[derive(Default)]
+struct Tables {
+    next_id: i32,
+    periods: Vec<Period>,
+    submissions: Vec<Submission>,
+    rolls: Vec<Roll>,
+    selections: Vec<Selection>,
+    // Roll id and ranking
+    ballots: Vec<(i32, String)>,
+    // Roll id, submission id and votes
+    results: Vec<(i32, i32, i32)>,
+    claimed: HashSet<String>,
+    opt_outs: HashSet<(GuildId, UserId)>,
+}
+
+impl Tables {
+    fn id(&mut self) -> i32 {
+        self.next_id += 1;
+        self.next_id
+    }
+}
+
+// The movie night tables in memory
+#[derive(Default)]
+pub struct FakeStore {
+    tables: Mutex<Tables>,
+}
+
+impl FakeStore {
+    pub fn add_ballot(&self, roll: &Roll, ranking: &[i32]) {
+        let ranking: Vec<String> = ranking.iter().map(|id| id.to_string()).collect();
+        self.tables.lock().unwrap().ballots.push((roll.id, ranking.join(",")));
+    }
+
+    // Each submission's votes recorded for the roll
+    pub fn results(&self, roll: &Roll) -> Vec<(i32, i32)> {
+        self.tables
+            .lock()
+            .unwrap()
+            .results
+            .iter()
+            .filter(|(roll_id, _, _)| *roll_id == roll.id)
+            .map(|(_, submission_id, votes)| (*submission_id, *votes))
+            .collect()
+    }
+
+    fn replace_period(&self, period: Period) {
+        let mut tables = self.tables.lock().unwrap();
+        if let Some(stored) = tables.periods.iter_mut().find(|stored| stored.id == period.id) {
+            *stored = period;
+        }
+    }
+
+    fn replace_roll(&self, roll: Roll) {
+        let mut tables = self.tables.lock().unwrap();
+        if let Some(stored) = tables.rolls.iter_mut().find(|stored| stored.id == roll.id) {
+            *stored = roll;
+        }
+    }
+}
+
+impl Store for FakeStore {
+    fn open_period(&self, guild_id: GuildId) -> Result<Option<Period>> {
+        Ok(self
+            .tables
+            .lock()
+            .unwrap()
+            .periods
+            .iter()
+            .rev()
+            .find(|period| period.guild_id == guild_id.to_string() && period.end_day.is_none())
+            .cloned())
+    }
+
+    fn closed_period(&self, guild_id: GuildId) -> Result<Option<Period>> {
+        Ok(self
+            .tables
+            .lock()
+            .unwrap()
+            .periods
+            .iter()
+            .rev()
+            .find(|period| period.guild_id == guild_id.to_string() && period.end_day.is_some())
+            .cloned())
+    }
+
+    fn period(&self, guild_id: GuildId, id: i32) -> Result<Option<Period>> {
+        Ok(self
+            .tables
+            .lock()
+            .unwrap()
+            .periods
+            .iter()
+            .find(|period| period.guild_id == guild_id.to_string() && period.id == id)
+            .cloned())
+    }
+
+    fn periods(&self, guild_id: GuildId) -> Result<Vec<Period>> {
+        Ok(self
+            .tables
+            .lock()
+            .unwrap()
+            .periods
+            .iter()
+            .filter(|period| period.guild_id == guild_id.to_string())
+            .cloned()
+            .collect())
+    }
+
+    fn create_period(&self, guild_id: GuildId, roll_seed: u64) -> Result<()> {
+        let mut tables = self.tables.lock().unwrap();
+        let id = tables.id();
+        tables.periods.push(Period {
+            id,
+            start_day: Utc::now().naive_utc().date(),
+            end_day: None,
+            vote_message: None,
+            guild_id: guild_id.to_string(),
+            roll_seed: Some(roll_seed.to_string()),
+        });
+
+        Ok(())
+    }
+
+    fn end_period(&self, mut period: Period) -> Result<Period> {
+        period.end_day = Some(Utc::now().naive_utc().date());
+        self.replace_period(period.clone());
+
+        Ok(period)
+    }
+
+    fn set_vote_message(&self, mut period: Period, message_id: MessageId) -> Result<()> {
+        period.vote_message = Some(message_id.to_string());
+        self.replace_period(period);
+
+        Ok(())
+    }
+
+    fn submissions(&self, period: &Period) -> Result<Vec<Submission>> {
+        Ok(self
+            .tables
+            .lock()
+            .unwrap()
+            .submissions
+            .iter()
+            .filter(|submission| submission.period_id == period.id)
+            .cloned()
+            .collect())
+    }
+
+    fn user_submission(&self, period: &Period, user_id: UserId) -> Result<Option<Submission>> {
+        Ok(self
+            .submissions(period)?
+            .into_iter()
+            .find(|submission| submission.dis_user_id == user_id.to_string()))
+    }
+
+    fn create_submission(&self, period: &Period, user_id: UserId, title: &str, imdb_id: &str) -> Result<()> {
+        let mut tables = self.tables.lock().unwrap();
+        let id = tables.id();
+        tables.submissions.push(Submission {
+            id,
+            dis_user_id: user_id.to_string(),
+            title: String::from(title),
+            link: String::from(imdb_id),
+            period_id: period.id,
+            carried_over: 0,
+        });
+
+        Ok(())
+    }
+
+    fn update_submission(&self, submission: Submission) -> Result<()> {
+        let mut tables = self.tables.lock().unwrap();
+        let stored = tables
+            .submissions
+            .iter_mut()
+            .find(|stored| stored.id == submission.id)
+            .ok_or("Unknown submission")?;
+        *stored = submission;
+
+        Ok(())
+    }
+
+    fn submission(&self, id: i32) -> Result<Submission> {
+        Ok(self
+            .tables
+            .lock()
+            .unwrap()
+            .submissions
+            .iter()
+            .find(|submission| submission.id == id)
+            .cloned()
+            .ok_or("Unknown submission")?)
+    }
+
+    fn carry_submission(&self, period: &Period, submission: &Submission) -> Result<()> {
+        let mut tables = self.tables.lock().unwrap();
+        let id = tables.id();
+        tables.submissions.push(Submission {
+            id,
+            period_id: period.id,
+            carried_over: submission.carried_over + 1,
+            ..submission.clone()
+        });
+
+        Ok(())
+    }
+
+    fn carry_over_opt_outs(&self, guild_id: GuildId) -> Result<Vec<UserId>> {
+        Ok(self
+            .tables
+            .lock()
+            .unwrap()
+            .opt_outs
+            .iter()
+            .filter(|(opted_out_guild, _)| *opted_out_guild == guild_id)
+            .map(|(_, user_id)| *user_id)
+            .collect())
+    }
+
+    fn set_carry_over_opt_out(&self, guild_id: GuildId, user_id: UserId, opted_out: bool) -> Result<()> {
+        let mut tables = self.tables.lock().unwrap();
+        if opted_out {
+            tables.opt_outs.insert((guild_id, user_id));
+        } else {
+            tables.opt_outs.remove(&(guild_id, user_id));
+        }
+
+        Ok(())
+    }
+
+    fn roll(&self, period: &Period) -> Result<Option<Roll>> {
+        Ok(self
+            .tables
+            .lock()
+            .unwrap()
+            .rolls
+            .iter()
+            .find(|roll| roll.period_id == period.id)
+            .cloned())
+    }
+
+    fn create_roll(
+        &self,
+        period: &Period,
+        submission_ids: &[i32],
+        mode: VotingMode,
+        seed: u64,
+        weighting: Option<u32>,
+    ) -> Result<Roll> {
+        let mut tables = self.tables.lock().unwrap();
+        let roll = Roll {
+            id: tables.id(),
+            period_id: period.id,
+            voting_mode: mode.to_string(),
+            winner: None,
+            tie_break: None,
+            tie_break_seed: None,
+            decided_at: None,
+            seed: Some(seed.to_string()),
+            weighting: weighting.map(|periods| periods as i32),
+        };
+        tables.rolls.push(roll.clone());
+
+        for (position, submission_id) in submission_ids.iter().enumerate() {
+            let id = tables.id();
+            tables.selections.push(Selection {
+                id,
+                roll_id: roll.id,
+                submission_id: *submission_id,
+                position: position as i32,
+                emote: None,
+            });
+        }
+
+        Ok(roll)
+    }
+
+    fn delete_roll(&self, id: i32) -> Result<()> {
+        let mut tables = self.tables.lock().unwrap();
+        tables.rolls.retain(|roll| roll.id != id);
+        tables.selections.retain(|selection| selection.roll_id != id);
+
+        Ok(())
+    }
+
+    fn selections(&self, roll: &Roll) -> Result<Vec<Selection>> {
+        let mut selections: Vec<Selection> = self
+            .tables
+            .lock()
+            .unwrap()
+            .selections
+            .iter()
+            .filter(|selection| selection.roll_id == roll.id)
+            .cloned()
+            .collect();
+        selections.sort_by_key(|selection| selection.position);
+
+        Ok(selections)
+    }
+
+    fn set_emote(&self, selection: Selection, emoji: &ReactionType) -> Result<()> {
+        let mut tables = self.tables.lock().unwrap();
+        if let Some(stored) = tables.selections.iter_mut().find(|stored| stored.id == selection.id) {
+            stored.emote = Some(emoji.to_string());
+        }
+
+        Ok(())
+    }
+
+    fn rankings(&self, roll: &Roll) -> Result<Vec<String>> {
+        Ok(self
+            .tables
+            .lock()
+            .unwrap()
+            .ballots
+            .iter()
+            .filter(|(roll_id, _)| *roll_id == roll.id)
+            .map(|(_, ranking)| ranking.clone())
+            .collect())
+    }
+
+    fn set_result(&self, roll: &Roll, submission_id: i32, votes: i32) -> Result<()> {
+        let mut tables = self.tables.lock().unwrap();
+        tables
+            .results
+            .retain(|(roll_id, stored_id, _)| (*roll_id, *stored_id) != (roll.id, submission_id));
+        tables.results.push((roll.id, submission_id, votes));
+
+        Ok(())
+    }
+
+    fn set_winner(&self, mut roll: Roll, submission_id: i32, decided_at: NaiveDateTime) -> Result<()> {
+        roll.winner = Some(submission_id);
+        roll.decided_at = Some(decided_at);
+        self.replace_roll(roll);
+
+        Ok(())
+    }
+
+    fn set_tie_break(&self, mut roll: Roll, method: TieBreak, seed: Option<u64>) -> Result<()> {
+        roll.tie_break = Some(method.to_string());
+        roll.tie_break_seed = seed.map(|seed| seed.to_string());
+        self.replace_roll(roll);
+
+        Ok(())
+    }
+
+    fn claim(&self, key: &str) -> Result<bool> {
+        Ok(self.tables.lock().unwrap().claimed.insert(String::from(key)))
+    }
+
+    fn release(&self, key: &str) -> Result<()> {
+        self.tables.lock().unwrap().claimed.remove(key);
+        Ok(())
+    }
+}
+
+// Only knows the movies the test adds, and never has details for them as if every lookup failed
+#[derive(Default)]
+pub struct FakeCatalog {
+    movies: Mutex<Vec<SearchMatch>>,
+}
+
+impl FakeCatalog {
+    pub fn add_movie(&self, imdb_id: &str, title: &str, year: i32) {
+        self.movies.lock().unwrap().push(SearchMatch {
+            imdb_id: String::from(imdb_id),
+            title: String::from(title),
+            year: Some(year),
+            poster: None,
+        });
+    }
+}
+
+#[async_trait]
+impl Catalog for FakeCatalog {
+    async fn details(&self, _: &str) -> Option<MovieDetails> {
+        None
+    }
+
+    async fn availability(&self, _: &str) -> Option<Availability> {
+        None
+    }
+
+    // Only IMDb links are known
+    async fn resolve(&self, link: MovieLink) -> Result<Option<(String, String)>> {
+        let imdb_id = match link {
+            MovieLink::Imdb(imdb_id) => imdb_id,
+            _ => return Ok(None),
+        };
+
+        Ok(self
+            .movies
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|movie| movie.imdb_id == imdb_id)
+            .map(|movie| (movie.title.clone(), movie.imdb_id.clone())))
+    }
+
+    async fn search(&self, title: &str) -> Result<Vec<SearchMatch>> {
+        let title = title.to_lowercase();
+
+        Ok(self
+            .movies
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|movie| movie.title.to_lowercase().contains(&title))
+            .cloned()
+            .collect())
+    }
+}
+
+// The fakes a test runs against
+#[derive(Default)]
+pub struct Fakes {
+    pub discord: FakeDiscord,
+    pub store: FakeStore,
+    pub catalog: FakeCatalog,
+}
+
+impl Fakes {
+    pub fn new() -> Self {
+        Fakes::default()
+    }
+
+    pub fn backend(&self) -> Backend<'_> {
+        Backend {
+            discord: &self.discord,
+            store: &self.store,
+            catalog: &self.catalog,
+        }
+    }
+}
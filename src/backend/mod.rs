@@ -0,0 +1,58 @@
+pub mod catalog;
+pub mod discord;
+pub mod store;
+
+#[cfg(test)]
+pub mod fake;
+
+use std::sync::Arc;
+
+use serenity::client::Context;
+
+use kekw_db::KekPool;
+
+use crate::config::Config;
+use crate::metadata::FallbackProvider;
+
+pub use catalog::{Catalog, LiveCatalog};
+pub use discord::{Discord, Embed, SerenityDiscord};
+pub use store::{KekStore, Store};
+
+// Everything movie night talks to. Commands and jobs use the live services,
+// tests swap in the fakes so the same code runs without a network or database.
+#[derive(Clone, Copy)]
+pub struct Backend<'a> {
+    pub discord: &'a dyn Discord,
+    pub store: &'a dyn Store,
+    pub catalog: &'a dyn Catalog,
+}
+
+// Discord, the database and the movie lookups for a command or job
+pub struct Live<'a> {
+    discord: SerenityDiscord<'a>,
+    store: KekStore,
+    catalog: LiveCatalog,
+}
+
+impl<'a> Live<'a> {
+    pub fn new(
+        ctx: &'a Context,
+        db_pool: &KekPool,
+        config: &Arc<Config>,
+        metadata: &Arc<FallbackProvider>,
+    ) -> Self {
+        Live {
+            discord: SerenityDiscord::new(ctx),
+            store: KekStore::new(db_pool.clone()),
+            catalog: LiveCatalog::new(db_pool.clone(), config.clone(), metadata.clone()),
+        }
+    }
+
+    pub fn backend(&self) -> Backend<'_> {
+        Backend {
+            discord: &self.discord,
+            store: &self.store,
+            catalog: &self.catalog,
+        }
+    }
+}
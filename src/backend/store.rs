@@ -0,0 +1,233 @@
+use chrono::NaiveDateTime;
+use diesel::result::Error::NotFound;
+use serenity::model::channel::ReactionType;
+use serenity::model::id::{GuildId, MessageId, UserId};
+
+use kekw_db::models::period::Period;
+use kekw_db::models::roll::Roll;
+use kekw_db::models::selection::Selection;
+use kekw_db::models::submission::Submission;
+use kekw_db::{
+    ballots, carry_over_opt_outs, idempotency_keys, periods, results, rolls, selections, submissions, KekPool,
+};
+
+use crate::timed::tie_break::TieBreak;
+use crate::voting::VotingMode;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Sync + Send>>;
+
+// The movie night tables, lookups that find nothing return None
+pub trait Store: Send + Sync {
+    // The guild's open submission period
+    fn open_period(&self, guild_id: GuildId) -> Result<Option<Period>>;
+    // The guild's most recently closed period
+    fn closed_period(&self, guild_id: GuildId) -> Result<Option<Period>>;
+    // Any of the guild's periods by id
+    fn period(&self, guild_id: GuildId, id: i32) -> Result<Option<Period>>;
+    fn periods(&self, guild_id: GuildId) -> Result<Vec<Period>>;
+    // The seed is kept with the period until it's rolled
+    fn create_period(&self, guild_id: GuildId, roll_seed: u64) -> Result<()>;
+    fn end_period(&self, period: Period) -> Result<Period>;
+    fn set_vote_message(&self, period: Period, message_id: MessageId) -> Result<()>;
+
+    fn submissions(&self, period: &Period) -> Result<Vec<Submission>>;
+    fn user_submission(&self, period: &Period, user_id: UserId) -> Result<Option<Submission>>;
+    fn create_submission(&self, period: &Period, user_id: UserId, title: &str, imdb_id: &str) -> Result<()>;
+    fn update_submission(&self, submission: Submission) -> Result<()>;
+    fn submission(&self, id: i32) -> Result<Submission>;
+    // A copy of the submission in the period, counted as carried over once more
+    fn carry_submission(&self, period: &Period, submission: &Submission) -> Result<()>;
+    // Members who don't want their submissions carried over
+    fn carry_over_opt_outs(&self, guild_id: GuildId) -> Result<Vec<UserId>>;
+    fn set_carry_over_opt_out(&self, guild_id: GuildId, user_id: UserId, opted_out: bool) -> Result<()>;
+
+    fn roll(&self, period: &Period) -> Result<Option<Roll>>;
+    // Also creates a selection for each submission, in order.
+    // The weighting is kept so the roll can be checked after the guild changes it.
+    fn create_roll(
+        &self,
+        period: &Period,
+        submission_ids: &[i32],
+        mode: VotingMode,
+        seed: u64,
+        weighting: Option<u32>,
+    ) -> Result<Roll>;
+    fn delete_roll(&self, id: i32) -> Result<()>;
+    fn selections(&self, roll: &Roll) -> Result<Vec<Selection>>;
+    fn set_emote(&self, selection: Selection, emoji: &ReactionType) -> Result<()>;
+    // Each ranked ballot's comma separated submission ids
+    fn rankings(&self, roll: &Roll) -> Result<Vec<String>>;
+    // Replaces the votes already stored for the submission, if any
+    fn set_result(&self, roll: &Roll, submission_id: i32, votes: i32) -> Result<()>;
+    fn set_winner(&self, roll: Roll, submission_id: i32, decided_at: NaiveDateTime) -> Result<()>;
+    fn set_tie_break(&self, roll: Roll, method: TieBreak, seed: Option<u64>) -> Result<()>;
+
+    // True the first time a key is claimed, false ever after
+    fn claim(&self, key: &str) -> Result<bool>;
+    // Give a claimed key back so the next claim succeeds again
+    fn release(&self, key: &str) -> Result<()>;
+}
+
+fn found<T>(result: diesel::QueryResult<T>) -> Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(NotFound) => Ok(None),
+        Err(why) => Err(why.into()),
+    }
+}
+
+// The Postgres database through kekw_db
+pub struct KekStore {
+    db_pool: KekPool,
+}
+
+impl KekStore {
+    pub fn new(db_pool: KekPool) -> Self {
+        KekStore { db_pool }
+    }
+}
+
+impl Store for KekStore {
+    fn open_period(&self, guild_id: GuildId) -> Result<Option<Period>> {
+        found(periods::get_most_recent_period(&self.db_pool, &guild_id.to_string()))
+    }
+
+    fn closed_period(&self, guild_id: GuildId) -> Result<Option<Period>> {
+        found(periods::get_most_recent_closed_period(&self.db_pool, &guild_id.to_string()))
+    }
+
+    fn period(&self, guild_id: GuildId, id: i32) -> Result<Option<Period>> {
+        Ok(self.periods(guild_id)?.into_iter().find(|period| period.id == id))
+    }
+
+    fn periods(&self, guild_id: GuildId) -> Result<Vec<Period>> {
+        Ok(periods::get_periods(&self.db_pool, &guild_id.to_string())?)
+    }
+
+    fn create_period(&self, guild_id: GuildId, roll_seed: u64) -> Result<()> {
+        // Convert u64 to string since postgresql doesnt have u64
+        periods::create_period(&self.db_pool, &guild_id.to_string(), &roll_seed.to_string())?;
+        Ok(())
+    }
+
+    fn end_period(&self, period: Period) -> Result<Period> {
+        Ok(periods::end_period(&self.db_pool, period)?)
+    }
+
+    fn set_vote_message(&self, period: Period, message_id: MessageId) -> Result<()> {
+        // Convert u64 to string since postgresql doesnt have u64
+        periods::set_vote_message(&self.db_pool, period, message_id.as_u64().to_string())?;
+        Ok(())
+    }
+
+    fn submissions(&self, period: &Period) -> Result<Vec<Submission>> {
+        Ok(submissions::get_moviesubs(&self.db_pool.get()?, period))
+    }
+
+    fn user_submission(&self, period: &Period, user_id: UserId) -> Result<Option<Submission>> {
+        Ok(submissions::check_prev_sub(&self.db_pool.get()?, period.id, &user_id.to_string())
+            .into_iter()
+            .next())
+    }
+
+    fn create_submission(&self, period: &Period, user_id: UserId, title: &str, imdb_id: &str) -> Result<()> {
+        submissions::create_moviesub(&self.db_pool.get()?, &user_id.to_string(), title, imdb_id, period.id);
+        Ok(())
+    }
+
+    fn update_submission(&self, submission: Submission) -> Result<()> {
+        submissions::update_moviesub(&self.db_pool, submission)?;
+        Ok(())
+    }
+
+    fn submission(&self, id: i32) -> Result<Submission> {
+        Ok(submissions::get_submission_by_id(&self.db_pool, id)?)
+    }
+
+    fn carry_submission(&self, period: &Period, submission: &Submission) -> Result<()> {
+        submissions::carry_moviesub(&self.db_pool, submission, period.id)?;
+        Ok(())
+    }
+
+    fn carry_over_opt_outs(&self, guild_id: GuildId) -> Result<Vec<UserId>> {
+        // Discord ids are stored as strings since postgresql doesnt have u64
+        Ok(carry_over_opt_outs::get_opt_outs(&self.db_pool, &guild_id.to_string())?
+            .into_iter()
+            .filter_map(|user_id| user_id.parse::<u64>().ok())
+            .map(UserId)
+            .collect())
+    }
+
+    fn set_carry_over_opt_out(&self, guild_id: GuildId, user_id: UserId, opted_out: bool) -> Result<()> {
+        carry_over_opt_outs::set_opt_out(&self.db_pool, &guild_id.to_string(), &user_id.to_string(), opted_out)?;
+        Ok(())
+    }
+
+    fn roll(&self, period: &Period) -> Result<Option<Roll>> {
+        found(rolls::get_roll_by_period(&self.db_pool, period))
+    }
+
+    fn create_roll(
+        &self,
+        period: &Period,
+        submission_ids: &[i32],
+        mode: VotingMode,
+        seed: u64,
+        weighting: Option<u32>,
+    ) -> Result<Roll> {
+        Ok(rolls::create_roll(
+            &self.db_pool,
+            period,
+            submission_ids,
+            mode.to_string(),
+            seed.to_string(),
+            weighting.map(|periods| periods as i32),
+        )?)
+    }
+
+    fn delete_roll(&self, id: i32) -> Result<()> {
+        rolls::delete_roll(&self.db_pool, id)?;
+        Ok(())
+    }
+
+    fn selections(&self, roll: &Roll) -> Result<Vec<Selection>> {
+        Ok(selections::get_selections_by_roll(&self.db_pool, roll)?)
+    }
+
+    fn set_emote(&self, selection: Selection, emoji: &ReactionType) -> Result<()> {
+        selections::set_emote(&self.db_pool, selection, emoji.to_string())?;
+        Ok(())
+    }
+
+    fn rankings(&self, roll: &Roll) -> Result<Vec<String>> {
+        Ok(ballots::get_ballots_by_roll(&self.db_pool, roll)?
+            .into_iter()
+            .map(|ballot| ballot.ranking)
+            .collect())
+    }
+
+    fn set_result(&self, roll: &Roll, submission_id: i32, votes: i32) -> Result<()> {
+        results::set_result(&self.db_pool, roll, submission_id, votes)?;
+        Ok(())
+    }
+
+    fn set_winner(&self, roll: Roll, submission_id: i32, decided_at: NaiveDateTime) -> Result<()> {
+        rolls::set_winner(&self.db_pool, roll, submission_id, decided_at)?;
+        Ok(())
+    }
+
+    fn set_tie_break(&self, roll: Roll, method: TieBreak, seed: Option<u64>) -> Result<()> {
+        // Convert u64 to string since postgresql doesnt have u64
+        rolls::set_tie_break(&self.db_pool, roll, method.to_string(), seed.map(|seed| seed.to_string()))?;
+        Ok(())
+    }
+
+    fn claim(&self, key: &str) -> Result<bool> {
+        Ok(idempotency_keys::claim(&self.db_pool, key)?)
+    }
+
+    fn release(&self, key: &str) -> Result<()> {
+        idempotency_keys::release(&self.db_pool, key)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,68 @@
+use serenity::framework::standard::{macros::command, Args, CommandResult};
+use serenity::model::prelude::*;
+use serenity::prelude::*;
+
+use kekw_db::KekPool;
+
+use crate::{audit, movie_info};
+use crate::error::Error;
+use crate::utils::require_guild;
+use crate::DBConnectionContainer;
+
+// Discord allows at most 25 fields in an embed
+const MAX_ENTRIES: usize = 25;
+
+// The recent entries as embed fields, newest first
+pub fn audit_fields(db_pool: &KekPool, guild_id: GuildId, count: usize) -> std::result::Result<Vec<(String, String)>, Error> {
+    let entries = audit::recent(db_pool, guild_id, count.min(MAX_ENTRIES)).map_err(Error::from_boxed)?;
+
+    let fields = entries
+        .iter()
+        .map(|entry| (audit::timestamp(entry), audit::describe(entry)))
+        .collect();
+
+    Ok(movie_info::fit_fields(fields))
+}
+
+// !m audit [n]
+#[command]
+pub async fn audit(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let db_pool = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<DBConnectionContainer>()
+            .expect("Expected DBConnection in TypeMap.")
+            .clone()
+    };
+
+    let guild_id = require_guild(msg.guild_id)?;
+    let count = match args.single::<usize>() {
+        Ok(count) if count > 0 => count,
+        Ok(_) => return Err(Error::UserInput(String::from("Usage: `!m audit [number of entries]`")).into()),
+        Err(_) if args.is_empty() => 10,
+        Err(_) => return Err(Error::UserInput(String::from("Usage: `!m audit [number of entries]`")).into()),
+    };
+
+    let fields = audit_fields(&db_pool, guild_id, count)?;
+    if fields.is_empty() {
+        msg.channel_id.say(&ctx.http, "Nothing has been changed yet.").await?;
+        return Ok(());
+    }
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title("Movie Night Audit Log");
+                for (name, value) in fields {
+                    e.field(name, value, false);
+                }
+                e
+            });
+            // Actors are named, not pinged
+            m.allowed_mentions(|am| am.empty_parse());
+            m
+        })
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,104 @@
+use serenity::framework::standard::{macros::command, Args, CommandResult};
+use serenity::model::prelude::*;
+use serenity::prelude::*;
+
+use chrono::Utc;
+use tracing::info;
+
+use kekw_db::movie_cache;
+
+use crate::links;
+use crate::omdb_cache;
+use crate::{ConfigContainer, DBConnectionContainer};
+
+#[command]
+#[owners_only]
+pub async fn cacheinfo(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let db_pool = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<DBConnectionContainer>()
+            .expect("Expected DBConnection in TypeMap.")
+            .clone()
+    };
+    let config = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<ConfigContainer>()
+            .expect("Expected Config in TypeMap.")
+            .clone()
+    };
+
+    match args.single::<String>() {
+        // !m cacheinfo clear [IMDb id or title]
+        Ok(action) if action == "clear" => {
+            if args.is_empty() {
+                let cleared = movie_cache::clear(&db_pool)?;
+                info!("{} cleared the movie cache", msg.author.name);
+                msg.reply(&ctx.http, format!("Cleared {} cached movie(s).", cleared))
+                    .await?;
+            } else {
+                let query = args.rest().trim();
+                let cleared = if links::is_imdb_id(query) {
+                    movie_cache::delete_entry(&db_pool, &omdb_cache::id_key(query))?
+                } else {
+                    // Searches for the title are cleared along with the title lookup
+                    movie_cache::delete_entry(&db_pool, &omdb_cache::title_key(query))?
+                        + movie_cache::delete_entry(&db_pool, &omdb_cache::search_key(query, None))?
+                };
+
+                msg.reply(&ctx.http, format!("Cleared {} cached entries for {}.", cleared, query))
+                    .await?;
+            }
+        }
+        Ok(action) => {
+            msg.reply(&ctx.http, format!("Unknown action {}, expected clear.", action))
+                .await?;
+        }
+        Err(_) => {
+            let entries = movie_cache::get_entries(&db_pool)?;
+            let now = Utc::now().naive_utc();
+
+            let misses = entries.iter().filter(|entry| entry.body.is_none()).count();
+            let searches = entries.iter().filter(|entry| omdb_cache::is_search(entry)).count();
+            let expired = entries
+                .iter()
+                .filter(|entry| omdb_cache::is_expired(&config, entry, now))
+                .count();
+            let oldest = entries
+                .iter()
+                .map(|entry| entry.fetched_at)
+                .min()
+                .map(|fetched_at| fetched_at.format("%Y-%m-%d %H:%M UTC").to_string())
+                .unwrap_or_else(|| String::from("-"));
+
+            msg.channel_id
+                .send_message(&ctx.http, |m| {
+                    m.embed(|e| {
+                        e.title("Movie Cache");
+                        e.field("Entries", entries.len(), true);
+                        e.field("Movies", entries.len() - misses - searches, true);
+                        e.field("Searches", searches, true);
+                        e.field("Not found", misses, true);
+                        e.field("Expired", expired, true);
+                        e.field("Oldest", oldest, true);
+                        e.field(
+                            "TTL",
+                            format!(
+                                "{}h, {}h for searches and not found",
+                                config.metadata.cache_ttl.num_hours(),
+                                config.metadata.cache_miss_ttl.num_hours()
+                            ),
+                            true,
+                        );
+                        e
+                    });
+
+                    m
+                })
+                .await?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,85 @@
+use serenity::framework::standard::{macros::command, Args, CommandResult};
+use serenity::model::prelude::*;
+use serenity::prelude::*;
+
+use tracing::info;
+
+use kekw_db::KekPool;
+
+use crate::audit::{self, Entry};
+use crate::backend::{KekStore, Store};
+use crate::error::Error;
+use crate::settings::GuildSettings;
+use crate::{settings, DBConnectionContainer};
+
+// Stop or resume carrying the member's unpicked submissions into the next period.
+// Returns the reply for the member.
+pub async fn set_member_carry_over(
+    ctx: &Context,
+    db_pool: &KekPool,
+    guild_settings: &GuildSettings,
+    user_id: UserId,
+    on: bool,
+) -> std::result::Result<String, Error> {
+    let store = KekStore::new(db_pool.clone());
+    let opted_out = store
+        .carry_over_opt_outs(guild_settings.guild_id)
+        .map_err(Error::from_boxed)?
+        .contains(&user_id);
+
+    let mut reply = String::from(match (on, opted_out) {
+        (true, false) => "Your unpicked submissions are already carried over.",
+        (false, true) => "Your unpicked submissions already aren't carried over.",
+        (true, true) => "Your unpicked submissions will be carried over to the next period again.",
+        (false, false) => "Your unpicked submissions won't be carried over anymore.",
+    });
+    if guild_settings.carry_over.is_none() {
+        reply.push_str(" Carry-over is off in this server right now though.");
+    }
+
+    if on == opted_out {
+        store
+            .set_carry_over_opt_out(guild_settings.guild_id, user_id, !on)
+            .map_err(Error::from_boxed)?;
+        info!("{} turned carry-over {}", user_id, if on { "on" } else { "off" });
+        audit::record(
+            ctx,
+            db_pool,
+            guild_settings.guild_id,
+            Entry::new(user_id, "carryover", "their submissions")
+                .before(if opted_out { "off" } else { "on" })
+                .after(if on { "on" } else { "off" }),
+        )
+        .await;
+    }
+
+    Ok(reply)
+}
+
+// !m carryover <on|off>
+#[command]
+pub async fn carryover(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let db_pool = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<DBConnectionContainer>()
+            .expect("Expected DBConnection in TypeMap.")
+            .clone()
+    };
+
+    let guild_id = msg
+        .guild_id
+        .ok_or_else(|| Error::UserInput(String::from("Carry-over can only be changed in a server.")))?;
+
+    let on = match args.single::<String>().map(|arg| arg.to_lowercase()) {
+        Ok(arg) if arg == "on" => true,
+        Ok(arg) if arg == "off" => false,
+        _ => return Err(Error::UserInput(String::from("Usage: `!m carryover <on|off>`")).into()),
+    };
+
+    let guild_settings = settings::load(&db_pool, guild_id)?;
+    let reply = set_member_carry_over(ctx, &db_pool, &guild_settings, msg.author.id, on).await?;
+    msg.reply(&ctx.http, reply).await?;
+
+    Ok(())
+}
@@ -0,0 +1,197 @@
+use serenity::framework::standard::{macros::command, Args, CommandResult};
+use serenity::model::prelude::*;
+use serenity::prelude::*;
+
+use tracing::info;
+
+use kekw_db::KekPool;
+
+use crate::audit::{self, Entry};
+use crate::error::Error;
+use crate::settings::{self, GuildSettings};
+use crate::utils::require_guild;
+use crate::DBConnectionContainer;
+
+// Describe where movie night happens in the guild
+pub fn describe(guild_settings: &GuildSettings) -> String {
+    let channel = match guild_settings.movie_channel {
+        Some(channel_id) => channel_id.mention().to_string(),
+        None => String::from("not set, movie night won't run on a schedule"),
+    };
+    let role = match guild_settings.notify_role {
+        Some(role_id) => role_id.mention().to_string(),
+        None => String::from("not set, nobody gets pinged"),
+    };
+    let admin = match guild_settings.admin_role {
+        Some(role_id) => role_id.mention().to_string(),
+        None => String::from("not set, only server managers can run admin commands"),
+    };
+    let log = match guild_settings.log_channel {
+        Some(channel_id) => channel_id.mention().to_string(),
+        None => String::from("not set, see `!m audit` for changes"),
+    };
+    let weighting = match guild_settings.weighting {
+        Some(periods) => format!("favours submitters not picked in the last {} periods", periods),
+        None => String::from("off, every submission is as likely to be rolled"),
+    };
+    let carry_over = match guild_settings.carry_over {
+        Some(count) => format!("unpicked submissions move to the next period, up to {} times", count),
+        None => String::from("off, every period starts empty"),
+    };
+
+    format!(
+        "Movie channel: {}\nMovie night role: {}\nMovie admin role: {}\nLog channel: {}\nRoll weighting: {}\nCarry-over: {}",
+        channel, role, admin, log, weighting, carry_over
+    )
+}
+
+pub fn set_channel(db_pool: &KekPool, guild_id: GuildId, channel_id: ChannelId) -> std::result::Result<String, Error> {
+    settings::set_movie_channel(db_pool, guild_id, channel_id).map_err(Error::from_boxed)?;
+    info!("Movie channel for guild {} set to {}", guild_id, channel_id);
+    Ok(format!("Movie night now happens in {}.", channel_id.mention()))
+}
+
+pub fn set_role(db_pool: &KekPool, guild_id: GuildId, role_id: RoleId) -> std::result::Result<String, Error> {
+    settings::set_notify_role(db_pool, guild_id, role_id).map_err(Error::from_boxed)?;
+    info!("Notify role for guild {} set to {}", guild_id, role_id);
+    Ok(format!("Movie night pings now go to {}.", role_id.mention()))
+}
+
+pub fn set_admin(db_pool: &KekPool, guild_id: GuildId, role_id: RoleId) -> std::result::Result<String, Error> {
+    settings::set_admin_role(db_pool, guild_id, role_id).map_err(Error::from_boxed)?;
+    info!("Admin role for guild {} set to {}", guild_id, role_id);
+    Ok(format!("Members with {} can now run the admin movie commands.", role_id.mention()))
+}
+
+pub fn set_log(db_pool: &KekPool, guild_id: GuildId, channel_id: ChannelId) -> std::result::Result<String, Error> {
+    settings::set_log_channel(db_pool, guild_id, channel_id).map_err(Error::from_boxed)?;
+    info!("Log channel for guild {} set to {}", guild_id, channel_id);
+    Ok(format!("Movie night changes are now logged in {}.", channel_id.mention()))
+}
+
+// A number of periods to look back, or off
+pub fn parse_weighting(arg: &str) -> std::result::Result<Option<u32>, Error> {
+    match arg.to_lowercase().as_str() {
+        "off" => Ok(None),
+        periods => match periods.parse::<u32>() {
+            Ok(periods) if periods > 0 => Ok(Some(periods)),
+            _ => Err(Error::UserInput(String::from(
+                "Roll weighting is a number of periods to look back, or `off`",
+            ))),
+        },
+    }
+}
+
+pub fn set_weighting(db_pool: &KekPool, guild_id: GuildId, periods: Option<u32>) -> std::result::Result<String, Error> {
+    settings::set_weighting(db_pool, guild_id, periods).map_err(Error::from_boxed)?;
+    info!("Roll weighting for guild {} set to {:?}", guild_id, periods);
+    Ok(match periods {
+        Some(periods) => format!(
+            "Rolls now favour submitters whose movies weren't picked in the last {} periods.",
+            periods
+        ),
+        None => String::from("Rolls now pick every submission evenly."),
+    })
+}
+
+// The most times a submission can be carried over, or off
+pub fn parse_carry_over(arg: &str) -> std::result::Result<Option<u32>, Error> {
+    match arg.to_lowercase().as_str() {
+        "off" => Ok(None),
+        count => match count.parse::<u32>() {
+            Ok(count) if count > 0 => Ok(Some(count)),
+            _ => Err(Error::UserInput(String::from(
+                "Carry-over is the most periods a submission can be carried into, or `off`",
+            ))),
+        },
+    }
+}
+
+pub fn set_carry_over(db_pool: &KekPool, guild_id: GuildId, count: Option<u32>) -> std::result::Result<String, Error> {
+    settings::set_carry_over(db_pool, guild_id, count).map_err(Error::from_boxed)?;
+    info!("Carry-over for guild {} set to {:?}", guild_id, count);
+    Ok(match count {
+        Some(count) => format!(
+            "Submissions that aren't picked now move to the next period, up to {} times.",
+            count
+        ),
+        None => String::from("Submissions are no longer carried over to the next period."),
+    })
+}
+
+// The current value of a setting, for the audit log
+pub fn value(guild_settings: &GuildSettings, setting: &str) -> String {
+    let value = match setting {
+        "channel" => guild_settings.movie_channel.map(|id| id.mention().to_string()),
+        "role" => guild_settings.notify_role.map(|id| id.mention().to_string()),
+        "admin" => guild_settings.admin_role.map(|id| id.mention().to_string()),
+        "log" => guild_settings.log_channel.map(|id| id.mention().to_string()),
+        "weighting" => guild_settings.weighting.map(|periods| format!("{} periods", periods)),
+        "carryover" => guild_settings.carry_over.map(|count| format!("up to {} times", count)),
+        _ => None,
+    };
+
+    value.unwrap_or_else(|| String::from("not set"))
+}
+
+// !m config [channel #channel | role @role | admin @role | log #channel | weighting <periods|off> | carryover <count|off>]
+#[command]
+#[required_permissions("MANAGE_GUILD")]
+pub async fn config(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let db_pool = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<DBConnectionContainer>()
+            .expect("Expected DBConnection in TypeMap.")
+            .clone()
+    };
+
+    let guild_id = require_guild(msg.guild_id)?;
+
+    if args.is_empty() {
+        let guild_settings = settings::load(&db_pool, guild_id)?;
+        msg.channel_id.say(&ctx.http, describe(&guild_settings)).await?;
+        return Ok(());
+    }
+
+    let usage = || {
+        Error::UserInput(String::from(
+            "Usage: `!m config <channel|role|admin|log> <#channel|@role>`, `!m config weighting <periods|off>` \
+             or `!m config carryover <count|off>`",
+        ))
+    };
+
+    let before = settings::load(&db_pool, guild_id)?;
+
+    let setting = args.single::<String>()?.to_lowercase();
+    let reply = match setting.as_str() {
+        "channel" => set_channel(&db_pool, guild_id, args.single::<ChannelId>().map_err(|_| usage())?)?,
+        "role" => set_role(&db_pool, guild_id, args.single::<RoleId>().map_err(|_| usage())?)?,
+        "admin" => set_admin(&db_pool, guild_id, args.single::<RoleId>().map_err(|_| usage())?)?,
+        "log" => set_log(&db_pool, guild_id, args.single::<ChannelId>().map_err(|_| usage())?)?,
+        "weighting" => {
+            let periods = parse_weighting(&args.single::<String>().map_err(|_| usage())?)?;
+            set_weighting(&db_pool, guild_id, periods)?
+        }
+        "carryover" => {
+            let count = parse_carry_over(&args.single::<String>().map_err(|_| usage())?)?;
+            set_carry_over(&db_pool, guild_id, count)?
+        }
+        _ => return Err(usage().into()),
+    };
+
+    let after = settings::load(&db_pool, guild_id)?;
+    audit::record(
+        ctx,
+        &db_pool,
+        guild_id,
+        Entry::new(msg.author.id, "config", setting.as_str())
+            .before(value(&before, &setting))
+            .after(value(&after, &setting)),
+    )
+    .await;
+
+    msg.reply(&ctx.http, reply).await?;
+
+    Ok(())
+}
@@ -1,2 +1,10 @@
+pub mod audit;
+pub mod cache;
+pub mod carryover;
+pub mod config;
 pub mod math;
-pub mod movie;
\ No newline at end of file
+pub mod movie;
+pub mod notify;
+pub mod permissions;
+pub mod schedule;
+pub mod slash;
\ No newline at end of file
@@ -1,121 +1,70 @@
 use serenity::framework::standard::{macros::command, Args, CommandResult};
 use serenity::model::prelude::*;
 use serenity::prelude::*;
-use serenity::model::channel::ReactionType;
+
+use std::collections::HashSet;
 
 use tracing::{error, info};
 
-use kekw_db::{periods, rolls, submissions};
+use diesel::result::Error::NotFound;
+
+use kekw_db::{ballots, periods, results, rolls, selections, submissions, KekPool};
 
+use kekw_db::models::period::Period;
+use kekw_db::models::result::VoteResult;
+use kekw_db::models::roll::Roll;
 use kekw_db::models::submission::Submission;
 
-use crate::DBConnectionContainer;
+use crate::backend::{KekStore, Live, SerenityDiscord, Store};
+use crate::config::Config;
+use crate::{availability, links, movie_info, movie_night};
+use crate::voting::{self, VotingMode};
+use crate::audit::{self, Entry};
+use crate::error::Error;
+use crate::timed::jobs;
+use crate::utils::require_guild;
+use crate::{settings, ConfigContainer, DBConnectionContainer, MetadataContainer};
+
+// Discord allows at most 25 fields in an embed
+const MAX_HISTORY: usize = 25;
 
 #[command]
 pub async fn submit(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     if args.is_empty() {
-        if let Err(why) = msg.channel_id.say(&ctx.http, "No movie supplied.").await {
-            error!("Error sending message: {:?}", why);
-        }
-    } else {
-        let movie_submission = args.rest();
-
-        let movie_submission: String = movie_submission.split_whitespace().enumerate().map(|(i,s)| {
-
-            let mut empty_string = String::from(" ");
+        msg.channel_id.say(&ctx.http, "No movie supplied.").await?;
+        return Ok(());
+    }
 
-            let formatted_string: String = s.chars().enumerate().map(|(i, c)| {
-                if i == 0 {
-                    c.to_uppercase().to_string()
-                } else {
-                    c.to_string()
-                }
-            }).collect::<String>();
+    // Pull DBConnection from local context
+    let db_pool = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<DBConnectionContainer>()
+            .expect("Expected DBConnection in TypeMap.")
+            .clone()
+    };
+    let config = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<ConfigContainer>()
+            .expect("Expected Config in TypeMap.")
+            .clone()
+    };
+    let metadata = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<MetadataContainer>()
+            .expect("Expected Metadata in TypeMap.")
+            .clone()
+    };
 
-            if i != 0 {
-                empty_string.push_str(&formatted_string);
-                empty_string
-            } else {
-                formatted_string
-            }
-        }).collect::<String>();
-
-        // Pull DBConnection from local context
-        let db_pool = {
-            let data_read = ctx.data.read().await;
-            data_read
-                .get::<DBConnectionContainer>()
-                .expect("Expected DBConnection in TypeMap.")
-                .clone()
-        };
+    let guild_id = require_guild(msg.guild_id)?;
 
-        match periods::get_most_recent_period(&db_pool) {
-            Ok(cur_period) => {
-                let movie_subs = submissions::check_prev_sub(
-                    &db_pool.get().unwrap(),
-                    cur_period.id,
-                    &msg.author.id.to_string(),
-                );
-
-                let mut response = String::new();
-                if movie_subs.len() == 0 {
-                    let num_added = submissions::create_moviesub(
-                        &db_pool.get().unwrap(),
-                        &msg.author.id.to_string(),
-                        &movie_submission,
-                        "test",
-                        cur_period.id,
-                    );
-                    info!("Added {} movie submissions.", num_added);
-                    response = format!("You've submitted the movie: {}", movie_submission);
-                    info!(
-                        "{}:{} submitted movie {}",
-                        msg.author, msg.author.name, movie_submission
-                    );
-                    msg.channel_id.say(&ctx.http, response).await?;
-                } else {
-                    let conf_message = format!("You've already submitted the movie: {}, would you like to update your submission?", movie_subs[0].title);
-                    let yes_msg = String::from("Submission updated!");
-                    let no_msg = String::from("Submission not updated.");
-
-                    use crate::utils::Confirmation;
-
-                    match crate::utils::ask_confirmation(
-                        &ctx,
-                        msg.author.id,
-                        msg.channel_id,
-                        conf_message,
-                        yes_msg,
-                        no_msg,
-                    )
-                    .await
-                    {
-                        Ok(Confirmation::Yes) => {
-                            // TODO: Make update_moviesub
-                            let mut updated_moviesub = movie_subs[0].clone();
-                            updated_moviesub.title = String::from(movie_submission);
-                            submissions::update_moviesub(&db_pool, updated_moviesub)?;
-                        }
-                        Ok(Confirmation::No) => (),
-                        Ok(Confirmation::InvalidConfirmation) => {
-                            msg.reply(ctx, "Error").await?;
-                        }
-                        _ => {
-                            msg.reply(ctx, "Error").await?;
-                        }
-                    }
-                }
-            }
-            Err(NotFound) => {
-                msg.channel_id
-                    .say(&ctx.http, "No current movie submission periods active.")
-                    .await
-                    .unwrap();
-            }
-            Err(e) => {
-                error!("Failed to get movie submission");
-            }
-        }
+    let live = Live::new(ctx, &db_pool, &config, &metadata);
+    let submitted =
+        movie_night::submit_movie(live.backend(), guild_id, msg.channel_id, msg.author.id, args.rest()).await?;
+    if let Some(entry) = submitted {
+        audit::record(ctx, &db_pool, guild_id, entry).await;
     }
 
     Ok(())
@@ -130,79 +79,26 @@ pub async fn getsubs(ctx: &Context, msg: &Message, args: Args) -> CommandResult
             .expect("Expected DBConnection in TypeMap.")
             .clone()
     };
+    let config = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<ConfigContainer>()
+            .expect("Expected Config in TypeMap.")
+            .clone()
+    };
+    let metadata = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<MetadataContainer>()
+            .expect("Expected Metadata in TypeMap.")
+            .clone()
+    };
 
-    match periods::get_most_recent_period(&db_pool) {
-        Ok(cur_period) => {
-            // Query DB
-            let movie_subs = submissions::get_moviesubs(&db_pool.get().unwrap(), &cur_period);
-            info!("Got {} movie submission(s).", movie_subs.len());
-
-            // Struct to help with information gathered from the DB
-            struct DisMovieSub {
-                movie_sub: Submission,
-                user: User,
-                nick: String,
-            }
-
-            let mut dis_movie_subs: Vec<DisMovieSub> = Vec::new();
-
-            use std::convert::TryFrom;
-
-            // TODO: Move to closure once async closures are a thing.
-            for movie_sub in movie_subs.clone() {
-                let user_id: u64 = movie_sub.dis_user_id.parse().unwrap();
-                let user = UserId::try_from(user_id)
-                    .unwrap()
-                    .to_user(&ctx.http)
-                    .await?;
-                let nick = match user.nick_in(&ctx.http, msg.guild_id.unwrap()).await {
-                    Some(nick) => nick,
-                    None => String::from(""),
-                };
-                dis_movie_subs.push(DisMovieSub {
-                    movie_sub,
-                    user,
-                    nick,
-                });
-            }
-
-            use serenity::model::id::UserId;
+    let guild_id = require_guild(msg.guild_id)?;
+    let guild_settings = settings::load(&db_pool, guild_id)?;
 
-            msg.channel_id
-                .send_message(&ctx.http, |m| {
-                    m.embed(|e| {
-                        e.title("Current Movie Submissions");
-                        for dis_movie_sub in dis_movie_subs {
-                            if dis_movie_sub.nick.is_empty() {
-                                e.field(
-                                    format!("{}", dis_movie_sub.user.name),
-                                    dis_movie_sub.movie_sub.title,
-                                    false,
-                                );
-                            } else {
-                                e.field(
-                                    format!("{}({})", dis_movie_sub.nick, dis_movie_sub.user.name),
-                                    dis_movie_sub.movie_sub.title,
-                                    false,
-                                );
-                            }
-                        }
-                        e
-                    });
-                    m
-                })
-                .await?;
-        }
-        Err(NotFound) => {
-            msg.channel_id
-                .say(&ctx.http, "No current movie submission periods active.")
-                .await
-                .unwrap();
-        }
-        Err(e) => {
-            error!("Failed to get movie submission");
-        }
-    }
+    let live = Live::new(ctx, &db_pool, &config, &metadata);
+    movie_night::post_submissions(live.backend(), &guild_settings, msg.channel_id).await?;
 
     Ok(())
 }
@@ -226,7 +122,9 @@ pub async fn deletesub(ctx: &Context, msg: &Message, args: Args) -> CommandResul
         return Ok(());
     }
 
-    match periods::get_most_recent_period(&db_pool) {
+    let guild_id = require_guild(msg.guild_id)?;
+
+    match periods::get_most_recent_period(&db_pool, &guild_id.to_string()) {
         Err(_) => {
             msg.reply(&ctx.http, "No current movie submission period exists.")
                 .await?;
@@ -240,6 +138,14 @@ pub async fn deletesub(ctx: &Context, msg: &Message, args: Args) -> CommandResul
                 ) {
                     Ok(sub) => {
                         submissions::delete_moviesub(&db_pool, &sub);
+                        audit::record(
+                            ctx,
+                            &db_pool,
+                            guild_id,
+                            Entry::new(msg.author.id, "deletesub", format!("{}'s submission {}", user.name, sub.id))
+                                .before(sub.title.as_str()),
+                        )
+                        .await;
                         msg.reply(
                             &ctx.http,
                             &format!("Deleted submission {} for {}.", sub.title, user.name),
@@ -269,246 +175,170 @@ pub async fn roll(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
             .expect("Expected DBConnection in TypeMap.")
             .clone()
     };
+    let config = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<ConfigContainer>()
+            .expect("Expected Config in TypeMap.")
+            .clone()
+    };
+    let metadata = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<MetadataContainer>()
+            .expect("Expected Metadata in TypeMap.")
+            .clone()
+    };
 
-    // Get most recent period
-    match periods::get_most_recent_period(&db_pool) {
-        Ok(cur_period) => {
-            // Get movie submissions for the current period
-            let movie_subs = submissions::get_moviesubs(&db_pool.get().unwrap(), &cur_period);
-            info!("Got {} movie submission(s).", movie_subs.len());
-
-            if movie_subs.len() >= 2 {
-                // Confirm roll
-                let conf_message = String::from("Would you like to roll for movie night?");
-                let yes_msg = String::from("Starting roll");
-                let no_msg = String::from("Cancelling roll");
-
-                use crate::utils::Confirmation;
-
-                let start_roll = match crate::utils::ask_confirmation(
-                    &ctx,
-                    msg.author.id,
-                    msg.channel_id,
-                    conf_message,
-                    yes_msg,
-                    no_msg,
-                )
-                .await
-                {
-                    Ok(Confirmation::Yes) => true,
-                    Ok(Confirmation::No) => false,
-                    _ => false,
-                };
-
-                // User decided to start a roll
-                if start_roll {
-                    // End current submission period
-                    let cur_period = periods::end_period(&db_pool, cur_period)?;
-
-                    // Check if a roll already exists
-                    if let Ok(cur_roll) = rolls::get_roll_by_period(&db_pool, &cur_period) {
-                        let conf_message = format!("There already exists a roll for this movie submission period, would you like to roll again?");
-                        let yes_msg = String::from("Rolling again!");
-                        let no_msg = String::from("Cancelling roll.");
-                        use crate::utils::Confirmation;
-                        match crate::utils::ask_confirmation(
-                            &ctx,
-                            msg.author.id,
-                            msg.channel_id,
-                            conf_message,
-                            yes_msg,
-                            no_msg,
-                        )
-                        .await
-                        {
-                            Ok(Confirmation::Yes) => {
-                                rolls::delete_roll(&db_pool.clone(), cur_roll.id)?;
-                            }
-                            Ok(Confirmation::No) => {
-                                return Ok(());
-                            }
-                            _ => {
-                                return Ok(());
-                            }
-                        };
-                    }
+    let guild_id = require_guild(msg.guild_id)?;
+    let guild_settings = settings::load(&db_pool, guild_id)?;
 
-                    // Randomization
-                    use rand::rngs::SmallRng;
-                    use rand::{Rng, SeedableRng};
+    let live = Live::new(ctx, &db_pool, &config, &metadata);
+    let rolled =
+        movie_night::roll_movies(live.backend(), &config, &guild_settings, msg.channel_id, msg.author.id).await?;
+    if let Some(entry) = rolled {
+        audit::record(ctx, &db_pool, guild_id, entry).await;
+    }
 
-                    let mut rng = SmallRng::from_entropy();
+    Ok(())
+}
 
-                    let choice_1: usize = rng.gen_range(0, movie_subs.len());
+// The roll of the guild's closed period that hasn't picked a winner yet
+fn running_roll(db_pool: &KekPool, guild_id: GuildId) -> Option<Roll> {
+    match periods::get_most_recent_closed_period(db_pool, &guild_id.to_string())
+        .and_then(|cur_period| rolls::get_roll_by_period(db_pool, &cur_period))
+    {
+        Ok(cur_roll) if cur_roll.winner.is_none() => Some(cur_roll),
+        _ => None,
+    }
+}
 
-                    let choice_2: usize = {
-                        let mut temp: usize = rng.gen_range(0, movie_subs.len());
-                        loop {
-                            if temp != choice_1 {
-                                break;
-                            }
+// Ballots sent over DM don't say which server they are for, so find the one
+// server the voter is in that has a ranked vote running
+pub async fn ballot_guild(ctx: &Context, db_pool: &KekPool, user_id: UserId) -> std::result::Result<GuildId, Error> {
+    let mut voting_guilds: Vec<GuildId> = Vec::new();
 
-                            // Regenerate another choice.
-                            temp = rng.gen_range(0, movie_subs.len());
-                        }
+    for guild in settings::configured(db_pool).map_err(Error::Db)? {
+        let ranked = running_roll(db_pool, guild.guild_id)
+            .map(|roll| roll.voting_mode.parse::<VotingMode>() == Ok(VotingMode::Ranked))
+            .unwrap_or(false);
 
-                        temp
-                    };
-
-                    let choice_movie1 = movie_subs[choice_1].clone();
-                    let choice_movie2 = movie_subs[choice_2].clone();
-
-                    // Insert roll into roll table
-                    let cur_roll = rolls::create_roll(&db_pool, &cur_period, choice_movie1.id, choice_movie2.id)?;
-
-                    use std::convert::TryFrom;
-                    // Lookup user by discord id
-                    let choice_user1 =
-                        UserId::try_from(choice_movie1.dis_user_id.parse::<u64>().unwrap())
-                            .unwrap()
-                            .to_user(&ctx.http)
-                            .await
-                            .unwrap();
-                    let choice_user2 =
-                        UserId::try_from(choice_movie2.dis_user_id.parse::<u64>().unwrap())
-                            .unwrap()
-                            .to_user(&ctx.http)
-                            .await
-                            .unwrap();
-
-                    // Get current nickname in current guild
-                    let choice_nick1 = choice_user1.name;
-                    let choice_nick2 = choice_user2.name;
-
-                    // Respond with movie selection message
-                    let msg_movie_selection = msg.channel_id.send_message(&ctx.http, |m| {
-                        m.embed(|e| {
-                            e.title("Movie Emoji Selections!");
-                            e.description("Please react with the two emotes you would like to use for voting. Make sure to only use Emoji's from within this Guild/Server. You have 5 minutes.");
-                            e.field(
-                                &choice_movie1.title,
-                                format!("submitted by {}", choice_nick1),
-                                false
-                            );
-                            e.field(
-                                &choice_movie2.title,
-                                format!("submitted by {}", choice_nick2),
-                                false
-                            );
-
-                            e
-                        });
-
-                        m
-                    }).await?;
-
-                    // Collect reactions from previous message
-                    use serenity::futures::StreamExt;
-                    let reactions: Vec<_> = msg_movie_selection
-                        .await_reactions(&ctx)
-                        .timeout(std::time::Duration::from_secs(60 * 5))
-                        .author_id(msg.author.id)
-                        .collect_limit(2)
-                        .await
-                        .collect()
-                        .await;
+        if ranked && guild.guild_id.member(ctx, user_id).await.is_ok() {
+            voting_guilds.push(guild.guild_id);
+        }
+    }
 
-                    // Check that two reactions were supplied
-                    if reactions.len() == 2 {
-                        let mut emoji_check = false;
-
-                        for reaction in reactions.clone() {
-                            emoji_check = match reaction.as_inner_ref().emoji.clone() {
-                                ReactionType::Custom {
-                                    animated: _,
-                                    id,
-                                    name: _,
-                                } => {
-                                    // TODO: Dumb way to do this?
-                                    // Check if emoji exists in the guild?
-                                    match msg
-                                        .guild(&ctx.cache)
-                                        .await
-                                        .unwrap()
-                                        .emoji(&ctx.http, id)
-                                        .await
-                                    {
-                                        Ok(_) => true,
-                                        Err(_) => false,
-                                    }
-                                }
-                                ReactionType::Unicode(_) => true,
-                                _ => false,
-                            };
-                        }
+    match voting_guilds.len() {
+        0 => Err(Error::UserInput(String::from("There is no movie vote running."))),
+        1 => Ok(voting_guilds[0]),
+        _ => Err(Error::UserInput(String::from(
+            "You're in more than one movie vote, use `/movie vote` in the server you're voting in.",
+        ))),
+    }
+}
 
-                        if emoji_check == false {
-                            msg_movie_selection.delete(&ctx.http).await.unwrap();
-                            msg.reply(&ctx.http, "Cannot use emoji outside of Guild/Server!")
-                                .await
-                                .unwrap();
-                        } else {
-                            // Delete emoji selection message
-                            msg_movie_selection.delete(&ctx.http).await.unwrap();
-
-                            let reaction_1 = reactions[0].clone();
-                            let reaction_2 = reactions[1].clone();
-
-                            // Send voting message
-                            let vote_msg = msg.channel_id.send_message(&ctx.http, |m| {
-                                m.embed(|e| {
-                                    e.title("Movie Voting!");
-                                    e.field(
-                                        choice_movie1.title,
-                                        format!(
-                                            "submitted by {} use {}",
-                                            choice_nick1,
-                                            &reaction_1.as_inner_ref().emoji
-                                        ),
-                                        false,
-                                    );
-                                    e.field(
-                                        choice_movie2.title,
-                                        format!(
-                                            "submitted by {} use {}",
-                                            choice_nick2,
-                                            &reaction_2.clone().as_inner_ref().emoji
-                                        ),
-                                        false,
-                                    );
-                                    e
-                                });
-
-                                m
-                            }).await?;
-
-                            let emoji_1 = &reaction_1.as_inner_ref().emoji;
-                            let emoji_2 = &reaction_2.as_inner_ref().emoji;
-
-                            // Convert u64 to string since postgresql doesnt have u64
-                            periods::set_vote_message(&db_pool, cur_period, vote_msg.id.as_u64().to_string())?;
-
-                            rolls::set_selection_emotes(&db_pool, cur_roll, emoji_1.to_string(), emoji_2.to_string())?;
-                        }
-                    } else {
-                        msg.reply(&ctx.http, "No reactions supplied, try rolling later.")
-                            .await
-                            .unwrap();
-                    }
-                } // End check start roll
-            } else {
-                msg.reply(&ctx.http, "Not enough movies submitted to choose two!")
-                    .await?;
-            }
+// Record a ranked ballot from the numbers shown on the voting message.
+// Returns the reply for the voter, rejected ballots are a UserInput error.
+pub fn cast_ballot(
+    db_pool: &KekPool,
+    config: &Config,
+    guild_id: GuildId,
+    dis_user_id: &str,
+    positions: &[usize],
+) -> std::result::Result<String, Error> {
+    let cur_roll = running_roll(db_pool, guild_id)
+        .ok_or_else(|| Error::UserInput(String::from("There is no movie vote running.")))?;
+
+    if cur_roll.voting_mode.parse::<VotingMode>() != Ok(VotingMode::Ranked) {
+        return Err(Error::UserInput(String::from(
+            "This vote uses reactions, react on the voting message instead!",
+        )));
+    }
+
+    let roll_selections = selections::get_selections_by_roll(db_pool, &cur_roll)?;
+
+    // Convert the numbers from the voting message into submission ids
+    let mut ranking: Vec<i32> = Vec::new();
+    for position in positions {
+        if *position < 1 || *position > roll_selections.len() {
+            return Err(Error::UserInput(format!(
+                "Please rank movies using numbers from 1 to {}.",
+                roll_selections.len()
+            )));
         }
-        Err(e) => {
-            msg.channel_id
-                .say(&ctx.http, "No current movie submission periods active.")
-                .await
-                .unwrap();
+
+        let submission_id = roll_selections[position - 1].submission_id;
+        if ranking.contains(&submission_id) {
+            return Err(Error::UserInput(String::from("Each movie can only be ranked once.")));
+        }
+
+        ranking.push(submission_id);
+    }
+
+    if ranking.is_empty() {
+        return Err(Error::UserInput(format!(
+            "Please rank the movies, for example {}.",
+            voting::ranked_example(config)
+        )));
+    }
+
+    let ranking_str: Vec<String> = ranking.iter().map(|id| id.to_string()).collect();
+    ballots::set_ballot(db_pool, &cur_roll, dis_user_id, &ranking_str.join(","))?;
+
+    let mut ballot_str = String::from("Your ballot has been recorded:");
+    for (i, submission_id) in ranking.iter().enumerate() {
+        let movie_sub = submissions::get_submission_by_id(db_pool, *submission_id)?;
+        ballot_str.push_str(&format!("\n{}. {}", i + 1, movie_sub.title));
+    }
+
+    Ok(ballot_str)
+}
+
+#[command]
+pub async fn vote(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let db_pool = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<DBConnectionContainer>()
+            .expect("Expected DBConnection in TypeMap.")
+            .clone()
+    };
+
+    let config = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<ConfigContainer>()
+            .expect("Expected Config in TypeMap.")
+            .clone()
+    };
+
+    // Anything that isn't a number is treated as an invalid position
+    let positions: Vec<usize> = args.iter::<usize>().map(|p| p.unwrap_or(0)).collect();
+
+    let guild_id = match msg.guild_id {
+        Some(guild_id) => guild_id,
+        None => ballot_guild(ctx, &db_pool, msg.author.id).await?,
+    };
+
+    let ballot_str = cast_ballot(&db_pool, &config, guild_id, &msg.author.id.to_string(), &positions)?;
+    info!("{}:{} submitted a ranked ballot", msg.author, msg.author.name);
+    // Only that a ballot was cast, the ranking stays secret
+    audit::record(ctx, &db_pool, guild_id, Entry::new(msg.author.id, "vote", "ranked ballot")).await;
+
+    // Keep ballots secret when they are sent in a server
+    if msg.guild_id.is_some() {
+        if let Err(why) = msg.delete(&ctx.http).await {
+            error!("Error deleting ballot message: {:?}", why);
         }
     }
 
+    msg.author
+        .direct_message(&ctx.http, |m| {
+            m.content(ballot_str);
+            m
+        })
+        .await?;
+
     Ok(())
 }
 
@@ -521,18 +351,30 @@ pub async fn startperiod(ctx: &Context, msg: &Message, args: Args) -> CommandRes
             .expect("Expected DBConnection in TypeMap.")
             .clone()
     };
+    let config = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<ConfigContainer>()
+            .expect("Expected Config in TypeMap.")
+            .clone()
+    };
+    let metadata = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<MetadataContainer>()
+            .expect("Expected Metadata in TypeMap.")
+            .clone()
+    };
 
-    match periods::get_most_recent_period(&db_pool) {
-        Ok(cur_period) => {
-            msg.channel_id.say(&ctx.http, "A submission period has already started, run `!m roll` to finish the current submission period.").await.unwrap();
-        }
-        Err(NotFound) => {
-            periods::create_period(&db_pool)?;
-            msg.channel_id
-                .say(&ctx.http, "Started new submission period!")
-                .await
-                .unwrap();
-        }
+    let guild_id = require_guild(msg.guild_id)?;
+    let guild_settings = settings::load(&db_pool, guild_id)?;
+
+    // Announced to the notify role in this channel
+    let live = Live::new(ctx, &db_pool, &config, &metadata);
+    if jobs::open_period(live.backend(), &config, &guild_settings, msg.channel_id).await? {
+        audit::record(ctx, &db_pool, guild_id, Entry::new(msg.author.id, "startperiod", "submission period").after("open")).await;
+    } else {
+        msg.channel_id.say(&ctx.http, "A submission period has already started, run `!m roll` to finish the current submission period.").await?;
     }
 
     Ok(())
@@ -548,23 +390,32 @@ pub async fn reopenperiod(ctx: &Context, msg: &Message, args: Args) -> CommandRe
             .clone()
     };
 
-    match periods::get_most_recent_closed_period(&db_pool) {
+    let guild_id = require_guild(msg.guild_id)?;
+
+    match periods::get_most_recent_closed_period(&db_pool, &guild_id.to_string()) {
         Ok(cur_period) => {
+            let target = format!("period {}", cur_period.id);
             periods::reopen_period(&db_pool, cur_period)?;
+            audit::record(
+                ctx,
+                &db_pool,
+                guild_id,
+                Entry::new(msg.author.id, "reopenperiod", target).before("closed").after("open"),
+            )
+            .await;
             msg.channel_id
                 .say(&ctx.http, "Reopened last submission period!")
-                .await
-                .unwrap();
+                .await?;
         }
-        Err(e) => {
+        Err(NotFound) => {
             msg.channel_id
                 .say(
                     &ctx.http,
                     "Could not find a recently closed submission period.",
                 )
-                .await
-                .unwrap();
+                .await?;
         }
+        Err(why) => return Err(why.into()),
     }
     Ok(())
 }
@@ -579,20 +430,29 @@ pub async fn endperiod(ctx: &Context, msg: &Message, args: Args) -> CommandResul
             .clone()
     };
 
-    match periods::get_most_recent_period(&db_pool) {
+    let guild_id = require_guild(msg.guild_id)?;
+
+    match periods::get_most_recent_period(&db_pool, &guild_id.to_string()) {
         Ok(cur_period) => {
+            let target = format!("period {}", cur_period.id);
             periods::end_period(&db_pool, cur_period)?;
+            audit::record(
+                ctx,
+                &db_pool,
+                guild_id,
+                Entry::new(msg.author.id, "endperiod", target).before("open").after("closed"),
+            )
+            .await;
             msg.channel_id
                 .say(&ctx.http, "Ended current movie submission without roll!")
-                .await
-                .unwrap();
+                .await?;
         }
-        Err(e) => {
+        Err(NotFound) => {
             msg.channel_id
                 .say(&ctx.http, "No current movie submission period exists.")
-                .await
-                .unwrap();
+                .await?;
         }
+        Err(why) => return Err(why.into()),
     }
     Ok(())
 }
@@ -607,54 +467,333 @@ pub async fn listperiods(ctx: &Context, msg: &Message, args: Args) -> CommandRes
             .clone()
     };
 
-    match periods::get_periods(&db_pool) {
-        Ok(movie_periods) => {
+    let guild_id = require_guild(msg.guild_id)?;
+    let fields = period_fields(&db_pool, guild_id)?;
+
+    if fields.is_empty() {
+        msg.channel_id
+            .say(&ctx.http, "There haven't been any submission periods yet.")
+            .await?;
+        return Ok(());
+    }
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title("Submission Periods");
+                for (name, value) in fields {
+                    e.field(name, value, false);
+                }
+                e
+            });
+
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+// Embed fields describing each period and its roll, most recent first.
+// Fitted to one embed, so a guild with many periods loses the oldest.
+pub fn period_fields(
+    db_pool: &KekPool,
+    guild_id: GuildId,
+) -> Result<Vec<(String, String)>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut movie_periods = periods::get_periods(db_pool, &guild_id.to_string())?;
+    movie_periods.sort_by(|a, b| b.id.cmp(&a.id));
+
+    let mut fields: Vec<(String, String)> = Vec::new();
+    for movie_period in movie_periods {
+        let name = match &movie_period.end_day {
+            Some(end_day) => format!(
+                "Period {}: {} to {}",
+                movie_period.id, movie_period.start_day, end_day
+            ),
+            None => format!("Period {}: {} to now", movie_period.id, movie_period.start_day),
+        };
+
+        let mut lines: Vec<String> = Vec::new();
+        match rolls::get_roll_by_period(db_pool, &movie_period) {
+            Ok(movie_roll) => {
+                let roll_selections = selections::get_selections_by_roll(db_pool, &movie_roll)?;
+                let roll_results = results::get_results_by_roll(db_pool, &movie_roll)?;
+
+                let mut winner_title = String::from("Not decided yet");
+                for (i, selection) in roll_selections.iter().enumerate() {
+                    let movie_roll_sub = submissions::get_submission_by_id(db_pool, selection.submission_id)?;
+                    lines.push(format!("Choice {}: {}", i + 1, with_votes(&movie_roll_sub, &roll_results)));
+
+                    if movie_roll.winner == Some(movie_roll_sub.id) {
+                        winner_title = movie_roll_sub.title;
+                    }
+                }
+
+                lines.push(format!("Winner: {}", winner_title));
+            }
+            Err(NotFound) => lines.push(String::from("No Roll!")),
+            Err(why) => return Err(why.into()),
+        }
+
+        fields.push((name, lines.join("\n")));
+    }
+
+    Ok(movie_info::fit_fields(fields))
+}
+
+// Roll the period again from its stored seed and say whether it matches, shared with the slash command
+pub fn verify_message(store: &dyn Store, guild_id: GuildId, period_id: i32) -> Result<String, Error> {
+    let period = store
+        .period(guild_id, period_id)
+        .map_err(Error::from_boxed)?
+        .ok_or_else(|| Error::UserInput(format!("There's no submission period {}.", period_id)))?;
+
+    let verification = voting::verify_roll(store, &period)
+        .map_err(Error::from_boxed)?
+        .ok_or_else(|| Error::UserInput(format!("Period {} doesn't have a roll with a stored seed.", period_id)))?;
+
+    let titles = |movie_subs: &[Submission]| -> String {
+        movie_subs
+            .iter()
+            .map(|movie_sub| movie_sub.title.as_str())
+            .collect::<Vec<&str>>()
+            .join(", ")
+    };
+
+    let mut verify_str = format!(
+        "Period {} was rolled with seed {}, hash `{}`.",
+        period.id,
+        verification.seed,
+        voting::commitment(verification.seed)
+    );
+    if verification.committed {
+        verify_str.push_str("\nThe hash was announced when the period opened.");
+    } else {
+        verify_str.push_str("\nThe roll was redone, so the seed wasn't announced in advance.");
+    }
+    if let Some(periods) = verification.weighting {
+        verify_str.push_str(&format!(
+            "\nSubmitters were weighted by how long they'd gone unpicked, looking back {} periods.",
+            periods
+        ));
+    }
+    verify_str.push_str(&format!("\nRolling again picks: {}", titles(&verification.recomputed)));
+    if verification.matches() {
+        verify_str.push_str("\nThat matches the roll.");
+    } else {
+        verify_str.push_str(&format!(
+            "\nThat does NOT match the roll, which picked: {}",
+            titles(&verification.stored)
+        ));
+    }
+
+    Ok(verify_str)
+}
+
+// !m verifyroll <period>
+#[command]
+pub async fn verifyroll(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let db_pool = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<DBConnectionContainer>()
+            .expect("Expected DBConnection in TypeMap.")
+            .clone()
+    };
+
+    let guild_id = require_guild(msg.guild_id)?;
+    let period_id = args
+        .single::<i32>()
+        .map_err(|_| Error::UserInput(String::from("Usage: `!m verifyroll <period>`, see `!m listperiods`")))?;
+
+    let store = KekStore::new(db_pool);
+    msg.channel_id
+        .say(&ctx.http, verify_message(&store, guild_id, period_id)?)
+        .await?;
+
+    Ok(())
+}
+
+// Show a submission's title along with its recorded votes
+fn with_votes(movie_sub: &Submission, roll_results: &[VoteResult]) -> String {
+    match roll_results
+        .iter()
+        .find(|result| result.submission_id == movie_sub.id)
+    {
+        Some(result) => format!("{} ({} votes)", movie_sub.title, result.votes),
+        None => movie_sub.title.clone(),
+    }
+}
+
+// Embed fields describing the last count winners, most recent first.
+// At most MAX_HISTORY are looked up however many are asked for.
+pub async fn history_fields(
+    ctx: &Context,
+    db_pool: &KekPool,
+    guild_id: GuildId,
+    count: usize,
+) -> Result<Vec<(String, String)>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut movie_periods = periods::get_periods(db_pool, &guild_id.to_string())?;
+    movie_periods.sort_by(|a, b| b.id.cmp(&a.id));
+
+    // Struct to help with information gathered from the DB
+    struct Decision {
+        period: Period,
+        winner: Submission,
+        submitter: String,
+        decided_at: String,
+        votes: Vec<String>,
+    }
+
+    let mut decisions: Vec<Decision> = Vec::new();
+    let count = count.min(MAX_HISTORY);
+
+    for movie_period in movie_periods {
+        if decisions.len() >= count {
+            break;
+        }
+
+        let movie_roll = match rolls::get_roll_by_period(db_pool, &movie_period) {
+            Ok(movie_roll) => movie_roll,
+            Err(_) => continue,
+        };
+
+        let winner_id = match movie_roll.winner {
+            Some(winner_id) => winner_id,
+            None => continue,
+        };
+
+        let winner = submissions::get_submission_by_id(db_pool, winner_id)?;
+        let submitter = voting::submitter_name(&SerenityDiscord::new(ctx), &winner).await?;
+
+        let mut votes: Vec<String> = Vec::new();
+        for result in results::get_results_by_roll(db_pool, &movie_roll)? {
+            let movie_sub = submissions::get_submission_by_id(db_pool, result.submission_id)?;
+            votes.push(format!("{}: {}", movie_sub.title, result.votes));
+        }
+
+        decisions.push(Decision {
+            period: movie_period,
+            winner,
+            submitter,
+            decided_at: match movie_roll.decided_at {
+                Some(decided_at) => decided_at.format("%Y-%m-%d %H:%M UTC").to_string(),
+                None => String::from("unknown"),
+            },
+            votes,
+        });
+    }
+
+    let fields = decisions
+        .into_iter()
+        .map(|decision| {
+            (
+                format!("{} (period {})", decision.winner.title, decision.period.id),
+                format!(
+                    "submitted by {}, decided {}\n{}",
+                    decision.submitter,
+                    decision.decided_at,
+                    decision.votes.join(", ")
+                ),
+            )
+        })
+        .collect();
+
+    Ok(movie_info::fit_fields(fields))
+}
+
+#[command]
+pub async fn history(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let db_pool = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<DBConnectionContainer>()
+            .expect("Expected DBConnection in TypeMap.")
+            .clone()
+    };
+
+    // Number of weeks to show, defaults to 10
+    let count = args.single::<usize>().unwrap_or(10);
+
+    let decisions = history_fields(ctx, &db_pool, require_guild(msg.guild_id)?, count).await?;
+
+    if decisions.is_empty() {
+        msg.channel_id
+            .say(&ctx.http, "No movies have been picked yet.")
+            .await?;
+        return Ok(());
+    }
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title("Movie Night History");
+                for (name, value) in decisions {
+                    e.field(name, value, false);
+                }
+                e
+            });
+
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+pub async fn info(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    if args.is_empty() {
+        msg.reply(&ctx.http, "No movie supplied.").await?;
+        return Ok(());
+    }
+
+    let db_pool = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<DBConnectionContainer>()
+            .expect("Expected DBConnection in TypeMap.")
+            .clone()
+    };
+    let config = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<ConfigContainer>()
+            .expect("Expected Config in TypeMap.")
+            .clone()
+    };
+    let metadata = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<MetadataContainer>()
+            .expect("Expected Metadata in TypeMap.")
+            .clone()
+    };
+
+    // Accepts a title, an IMDb id or a link
+    match movie_info::find_movie(&db_pool, &config, metadata.as_ref(), args.rest()).await? {
+        Some(movie) => {
+            let availability = availability::lookup(&db_pool, &config, &movie.imdb_id).await;
+
             msg.channel_id
                 .send_message(&ctx.http, |m| {
                     m.embed(|e| {
-                        for movie_period in movie_periods {
-                            e.field("Start Date", movie_period.start_day, true);
-                            if movie_period.end_day.is_some() {
-                                e.field("End Date", movie_period.end_day.unwrap(), true);
-                            }
-
-                            if let Ok(movie_roll) =
-                                rolls::get_roll_by_period(&db_pool, &movie_period)
-                            {
-                                let movie_roll_1 = submissions::get_submission_by_id(
-                                    &db_pool,
-                                    movie_roll.selection_1,
-                                )
-                                .unwrap();
-                                let movie_roll_2 = submissions::get_submission_by_id(
-                                    &db_pool,
-                                    movie_roll.selection_2,
-                                )
-                                .unwrap();
-                                e.field("Choice 1", movie_roll_1.title, false);
-                                e.field("Choice 2", movie_roll_2.title, false);
-                            } else {
-                                e.field("Roll", "No Roll!", false);
-                            }
+                        movie_info::movie_embed(e, &movie);
+                        if let Some(availability) = &availability {
+                            movie_info::availability_field(e, availability);
                         }
                         e
                     });
 
                     m
                 })
-                .await
-                .unwrap();
+                .await?;
         }
-        Err(e) => {
-            msg.channel_id
-                .say(
-                    &ctx.http,
-                    "Could not find a recently closed submission period.",
-                )
-                .await
-                .unwrap();
+        None => {
+            msg.reply(&ctx.http, "Couldn't find that movie.").await?;
         }
     }
+
     Ok(())
 }
 
@@ -667,40 +806,50 @@ pub async fn fixdb(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
             .expect("Expected DBConnection in TypeMap.")
             .clone()
     };
+    let config = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<ConfigContainer>()
+            .expect("Expected Config in TypeMap.")
+            .clone()
+    };
+    let metadata = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<MetadataContainer>()
+            .expect("Expected Metadata in TypeMap.")
+            .clone()
+    };
 
-    let movie_subs = submissions::get_all_moviesubs(&db_pool);
-
-    for movie_sub in movie_subs {
-        let movie_title = movie_sub.title.clone();
-        let keys = movie_title.split(" ");
-
-        for key in keys {
-            if key.contains("imdb") && key.contains("http") {
-                if let Ok(imdb_url) = reqwest::Url::parse(key) {
-                    let path_segments = imdb_url.path_segments().ok_or_else(|| "cannot be base")?;
-
-                    for path_segment in path_segments {
-                        if path_segment.starts_with("tt") {
-                            use crate::omdb;
+    let guild_id = require_guild(msg.guild_id)?;
 
-                            println!("Found imdb link {}", path_segment);
+    // Submissions are stored for every guild, only this guild's are repaired
+    let guild_periods: HashSet<i32> = periods::get_periods(&db_pool, &guild_id.to_string())?
+        .iter()
+        .map(|period| period.id)
+        .collect();
+    let movie_subs = submissions::get_all_moviesubs(&db_pool)
+        .into_iter()
+        .filter(|movie_sub| guild_periods.contains(&movie_sub.period_id));
 
-                            let movie = omdb::query_by_id(String::from(path_segment))
-                                .await
-                                .unwrap()
-                                .unwrap();
+    // New submissions are resolved when submitted, this repairs older ones that were stored as links
+    for movie_sub in movie_subs {
+        if let Some(link) = links::find_movie_link(&movie_sub.title) {
+            info!("Found movie link in submission {}", movie_sub.id);
 
-                            let mut updated_moviesub = movie_sub.clone();
+            if let Some((title, imdb_id)) = links::resolve(&db_pool, &config, metadata.as_ref(), link).await? {
+                let mut updated_moviesub = movie_sub.clone();
 
-                            println!("Updating movie submission id: {}", updated_moviesub.id);
+                info!("Updating movie submission id: {}", updated_moviesub.id);
 
-                            updated_moviesub.title = movie.title;
-                            updated_moviesub.link = movie.imdb_id;
+                updated_moviesub.title = title;
+                updated_moviesub.link = imdb_id;
 
-                            submissions::update_moviesub(&db_pool, updated_moviesub)?;
-                        }
-                    }
-                }
+                let entry = Entry::new(msg.author.id, "fixdb", format!("submission {}", movie_sub.id))
+                    .before(movie_sub.title.as_str())
+                    .after(updated_moviesub.title.as_str());
+                submissions::update_moviesub(&db_pool, updated_moviesub)?;
+                audit::record(ctx, &db_pool, guild_id, entry).await;
             }
         }
     }
@@ -0,0 +1,86 @@
+use serenity::framework::standard::{macros::command, Args, CommandResult};
+use serenity::model::prelude::*;
+use serenity::prelude::*;
+
+use tracing::info;
+
+use kekw_db::KekPool;
+
+use crate::audit::{self, Entry};
+use crate::error::Error;
+use crate::settings::GuildSettings;
+use crate::{settings, DBConnectionContainer};
+
+// Give or take away the guild's notify role. Returns the reply for the member.
+pub async fn set_notify(
+    ctx: &Context,
+    db_pool: &KekPool,
+    guild_settings: &GuildSettings,
+    user_id: UserId,
+    on: bool,
+) -> std::result::Result<String, Error> {
+    let notify_role = guild_settings.notify_role.ok_or_else(|| {
+        Error::UserInput(String::from(
+            "This server doesn't have a movie night role, ask an admin to set one with `!m config role`.",
+        ))
+    })?;
+
+    let mut member = guild_settings.guild_id.member(&ctx.http, user_id).await?;
+    let has_role = member.roles.contains(&notify_role);
+
+    let reply = match (on, has_role) {
+        (true, true) => "You're already getting movie night pings.",
+        (false, false) => "You're not getting movie night pings.",
+        (true, false) => {
+            member.add_role(&ctx.http, notify_role).await?;
+            "You'll now get pinged for movie night."
+        }
+        (false, true) => {
+            member.remove_role(&ctx.http, notify_role).await?;
+            "You won't get pinged for movie night anymore."
+        }
+    };
+
+    if on != has_role {
+        info!("{} turned movie night pings {}", member.user.name, if on { "on" } else { "off" });
+        audit::record(
+            ctx,
+            db_pool,
+            guild_settings.guild_id,
+            Entry::new(user_id, "notify", "movie night pings")
+                .before(if has_role { "on" } else { "off" })
+                .after(if on { "on" } else { "off" }),
+        )
+        .await;
+    }
+
+    Ok(String::from(reply))
+}
+
+// !m notify <on|off>
+#[command]
+pub async fn notify(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let db_pool = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<DBConnectionContainer>()
+            .expect("Expected DBConnection in TypeMap.")
+            .clone()
+    };
+
+    let guild_id = msg
+        .guild_id
+        .ok_or_else(|| Error::UserInput(String::from("Pings can only be changed in a server.")))?;
+
+    let on = match args.single::<String>().map(|arg| arg.to_lowercase()) {
+        Ok(arg) if arg == "on" => true,
+        Ok(arg) if arg == "off" => false,
+        _ => return Err(Error::UserInput(String::from("Usage: `!m notify <on|off>`")).into()),
+    };
+
+    let guild_settings = settings::load(&db_pool, guild_id)?;
+    let reply = set_notify(ctx, &db_pool, &guild_settings, msg.author.id, on).await?;
+    msg.reply(&ctx.http, reply).await?;
+
+    Ok(())
+}
@@ -0,0 +1,101 @@
+use serenity::framework::standard::{
+    macros::{check, command},
+    Args, CommandOptions, CommandResult, Reason,
+};
+use serenity::model::prelude::*;
+use serenity::prelude::*;
+
+use crate::audit::{self, Entry};
+use crate::error::Error;
+use crate::permissions::{self, Level};
+use crate::utils::require_guild;
+use crate::DBConnectionContainer;
+
+// Attached to the movie group, checks the command against the guild's permission table
+#[check]
+#[name = "MoviePermission"]
+pub async fn movie_permission_check(
+    ctx: &Context,
+    msg: &Message,
+    _: &mut Args,
+    options: &CommandOptions,
+) -> Result<(), Reason> {
+    let db_pool = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<DBConnectionContainer>()
+            .expect("Expected DBConnection in TypeMap.")
+            .clone()
+    };
+
+    let command = options.names.first().copied().unwrap_or_default();
+
+    match permissions::authorize(ctx, &db_pool, msg.guild_id, msg.author.id, command).await {
+        Ok(()) => Ok(()),
+        Err(Error::Denied(why)) => Err(Reason::User(why)),
+        Err(why) => Err(Reason::UserAndLog {
+            user: why.reply(),
+            log: format!("Permission check for {} failed: {}", command, why),
+        }),
+    }
+}
+
+// The permission table as a message
+pub fn describe(table: &[(&str, Level)]) -> String {
+    let everyone: Vec<&str> = table
+        .iter()
+        .filter(|(_, level)| *level == Level::Everyone)
+        .map(|(name, _)| *name)
+        .collect();
+    let admin: Vec<&str> = table
+        .iter()
+        .filter(|(_, level)| *level == Level::Admin)
+        .map(|(name, _)| *name)
+        .collect();
+
+    format!(
+        "Everyone: {}\nMovie admins: {}",
+        if everyone.is_empty() { String::from("nothing") } else { everyone.join(", ") },
+        if admin.is_empty() { String::from("nothing") } else { admin.join(", ") },
+    )
+}
+
+// !m permissions [command <everyone|admin>]
+#[command]
+#[required_permissions("MANAGE_GUILD")]
+pub async fn permissions(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let db_pool = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<DBConnectionContainer>()
+            .expect("Expected DBConnection in TypeMap.")
+            .clone()
+    };
+
+    let guild_id = require_guild(msg.guild_id)?;
+
+    if !args.is_empty() {
+        let usage = || Error::UserInput(String::from("Usage: `!m permissions <command> <everyone|admin>`"));
+        let command = args.single::<String>().map_err(|_| usage())?.to_lowercase();
+        let level = args
+            .single::<String>()
+            .map_err(|_| usage())?
+            .parse::<Level>()
+            .map_err(Error::UserInput)?;
+
+        let before = permissions::table(&db_pool, guild_id)?
+            .into_iter()
+            .find(|(name, _)| *name == command)
+            .map(|(_, level)| level.to_string());
+        permissions::set_level(&db_pool, guild_id, &command, level)?;
+
+        let mut entry = Entry::new(msg.author.id, "permissions", command.as_str()).after(level.to_string());
+        entry.before = before;
+        audit::record(ctx, &db_pool, guild_id, entry).await;
+    }
+
+    let table = permissions::table(&db_pool, guild_id)?;
+    msg.channel_id.say(&ctx.http, describe(&table)).await?;
+
+    Ok(())
+}
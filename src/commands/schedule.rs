@@ -0,0 +1,106 @@
+use serenity::framework::standard::{macros::command, Args, CommandResult};
+use serenity::model::prelude::*;
+use serenity::prelude::*;
+
+use chrono::Utc;
+use tracing::info;
+
+use crate::audit::{self, Entry};
+use crate::error::Error;
+use crate::permissions;
+use crate::timed::schedule::{self as movie_schedule, Schedule};
+use crate::utils::require_guild;
+use crate::{ConfigContainer, DBConnectionContainer};
+
+#[command]
+pub async fn schedule(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let db_pool = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<DBConnectionContainer>()
+            .expect("Expected DBConnection in TypeMap.")
+            .clone()
+    };
+    let config = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<ConfigContainer>()
+            .expect("Expected Config in TypeMap.")
+            .clone()
+    };
+
+    let guild_id = require_guild(msg.guild_id)?;
+    let cur_schedule = movie_schedule::load(&db_pool, guild_id, &config.schedule);
+
+    if args.is_empty() {
+        let next_selection = cur_schedule
+            .next_after(Utc::now())
+            .with_timezone(&cur_schedule.timezone);
+        msg.channel_id
+            .say(
+                &ctx.http,
+                format!(
+                    "Movie selection runs {}. Next selection: {}",
+                    cur_schedule,
+                    next_selection.format("%A %B %e %H:%M %Z")
+                ),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    // !m schedule <weekly|biweekly|monthly> <day> <HH:MM> [time zone]
+    permissions::authorize(ctx, &db_pool, Some(guild_id), msg.author.id, "setschedule").await?;
+
+    let usage = || Error::UserInput(String::from("Usage: `!m schedule <weekly|biweekly|monthly> <day> <HH:MM> [time zone]`"));
+    let cadence = args.single::<String>().map_err(|_| usage())?;
+    let day = args.single::<String>().map_err(|_| usage())?;
+    let time = args.single::<String>().map_err(|_| usage())?;
+    // Keep the current time zone unless a new one is supplied
+    let timezone = match args.single::<String>() {
+        Ok(timezone) => timezone,
+        Err(_) => String::from(cur_schedule.timezone.name()),
+    };
+
+    // Anchor biweekly schedules to the week they were set in
+    let anchor = Utc::now()
+        .with_timezone(&cur_schedule.timezone)
+        .date()
+        .naive_local();
+
+    match Schedule::parse(&day, &time, &timezone, &cadence, anchor) {
+        Ok(new_schedule) => {
+            movie_schedule::save(&db_pool, guild_id, &new_schedule)?;
+            info!("{} changed the movie schedule to {}", msg.author.name, new_schedule);
+            audit::record(
+                ctx,
+                &db_pool,
+                guild_id,
+                Entry::new(msg.author.id, "schedule", "movie schedule")
+                    .before(cur_schedule.to_string())
+                    .after(new_schedule.to_string()),
+            )
+            .await;
+
+            let next_selection = new_schedule
+                .next_after(Utc::now())
+                .with_timezone(&new_schedule.timezone);
+            msg.channel_id
+                .say(
+                    &ctx.http,
+                    format!(
+                        "Movie selection now runs {}. Next selection: {}",
+                        new_schedule,
+                        next_selection.format("%A %B %e %H:%M %Z")
+                    ),
+                )
+                .await?;
+        }
+        Err(why) => {
+            msg.reply(&ctx.http, format!("Could not update schedule: {}", why))
+                .await?;
+        }
+    }
+
+    Ok(())
+}
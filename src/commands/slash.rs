@@ -0,0 +1,969 @@
+use serenity::builder::{CreateApplicationCommandOption, CreateEmbed};
+use serenity::model::id::GuildId;
+use serenity::model::interactions::application_command::{
+    ApplicationCommandInteraction, ApplicationCommandInteractionDataOption,
+    ApplicationCommandInteractionDataOptionValue, ApplicationCommandOptionType,
+};
+use serenity::model::interactions::autocomplete::AutocompleteInteraction;
+use serenity::model::interactions::{
+    Interaction, InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+};
+use serenity::prelude::*;
+
+use chrono::Utc;
+use diesel::result::Error::NotFound;
+use tracing::{error, info};
+
+use kekw_db::{periods, submissions};
+
+use crate::audit::{self, Entry};
+use crate::commands::audit::audit_fields;
+use crate::commands::carryover::set_member_carry_over;
+use crate::commands::config::{
+    self as guild_config, parse_carry_over, parse_weighting, set_admin, set_carry_over, set_channel, set_log, set_role,
+    set_weighting,
+};
+use crate::commands::movie::{cast_ballot, history_fields, period_fields, verify_message};
+use crate::commands::notify::set_notify;
+use crate::commands::permissions::describe as describe_permissions;
+use crate::permissions::{self, Level};
+use crate::timed::{jobs, schedule::{self as movie_schedule, Schedule}};
+use crate::availability::{self, Availability};
+use crate::error::Error;
+use crate::metadata::{MovieDetails, SearchMatch};
+use crate::utils::require_guild;
+use crate::backend::{KekStore, Live};
+use crate::{
+    links, movie_info, movie_night, omdb_cache, settings, ConfigContainer, DBConnectionContainer, MetadataContainer,
+};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Sync + Send>>;
+
+// These look up movies or every past roll, which can take longer than the 3 seconds Discord waits for a response
+const DEFERRED: &[&str] = &["submit", "getsubs", "info", "history"];
+
+// Discord allows at most 25 choices
+const AUTOCOMPLETE_CHOICES: usize = 25;
+
+// Shorter titles match too many movies to be worth suggesting
+const AUTOCOMPLETE_MIN_CHARS: usize = 3;
+
+// How a slash command should be answered
+enum Reply {
+    Public(String),
+    // Only shown to the user who ran the command
+    Ephemeral(String),
+    Embed(String, Vec<(String, String)>),
+    Movie(MovieDetails, Option<Availability>),
+    // The command already responded itself
+    Done,
+}
+
+fn subcommand<'a>(
+    option: &'a mut CreateApplicationCommandOption,
+    name: &str,
+    description: &str,
+) -> &'a mut CreateApplicationCommandOption {
+    option
+        .name(name)
+        .description(description)
+        .kind(ApplicationCommandOptionType::SubCommand)
+}
+
+// Register the /movie command and its subcommands in a guild
+pub async fn register(ctx: &Context, guild_id: GuildId) -> Result<()> {
+    guild_id
+        .set_application_commands(&ctx.http, |commands| {
+            commands.create_application_command(|command| {
+                command.name("movie").description("Movie Night!");
+                command.create_option(|o| {
+                    subcommand(o, "submit", "Submit a movie to Movie Night").create_sub_option(|so| {
+                        so.name("title")
+                            .description("The movie to submit")
+                            .kind(ApplicationCommandOptionType::String)
+                            .required(true)
+                            .set_autocomplete(true)
+                    })
+                });
+                command.create_option(|o| subcommand(o, "getsubs", "Show the current submissions"));
+                command.create_option(|o| {
+                    subcommand(o, "deletesub", "Delete a user's submission").create_sub_option(|so| {
+                        so.name("user")
+                            .description("Whose submission to delete")
+                            .kind(ApplicationCommandOptionType::User)
+                            .required(true)
+                    })
+                });
+                command.create_option(|o| subcommand(o, "roll", "End the submission period and start voting"));
+                command.create_option(|o| {
+                    subcommand(o, "vote", "Rank the movies being voted on").create_sub_option(|so| {
+                        so.name("ranking")
+                            .description("Movie numbers, favourite first. For example 2 1 3")
+                            .kind(ApplicationCommandOptionType::String)
+                            .required(true)
+                    })
+                });
+                command.create_option(|o| subcommand(o, "startperiod", "Start a new submission period"));
+                command.create_option(|o| subcommand(o, "endperiod", "End the submission period without a roll"));
+                command.create_option(|o| subcommand(o, "reopenperiod", "Reopen the last submission period"));
+                command.create_option(|o| subcommand(o, "listperiods", "List every submission period"));
+                command.create_option(|o| {
+                    subcommand(o, "history", "Show the recent winners").create_sub_option(|so| {
+                        so.name("count")
+                            .description("How many weeks to show")
+                            .kind(ApplicationCommandOptionType::Integer)
+                            .required(false)
+                    })
+                });
+                command.create_option(|o| {
+                    subcommand(o, "verifyroll", "Check a roll against its seed").create_sub_option(|so| {
+                        so.name("period")
+                            .description("The period number from listperiods")
+                            .kind(ApplicationCommandOptionType::Integer)
+                            .required(true)
+                    })
+                });
+                command.create_option(|o| {
+                    subcommand(o, "info", "Show details about a movie").create_sub_option(|so| {
+                        so.name("title")
+                            .description("A title, IMDb id or link")
+                            .kind(ApplicationCommandOptionType::String)
+                            .required(true)
+                            .set_autocomplete(true)
+                    })
+                });
+                command.create_option(|o| {
+                    subcommand(o, "notify", "Turn movie night pings on or off").create_sub_option(|so| {
+                        so.name("pings")
+                            .description("Whether to get pinged")
+                            .kind(ApplicationCommandOptionType::String)
+                            .add_string_choice("on", "on")
+                            .add_string_choice("off", "off")
+                            .required(true)
+                    })
+                });
+                command.create_option(|o| {
+                    subcommand(o, "carryover", "Turn carrying over your unpicked submissions on or off").create_sub_option(
+                        |so| {
+                            so.name("submissions")
+                                .description("Whether to carry them over")
+                                .kind(ApplicationCommandOptionType::String)
+                                .add_string_choice("on", "on")
+                                .add_string_choice("off", "off")
+                                .required(true)
+                        },
+                    )
+                });
+                command.create_option(|o| {
+                    subcommand(o, "schedule", "Show or change when movie selection happens")
+                        .create_sub_option(|so| {
+                            so.name("cadence")
+                                .description("How often movie night happens")
+                                .kind(ApplicationCommandOptionType::String)
+                                .add_string_choice("weekly", "weekly")
+                                .add_string_choice("biweekly", "biweekly")
+                                .add_string_choice("monthly", "monthly")
+                                .required(false)
+                        })
+                        .create_sub_option(|so| {
+                            so.name("day")
+                                .description("Day of the week, for example fri")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(false)
+                        })
+                        .create_sub_option(|so| {
+                            so.name("time")
+                                .description("Time in HH:MM")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(false)
+                        })
+                        .create_sub_option(|so| {
+                            so.name("timezone")
+                                .description("Time zone, for example US/Eastern")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(false)
+                        })
+                });
+                command.create_option(|o| {
+                    subcommand(o, "config", "Show or change where movie night happens")
+                        .create_sub_option(|so| {
+                            so.name("channel")
+                                .description("Channel for movie night announcements")
+                                .kind(ApplicationCommandOptionType::Channel)
+                                .required(false)
+                        })
+                        .create_sub_option(|so| {
+                            so.name("role")
+                                .description("Role that gets pinged for movie night")
+                                .kind(ApplicationCommandOptionType::Role)
+                                .required(false)
+                        })
+                        .create_sub_option(|so| {
+                            so.name("admin")
+                                .description("Role that can run the admin movie commands")
+                                .kind(ApplicationCommandOptionType::Role)
+                                .required(false)
+                        })
+                        .create_sub_option(|so| {
+                            so.name("log")
+                                .description("Channel that changes to movie night are logged in")
+                                .kind(ApplicationCommandOptionType::Channel)
+                                .required(false)
+                        })
+                        .create_sub_option(|so| {
+                            so.name("weighting")
+                                .description("Periods to look back when favouring submitters who weren't picked, or off")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(false)
+                        })
+                        .create_sub_option(|so| {
+                            so.name("carryover")
+                                .description("Most periods an unpicked submission is carried into, or off")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(false)
+                        })
+                });
+                command.create_option(|o| {
+                    subcommand(o, "audit", "Show recent changes to movie night").create_sub_option(|so| {
+                        so.name("count")
+                            .description("How many changes to show")
+                            .kind(ApplicationCommandOptionType::Integer)
+                            .required(false)
+                    })
+                });
+                command.create_option(|o| {
+                    subcommand(o, "permissions", "Show or change who can run each command")
+                        .create_sub_option(|so| {
+                            so.name("command")
+                                .description("The command to change, for example roll")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(false)
+                        })
+                        .create_sub_option(|so| {
+                            so.name("level")
+                                .description("Who can run it")
+                                .kind(ApplicationCommandOptionType::String)
+                                .add_string_choice("everyone", "everyone")
+                                .add_string_choice("admin", "admin")
+                                .required(false)
+                        })
+                });
+                command
+            })
+        })
+        .await?;
+
+    info!("Registered slash commands in guild {}", guild_id);
+
+    Ok(())
+}
+
+pub async fn handle_interaction(ctx: &Context, interaction: Interaction) {
+    match interaction {
+        Interaction::ApplicationCommand(command) => {
+            if command.data.name != "movie" {
+                return;
+            }
+
+            let deferred = command
+                .data
+                .options
+                .first()
+                .map_or(false, |subcommand| DEFERRED.contains(&subcommand.name.as_str()));
+            if deferred {
+                let deferral = command
+                    .create_interaction_response(&ctx.http, |response| {
+                        response.kind(InteractionResponseType::DeferredChannelMessageWithSource)
+                    })
+                    .await;
+                if let Err(why) = deferral {
+                    error!("Error deferring slash command: {:?}", why);
+                    return;
+                }
+            }
+
+            let reply = match run_subcommand(ctx, &command).await {
+                Ok(reply) => reply,
+                Err(why) => {
+                    let why = Error::from_boxed(why);
+                    error!("Slash command failed: {}", why);
+                    Reply::Ephemeral(why.reply())
+                }
+            };
+
+            let responded = if deferred {
+                respond_deferred(ctx, &command, reply).await
+            } else {
+                respond(ctx, &command, reply).await
+            };
+            if let Err(why) = responded {
+                error!("Error responding to slash command: {:?}", why);
+            }
+        }
+        Interaction::Autocomplete(autocomplete) => {
+            if let Err(why) = autocomplete_title(ctx, &autocomplete).await {
+                error!("Error responding to autocomplete: {:?}", why);
+            }
+        }
+        _ => (),
+    }
+}
+
+// Report a failure after the command was already answered, Discord rejects a second response
+async fn follow_up_error(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+    why: Box<dyn std::error::Error + Sync + Send>,
+) -> Result<()> {
+    let why = Error::from_boxed(why);
+    error!("Slash command failed: {}", why);
+
+    command
+        .create_followup_message(&ctx.http, |message| {
+            message
+                .content(why.reply())
+                .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+        })
+        .await?;
+
+    Ok(())
+}
+
+async fn respond(ctx: &Context, command: &ApplicationCommandInteraction, reply: Reply) -> Result<()> {
+    if let Reply::Done = reply {
+        return Ok(());
+    }
+
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|data| {
+                    match reply {
+                        Reply::Public(content) => {
+                            data.content(content);
+                        }
+                        Reply::Ephemeral(content) => {
+                            data.content(content)
+                                .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL);
+                        }
+                        Reply::Embed(title, fields) => {
+                            data.create_embed(|e| fields_embed(e, title, fields));
+                        }
+                        Reply::Movie(movie, availability) => {
+                            data.create_embed(|e| movie_embed(e, &movie, &availability));
+                        }
+                        Reply::Done => (),
+                    }
+                    data
+                })
+        })
+        .await?;
+
+    Ok(())
+}
+
+// Answer a command that was deferred by editing the placeholder Discord shows while it runs.
+// The placeholder is public, so ephemeral replies replace it with a follow up only the user sees.
+async fn respond_deferred(ctx: &Context, command: &ApplicationCommandInteraction, reply: Reply) -> Result<()> {
+    match reply {
+        Reply::Done => (),
+        Reply::Ephemeral(content) => {
+            command.delete_original_interaction_response(&ctx.http).await?;
+            command
+                .create_followup_message(&ctx.http, |message| {
+                    message
+                        .content(content)
+                        .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                })
+                .await?;
+        }
+        reply => {
+            command
+                .edit_original_interaction_response(&ctx.http, |response| {
+                    match reply {
+                        Reply::Public(content) => {
+                            response.content(content);
+                        }
+                        Reply::Embed(title, fields) => {
+                            response.create_embed(|e| fields_embed(e, title, fields));
+                        }
+                        Reply::Movie(movie, availability) => {
+                            response.create_embed(|e| movie_embed(e, &movie, &availability));
+                        }
+                        Reply::Ephemeral(_) | Reply::Done => (),
+                    }
+                    response
+                })
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+fn fields_embed(e: &mut CreateEmbed, title: String, fields: Vec<(String, String)>) -> &mut CreateEmbed {
+    e.title(title);
+    for (name, value) in fields {
+        e.field(name, value, false);
+    }
+    e
+}
+
+fn movie_embed<'a>(
+    e: &'a mut CreateEmbed,
+    movie: &MovieDetails,
+    availability: &Option<Availability>,
+) -> &'a mut CreateEmbed {
+    movie_info::movie_embed(e, movie);
+    if let Some(availability) = availability {
+        movie_info::availability_field(e, availability);
+    }
+    e
+}
+
+fn find_option<'a>(
+    options: &'a [ApplicationCommandInteractionDataOption],
+    name: &str,
+) -> Option<&'a ApplicationCommandInteractionDataOption> {
+    options.iter().find(|option| option.name == name)
+}
+
+fn string_option(options: &[ApplicationCommandInteractionDataOption], name: &str) -> Option<String> {
+    find_option(options, name)
+        .and_then(|option| option.value.as_ref())
+        .and_then(|value| value.as_str())
+        .map(String::from)
+}
+
+// Same as the MANAGE_GUILD requirement on the prefix commands
+fn can_manage(command: &ApplicationCommandInteraction) -> bool {
+    command
+        .member
+        .as_ref()
+        .and_then(|member| member.permissions)
+        .map_or(false, |permissions| permissions.manage_guild())
+}
+
+async fn run_subcommand(ctx: &Context, command: &ApplicationCommandInteraction) -> Result<Reply> {
+    let db_pool = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<DBConnectionContainer>()
+            .expect("Expected DBConnection in TypeMap.")
+            .clone()
+    };
+    let config = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<ConfigContainer>()
+            .expect("Expected Config in TypeMap.")
+            .clone()
+    };
+    let metadata = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<MetadataContainer>()
+            .expect("Expected Metadata in TypeMap.")
+            .clone()
+    };
+
+    let subcommand = match command.data.options.first() {
+        Some(subcommand) => subcommand,
+        None => return Ok(Reply::Ephemeral(String::from("Unknown command."))),
+    };
+    let options = &subcommand.options;
+    let author = &command.user;
+    let guild_id = require_guild(command.guild_id)?;
+
+    // Same permission table as the prefix commands
+    permissions::authorize(ctx, &db_pool, Some(guild_id), author.id, &subcommand.name).await?;
+
+    match subcommand.name.as_str() {
+        "submit" => {
+            let query = string_option(options, "title").unwrap_or_default();
+            if query.trim().is_empty() {
+                return Ok(Reply::Ephemeral(String::from("No movie supplied.")));
+            }
+
+            let cur_period = match periods::get_most_recent_period(&db_pool, &guild_id.to_string()) {
+                Ok(cur_period) => cur_period,
+                Err(NotFound) => {
+                    return Ok(Reply::Ephemeral(String::from(
+                        "No current movie submission periods active.",
+                    )))
+                }
+                Err(why) => return Err(why.into()),
+            };
+
+            // Autocomplete fills in the IMDb id, otherwise take the linked movie or the best match
+            let (title, imdb_id) = if let Some(link) = links::find_movie_link(&query) {
+                match links::resolve(&db_pool, &config, metadata.as_ref(), link).await? {
+                    Some(movie) => movie,
+                    None => return Ok(Reply::Ephemeral(format!("Couldn't find the movie linked by {}.", query))),
+                }
+            } else if links::is_imdb_id(&query) {
+                match omdb_cache::query_by_id(&db_pool, &config, metadata.as_ref(), &query).await? {
+                    Some(movie) => (movie.title, movie.imdb_id),
+                    None => return Ok(Reply::Ephemeral(format!("Couldn't find {}.", query))),
+                }
+            } else {
+                match omdb_cache::search(&db_pool, &config, metadata.as_ref(), &query, None).await?.into_iter().next() {
+                    Some(movie) => (movie.title, movie.imdb_id),
+                    None => {
+                        return Ok(Reply::Ephemeral(format!(
+                            "Couldn't find {}, check the spelling and try again.",
+                            query
+                        )))
+                    }
+                }
+            };
+
+            info!("{}:{} submitted movie {}", author, author.name, title);
+
+            // There's no reaction confirmation here, running the command again replaces the submission
+            let live = Live::new(ctx, &db_pool, &config, &metadata);
+            match movie_night::submit(live.backend().store, &cur_period, author.id, &title, &imdb_id)? {
+                None => {
+                    audit::record(
+                        ctx,
+                        &db_pool,
+                        guild_id,
+                        Entry::new(author.id, "submit", format!("submission for period {}", cur_period.id)).after(title.as_str()),
+                    )
+                    .await;
+                    Ok(Reply::Public(format!("You've submitted the movie: {}", title)))
+                }
+                Some(previous) => {
+                    let entry = Entry::new(author.id, "submit", format!("submission {}", previous.id))
+                        .before(previous.title.as_str())
+                        .after(title.as_str());
+                    audit::record(ctx, &db_pool, guild_id, entry).await;
+                    Ok(Reply::Public(format!("Updated your submission from {} to {}", previous.title, title)))
+                }
+            }
+        }
+        "getsubs" => {
+            let cur_period = match periods::get_most_recent_period(&db_pool, &guild_id.to_string()) {
+                Ok(cur_period) => cur_period,
+                Err(NotFound) => {
+                    return Ok(Reply::Ephemeral(String::from(
+                        "No current movie submission periods active.",
+                    )))
+                }
+                Err(why) => return Err(why.into()),
+            };
+
+            let live = Live::new(ctx, &db_pool, &config, &metadata);
+            let guild_settings = settings::load(&db_pool, guild_id)?;
+            let fields = movie_night::submission_fields(live.backend(), &guild_settings, &cur_period).await?;
+
+            Ok(Reply::Embed(String::from("Current Movie Submissions"), fields))
+        }
+        "deletesub" => {
+            let user = match find_option(options, "user").and_then(|option| option.resolved.as_ref()) {
+                Some(ApplicationCommandInteractionDataOptionValue::User(user, _)) => user,
+                _ => return Ok(Reply::Ephemeral(String::from("Please provide the user you would like to delete the submission for!"))),
+            };
+
+            let cur_period = match periods::get_most_recent_period(&db_pool, &guild_id.to_string()) {
+                Ok(cur_period) => cur_period,
+                Err(NotFound) => {
+                    return Ok(Reply::Ephemeral(String::from(
+                        "No current movie submission period exists.",
+                    )))
+                }
+                Err(why) => return Err(why.into()),
+            };
+
+            match submissions::get_submission_by_period_and_user(&db_pool, &cur_period, user.id.to_string()) {
+                Ok(sub) => {
+                    submissions::delete_moviesub(&db_pool, &sub);
+                    audit::record(
+                        ctx,
+                        &db_pool,
+                        guild_id,
+                        Entry::new(author.id, "deletesub", format!("{}'s submission {}", user.name, sub.id))
+                            .before(sub.title.as_str()),
+                    )
+                    .await;
+                    Ok(Reply::Public(format!("Deleted submission {} for {}.", sub.title, user.name)))
+                }
+                Err(NotFound) => Ok(Reply::Ephemeral(format!("Submission does not exist for {}.", user.name))),
+                Err(why) => Err(why.into()),
+            }
+        }
+        "roll" => {
+            let cur_period = match periods::get_most_recent_period(&db_pool, &guild_id.to_string()) {
+                Ok(cur_period) => cur_period,
+                Err(NotFound) => {
+                    return Ok(Reply::Ephemeral(String::from(
+                        "No current movie submission periods active.",
+                    )))
+                }
+                Err(why) => return Err(why.into()),
+            };
+
+            // Rolling takes longer than Discord waits for a response, so answer first
+            let guild_settings = settings::load(&db_pool, guild_id)?;
+
+            respond(ctx, command, Reply::Public(String::from("Starting roll"))).await?;
+            let live = Live::new(ctx, &db_pool, &config, &metadata);
+            match jobs::roll_period(live.backend(), &config, &guild_settings, command.channel_id).await {
+                Ok(Some(_)) => {
+                    audit::record(
+                        ctx,
+                        &db_pool,
+                        guild_id,
+                        Entry::new(author.id, "roll", format!("period {}", cur_period.id)).before("open").after("voting"),
+                    )
+                    .await;
+                }
+                Ok(None) => (),
+                Err(why) => follow_up_error(ctx, command, why).await?,
+            }
+
+            Ok(Reply::Done)
+        }
+        "vote" => {
+            let positions: Vec<usize> = string_option(options, "ranking")
+                .unwrap_or_default()
+                .split_whitespace()
+                .map(|position| position.parse::<usize>().unwrap_or(0))
+                .collect();
+
+            // Ballots are always ephemeral so they stay secret, and so are rejections
+            let ballot_str = cast_ballot(&db_pool, &config, guild_id, &author.id.to_string(), &positions)?;
+            // Only that a ballot was cast, the ranking stays secret
+            audit::record(ctx, &db_pool, guild_id, Entry::new(author.id, "vote", "ranked ballot")).await;
+            Ok(Reply::Ephemeral(ballot_str))
+        }
+        "startperiod" => {
+            let guild_settings = settings::load(&db_pool, guild_id)?;
+
+            // The announcement pings the notify role, so the reply itself stays private
+            let live = Live::new(ctx, &db_pool, &config, &metadata);
+            if jobs::open_period(live.backend(), &config, &guild_settings, command.channel_id).await? {
+                audit::record(ctx, &db_pool, guild_id, Entry::new(author.id, "startperiod", "submission period").after("open")).await;
+                Ok(Reply::Ephemeral(String::from("Started new submission period!")))
+            } else {
+                Ok(Reply::Ephemeral(String::from(
+                    "A submission period has already started, run `/movie roll` to finish the current submission period.",
+                )))
+            }
+        }
+        "endperiod" => match periods::get_most_recent_period(&db_pool, &guild_id.to_string()) {
+            Ok(cur_period) => {
+                let target = format!("period {}", cur_period.id);
+                periods::end_period(&db_pool, cur_period)?;
+                audit::record(
+                    ctx,
+                    &db_pool,
+                    guild_id,
+                    Entry::new(author.id, "endperiod", target).before("open").after("closed"),
+                )
+                .await;
+                Ok(Reply::Public(String::from("Ended current movie submission without roll!")))
+            }
+            Err(NotFound) => Ok(Reply::Ephemeral(String::from(
+                "No current movie submission period exists.",
+            ))),
+            Err(why) => Err(why.into()),
+        },
+        "reopenperiod" => match periods::get_most_recent_closed_period(&db_pool, &guild_id.to_string()) {
+            Ok(cur_period) => {
+                let target = format!("period {}", cur_period.id);
+                periods::reopen_period(&db_pool, cur_period)?;
+                audit::record(
+                    ctx,
+                    &db_pool,
+                    guild_id,
+                    Entry::new(author.id, "reopenperiod", target).before("closed").after("open"),
+                )
+                .await;
+                Ok(Reply::Public(String::from("Reopened last submission period!")))
+            }
+            Err(NotFound) => Ok(Reply::Ephemeral(String::from(
+                "Could not find a recently closed submission period.",
+            ))),
+            Err(why) => Err(why.into()),
+        },
+        "listperiods" => {
+            let fields = period_fields(&db_pool, guild_id)?;
+            if fields.is_empty() {
+                return Ok(Reply::Ephemeral(String::from(
+                    "There haven't been any submission periods yet.",
+                )));
+            }
+
+            Ok(Reply::Embed(String::from("Submission Periods"), fields))
+        }
+        "history" => {
+            let count = find_option(options, "count")
+                .and_then(|option| option.value.as_ref())
+                .and_then(|value| value.as_u64())
+                .unwrap_or(10) as usize;
+
+            let fields = history_fields(ctx, &db_pool, guild_id, count).await?;
+            if fields.is_empty() {
+                return Ok(Reply::Ephemeral(String::from("No movies have been picked yet.")));
+            }
+
+            Ok(Reply::Embed(String::from("Movie Night History"), fields))
+        }
+        "verifyroll" => {
+            let period_id = find_option(options, "period")
+                .and_then(|option| option.value.as_ref())
+                .and_then(|value| value.as_i64())
+                .unwrap_or(0) as i32;
+
+            let store = KekStore::new(db_pool.clone());
+            Ok(Reply::Public(verify_message(&store, guild_id, period_id)?))
+        }
+        "info" => {
+            let query = string_option(options, "title").unwrap_or_default();
+
+            match movie_info::find_movie(&db_pool, &config, metadata.as_ref(), &query).await? {
+                Some(movie) => {
+                    let availability = availability::lookup(&db_pool, &config, &movie.imdb_id).await;
+                    Ok(Reply::Movie(movie, availability))
+                }
+                None => Ok(Reply::Ephemeral(format!("Couldn't find {}.", query))),
+            }
+        }
+        "notify" => {
+            let on = string_option(options, "pings").as_deref() == Some("on");
+            let guild_settings = settings::load(&db_pool, guild_id)?;
+
+            Ok(Reply::Ephemeral(set_notify(ctx, &db_pool, &guild_settings, author.id, on).await?))
+        }
+        "carryover" => {
+            let on = string_option(options, "submissions").as_deref() == Some("on");
+            let guild_settings = settings::load(&db_pool, guild_id)?;
+
+            Ok(Reply::Ephemeral(
+                set_member_carry_over(ctx, &db_pool, &guild_settings, author.id, on).await?,
+            ))
+        }
+        "schedule" => {
+            let cur_schedule = movie_schedule::load(&db_pool, guild_id, &config.schedule);
+
+            let new_schedule = match string_option(options, "cadence") {
+                None => cur_schedule,
+                Some(cadence) => {
+                    permissions::authorize(ctx, &db_pool, Some(guild_id), author.id, "setschedule").await?;
+
+                    let (day, time) = match (string_option(options, "day"), string_option(options, "time")) {
+                        (Some(day), Some(time)) => (day, time),
+                        _ => return Ok(Reply::Ephemeral(String::from("Please supply both a day and a time."))),
+                    };
+                    let timezone = string_option(options, "timezone")
+                        .unwrap_or(String::from(cur_schedule.timezone.name()));
+                    let anchor = Utc::now()
+                        .with_timezone(&cur_schedule.timezone)
+                        .date()
+                        .naive_local();
+
+                    match Schedule::parse(&day, &time, &timezone, &cadence, anchor) {
+                        Ok(new_schedule) => {
+                            movie_schedule::save(&db_pool, guild_id, &new_schedule)?;
+                            info!("{} changed the movie schedule to {}", author.name, new_schedule);
+                            audit::record(
+                                ctx,
+                                &db_pool,
+                                guild_id,
+                                Entry::new(author.id, "schedule", "movie schedule")
+                                    .before(cur_schedule.to_string())
+                                    .after(new_schedule.to_string()),
+                            )
+                            .await;
+                            new_schedule
+                        }
+                        Err(why) => return Ok(Reply::Ephemeral(format!("Could not update schedule: {}", why))),
+                    }
+                }
+            };
+
+            let next_selection = new_schedule
+                .next_after(Utc::now())
+                .with_timezone(&new_schedule.timezone);
+            Ok(Reply::Public(format!(
+                "Movie selection runs {}. Next selection: {}",
+                new_schedule,
+                next_selection.format("%A %B %e %H:%M %Z")
+            )))
+        }
+        "config" => {
+            if !can_manage(command) {
+                return Ok(Reply::Ephemeral(String::from("You need Manage Server to change movie night settings.")));
+            }
+
+            let channel = match find_option(options, "channel").and_then(|option| option.resolved.as_ref()) {
+                Some(ApplicationCommandInteractionDataOptionValue::Channel(channel)) => Some(channel.id),
+                _ => None,
+            };
+            let role = match find_option(options, "role").and_then(|option| option.resolved.as_ref()) {
+                Some(ApplicationCommandInteractionDataOptionValue::Role(role)) => Some(role.id),
+                _ => None,
+            };
+            let admin = match find_option(options, "admin").and_then(|option| option.resolved.as_ref()) {
+                Some(ApplicationCommandInteractionDataOptionValue::Role(role)) => Some(role.id),
+                _ => None,
+            };
+            let log = match find_option(options, "log").and_then(|option| option.resolved.as_ref()) {
+                Some(ApplicationCommandInteractionDataOptionValue::Channel(channel)) => Some(channel.id),
+                _ => None,
+            };
+            let weighting = match string_option(options, "weighting") {
+                Some(weighting) => Some(parse_weighting(&weighting)?),
+                None => None,
+            };
+            let carry_over = match string_option(options, "carryover") {
+                Some(carry_over) => Some(parse_carry_over(&carry_over)?),
+                None => None,
+            };
+
+            let before = settings::load(&db_pool, guild_id)?;
+
+            let mut replies: Vec<String> = Vec::new();
+            if let Some(channel_id) = channel {
+                replies.push(set_channel(&db_pool, guild_id, channel_id)?);
+            }
+            if let Some(role_id) = role {
+                replies.push(set_role(&db_pool, guild_id, role_id)?);
+            }
+            if let Some(role_id) = admin {
+                replies.push(set_admin(&db_pool, guild_id, role_id)?);
+            }
+            if let Some(channel_id) = log {
+                replies.push(set_log(&db_pool, guild_id, channel_id)?);
+            }
+            if let Some(periods) = weighting {
+                replies.push(set_weighting(&db_pool, guild_id, periods)?);
+            }
+            if let Some(count) = carry_over {
+                replies.push(set_carry_over(&db_pool, guild_id, count)?);
+            }
+
+            if replies.is_empty() {
+                return Ok(Reply::Ephemeral(guild_config::describe(&before)));
+            }
+
+            let after = settings::load(&db_pool, guild_id)?;
+            let changed = [
+                ("channel", channel.is_some()),
+                ("role", role.is_some()),
+                ("admin", admin.is_some()),
+                ("log", log.is_some()),
+                ("weighting", weighting.is_some()),
+                ("carryover", carry_over.is_some()),
+            ];
+            for (setting, _) in changed.iter().filter(|(_, changed)| *changed) {
+                audit::record(
+                    ctx,
+                    &db_pool,
+                    guild_id,
+                    Entry::new(author.id, "config", *setting)
+                        .before(guild_config::value(&before, setting))
+                        .after(guild_config::value(&after, setting)),
+                )
+                .await;
+            }
+
+            Ok(Reply::Public(replies.join("\n")))
+        }
+        "permissions" => {
+            if !can_manage(command) {
+                return Ok(Reply::Ephemeral(String::from("You need Manage Server to change command permissions.")));
+            }
+
+            match (string_option(options, "command"), string_option(options, "level")) {
+                (Some(name), Some(level)) => {
+                    let name = name.to_lowercase();
+                    let level = level.parse::<Level>().map_err(Error::UserInput)?;
+                    let before = permissions::table(&db_pool, guild_id)?
+                        .into_iter()
+                        .find(|(command_name, _)| *command_name == name)
+                        .map(|(_, level)| level.to_string());
+                    permissions::set_level(&db_pool, guild_id, &name, level)?;
+
+                    let mut entry = Entry::new(author.id, "permissions", name.as_str()).after(level.to_string());
+                    entry.before = before;
+                    audit::record(ctx, &db_pool, guild_id, entry).await;
+                }
+                (None, None) => (),
+                _ => return Ok(Reply::Ephemeral(String::from("Please supply both a command and a level."))),
+            }
+
+            Ok(Reply::Ephemeral(describe_permissions(&permissions::table(&db_pool, guild_id)?)))
+        }
+        "audit" => {
+            let count = find_option(options, "count")
+                .and_then(|option| option.value.as_ref())
+                .and_then(|value| value.as_u64())
+                .unwrap_or(10) as usize;
+
+            let fields = audit_fields(&db_pool, guild_id, count)?;
+            if fields.is_empty() {
+                return Ok(Reply::Ephemeral(String::from("Nothing has been changed yet.")));
+            }
+
+            Ok(Reply::Embed(String::from("Movie Night Audit Log"), fields))
+        }
+        _ => Ok(Reply::Ephemeral(String::from("Unknown command."))),
+    }
+}
+
+// Suggest matches for the title being typed into /movie submit or /movie info
+async fn autocomplete_title(ctx: &Context, autocomplete: &AutocompleteInteraction) -> Result<()> {
+    let typed = autocomplete
+        .data
+        .options
+        .iter()
+        .flat_map(|subcommand| subcommand.options.iter())
+        .find(|option| option.focused)
+        .and_then(|option| option.value.as_ref())
+        .and_then(|value| value.as_str())
+        .unwrap_or("")
+        .trim()
+        .to_string();
+
+    let db_pool = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<DBConnectionContainer>()
+            .expect("Expected DBConnection in TypeMap.")
+            .clone()
+    };
+    let config = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<ConfigContainer>()
+            .expect("Expected Config in TypeMap.")
+            .clone()
+    };
+
+    let mut choices: Vec<(String, String)> = Vec::new();
+
+    // Only searches that were already made are suggested. Every keystroke is a new title,
+    // so searching the provider here would look up the details of each match over and over.
+    if typed.chars().count() >= AUTOCOMPLETE_MIN_CHARS {
+        let key = omdb_cache::search_key(&typed, None);
+        if let Some(Some(matches)) = omdb_cache::cached::<Vec<SearchMatch>>(&db_pool, &config, &key) {
+            for movie in matches.into_iter().take(AUTOCOMPLETE_CHOICES) {
+                choices.push((movie.label(), movie.imdb_id));
+            }
+        }
+    }
+
+    autocomplete
+        .create_autocomplete_response(&ctx.http, |response| {
+            for (name, value) in choices {
+                response.add_string_choice(name, value);
+            }
+            response
+        })
+        .await?;
+
+    Ok(())
+}
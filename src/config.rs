@@ -0,0 +1,367 @@
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::str::FromStr;
+
+use chrono::{Duration, NaiveDate};
+use reqwest::Url;
+use serenity::model::id::{ChannelId, RoleId};
+
+use crate::availability;
+use crate::metadata;
+use crate::timed::schedule::Schedule;
+use crate::timed::tie_break::TieBreak;
+use crate::voting::{self, VotingMode};
+
+static DEFAULT_CONFIG_PATH: &'static str = "config.toml";
+
+static OMDB_ENDPOINT: &'static str = "http://www.omdbapi.com/";
+static TMDB_ENDPOINT: &'static str = "https://api.themoviedb.org/3/";
+
+// Defaults match the original hardcoded reset (Thursday 18:00 Eastern, weekly)
+static DEFAULT_DAY: &'static str = "Thu";
+static DEFAULT_TIME: &'static str = "18:00";
+static DEFAULT_TIMEZONE: &'static str = "US/Eastern";
+static DEFAULT_CADENCE: &'static str = "weekly";
+
+pub struct VotingConfig {
+    pub mode: VotingMode,
+    // Number of movies to roll
+    pub candidates: usize,
+    // How long voting stays open before the movie is selected
+    pub window: Duration,
+    // Guild emoji names or unicode emoji to vote with, empty for every guild emoji
+    pub emoji: Vec<String>,
+    pub tie_break: TieBreak,
+    pub runoff_minutes: u64,
+    pub host_minutes: u64,
+}
+
+pub struct MetadataConfig {
+    // Tried in order until one answers
+    pub providers: Vec<metadata::ProviderKind>,
+    pub omdb_api_key: Option<String>,
+    pub omdb_base_url: Url,
+    pub tmdb_api_key: Option<String>,
+    pub tmdb_base_url: Url,
+    pub cache_ttl: Duration,
+    pub cache_miss_ttl: Duration,
+}
+
+pub struct AvailabilityConfig {
+    pub provider: availability::ProviderKind,
+    pub region: String,
+    pub ttl: Duration,
+}
+
+pub struct NotifyConfig {
+    // Mention the notify role in announcements
+    pub ping: bool,
+    // How long before the roll to remind people to submit, None for no reminder
+    pub reminder: Option<Duration>,
+}
+
+pub struct Features {
+    // Register the /movie command in every guild
+    pub slash_commands: bool,
+    // Roll and select movies on the schedule
+    pub scheduler: bool,
+    // Open the next submission period as soon as a movie is selected
+    pub auto_start_period: bool,
+}
+
+pub struct Config {
+    pub prefix: String,
+    pub discord_token: String,
+    // Single server setups can configure their channel and role here instead of with
+    // !m config, they are stored for the channel's guild on startup
+    pub movie_channel: Option<ChannelId>,
+    pub notify_role: Option<RoleId>,
+    pub admin_role: Option<RoleId>,
+    // Picks the winner with the host tie break
+    pub host_role: Option<RoleId>,
+    // Used until a schedule is saved with !m schedule
+    pub schedule: Schedule,
+    pub voting: VotingConfig,
+    pub metadata: MetadataConfig,
+    pub availability: AvailabilityConfig,
+    pub notify: NotifyConfig,
+    pub features: Features,
+}
+
+// Settings from the config file, each of which can be overridden by an environment variable
+struct Source {
+    file: toml::Value,
+    // Off for tests, so whatever is set in the environment can't change the outcome
+    use_env: bool,
+    errors: Vec<String>,
+}
+
+impl Source {
+    // Environment first, then the file. Lists are joined with commas.
+    fn get(&self, key: &str, env_key: &str) -> Option<String> {
+        if self.use_env {
+            if let Ok(value) = env::var(env_key) {
+                return Some(value);
+            }
+        }
+
+        let mut value = &self.file;
+        for part in key.split('.') {
+            value = value.get(part)?;
+        }
+
+        match value {
+            toml::Value::String(s) => Some(s.clone()),
+            toml::Value::Array(items) => Some(
+                items
+                    .iter()
+                    .map(|item| match item {
+                        toml::Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    })
+                    .collect::<Vec<String>>()
+                    .join(","),
+            ),
+            other => Some(other.to_string()),
+        }
+    }
+
+    fn parse<T>(&mut self, key: &str, env_key: &str, value: &str) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        match value.trim().parse::<T>() {
+            Ok(value) => Some(value),
+            Err(why) => {
+                self.errors
+                    .push(format!("{} ({}) is invalid: {}", key, env_key, why));
+                None
+            }
+        }
+    }
+
+    fn optional<T>(&mut self, key: &str, env_key: &str) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let value = self.get(key, env_key)?;
+        self.parse(key, env_key, &value)
+    }
+
+    fn or<T>(&mut self, key: &str, env_key: &str, default: T) -> T
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        match self.get(key, env_key) {
+            Some(value) => self.parse(key, env_key, &value).unwrap_or(default),
+            None => default,
+        }
+    }
+
+    fn required<T>(&mut self, key: &str, env_key: &str) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        if self.get(key, env_key).is_none() {
+            self.errors
+                .push(format!("{} ({}) must be set", key, env_key));
+            return None;
+        }
+
+        self.optional(key, env_key)
+    }
+
+    fn list<T>(&mut self, key: &str, env_key: &str) -> Option<Vec<T>>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let value = self.get(key, env_key)?;
+        let mut items = Vec::new();
+        for item in value.split(',').filter(|item| !item.trim().is_empty()) {
+            items.push(self.parse(key, env_key, item)?);
+        }
+
+        Some(items)
+    }
+
+    // A zero or negative duration would make things happen right away or never expire
+    fn positive(&mut self, key: &str, env_key: &str, default: i64) -> i64 {
+        let value = self.or(key, env_key, default);
+        if value <= 0 {
+            self.errors.push(format!("{} ({}) must be positive", key, env_key));
+        }
+
+        value
+    }
+
+    fn hours(&mut self, key: &str, env_key: &str, default: i64) -> Duration {
+        Duration::hours(self.positive(key, env_key, default))
+    }
+}
+
+impl Config {
+    // Read the file from MOVIE_CONFIG (config.toml by default) and apply the environment.
+    // Every problem is reported at once so the bot can be fixed in one go.
+    pub fn load() -> Result<Config, String> {
+        let (path, explicit) = match env::var("MOVIE_CONFIG") {
+            Ok(path) => (path, true),
+            Err(_) => (String::from(DEFAULT_CONFIG_PATH), false),
+        };
+
+        let file = match fs::read_to_string(&path) {
+            Ok(contents) => contents
+                .parse::<toml::Value>()
+                .map_err(|why| format!("Could not parse {}: {}", path, why))?,
+            // Running from the environment alone is fine unless a file was asked for
+            Err(_) if !explicit => toml::Value::Table(toml::value::Table::new()),
+            Err(why) => return Err(format!("Could not read {}: {}", path, why)),
+        };
+
+        Config::from_source(Source {
+            file,
+            use_env: true,
+            errors: Vec::new(),
+        })
+    }
+
+    // Config from just a TOML string, ignoring the environment, for tests
+    #[cfg(test)]
+    pub fn from_toml(contents: &str) -> Result<Config, String> {
+        Config::from_source(Source {
+            file: contents
+                .parse::<toml::Value>()
+                .map_err(|why| format!("Could not parse config: {}", why))?,
+            use_env: false,
+            errors: Vec::new(),
+        })
+    }
+
+    fn from_source(mut source: Source) -> Result<Config, String> {
+        let prefix = source.or("prefix", "MOVIE_PREFIX", String::from("!"));
+        let discord_token = source.required::<String>("discord_token", "DISCORD_TOKEN");
+        let movie_channel = source.optional::<u64>("movie_channel", "MOVIE_CHANNEL");
+        let notify_role = source.optional::<u64>("notify_role", "MOVIE_NOTIFY_ROLE_ID");
+        let admin_role = source.optional::<u64>("admin_role", "MOVIE_ADMIN_ROLE_ID");
+        let host_role = source.optional::<u64>("host_role", "MOVIE_HOST_ROLE_ID");
+
+        let day = source.or("schedule.day", "MOVIE_SCHEDULE_DAY", String::from(DEFAULT_DAY));
+        let time = source.or("schedule.time", "MOVIE_SCHEDULE_TIME", String::from(DEFAULT_TIME));
+        let timezone = source.or("schedule.timezone", "MOVIE_SCHEDULE_TZ", String::from(DEFAULT_TIMEZONE));
+        let cadence = source.or("schedule.cadence", "MOVIE_SCHEDULE_CADENCE", String::from(DEFAULT_CADENCE));
+        let schedule = match Schedule::parse(&day, &time, &timezone, &cadence, NaiveDate::from_ymd(2021, 2, 11)) {
+            Ok(schedule) => Some(schedule),
+            Err(why) => {
+                source.errors.push(format!("schedule is invalid: {}", why));
+                None
+            }
+        };
+
+        let candidates = source.or("voting.candidates", "MOVIE_ROLL_CANDIDATES", voting::MIN_CANDIDATES);
+        if candidates < voting::MIN_CANDIDATES || candidates > voting::MAX_CANDIDATES {
+            source.errors.push(format!(
+                "voting.candidates (MOVIE_ROLL_CANDIDATES) must be between {} and {}",
+                voting::MIN_CANDIDATES,
+                voting::MAX_CANDIDATES
+            ));
+        }
+        let voting = VotingConfig {
+            mode: source.or("voting.mode", "MOVIE_VOTING_MODE", VotingMode::Plurality),
+            candidates,
+            window: source.hours("voting.hours", "MOVIE_VOTING_HOURS", 24),
+            emoji: source.list("voting.emoji", "MOVIE_VOTE_EMOJI").unwrap_or_default(),
+            tie_break: source.or("voting.tie_break", "MOVIE_TIE_BREAK", TieBreak::CoinFlip),
+            runoff_minutes: source.positive("voting.runoff_minutes", "MOVIE_RUNOFF_MINUTES", 60) as u64,
+            host_minutes: source.positive("voting.host_minutes", "MOVIE_HOST_MINUTES", 60) as u64,
+        };
+        if voting.tie_break == TieBreak::Host && host_role.is_none() {
+            source
+                .errors
+                .push(String::from("host_role (MOVIE_HOST_ROLE_ID) must be set for the host tie break"));
+        }
+
+        let metadata = MetadataConfig {
+            providers: source
+                .list("metadata.providers", "MOVIE_METADATA_PROVIDERS")
+                .unwrap_or_else(|| vec![metadata::ProviderKind::Omdb]),
+            omdb_api_key: source.optional("metadata.omdb_api_key", "OMDB_API_KEY"),
+            omdb_base_url: source.or("metadata.omdb_base_url", "OMDB_BASE_URL", Url::parse(OMDB_ENDPOINT).unwrap()),
+            tmdb_api_key: source.optional("metadata.tmdb_api_key", "TMDB_API_KEY"),
+            tmdb_base_url: source.or("metadata.tmdb_base_url", "TMDB_BASE_URL", Url::parse(TMDB_ENDPOINT).unwrap()),
+            cache_ttl: source.hours("metadata.cache_ttl_hours", "MOVIE_CACHE_TTL_HOURS", 24 * 7),
+            cache_miss_ttl: source.hours("metadata.cache_miss_ttl_hours", "MOVIE_CACHE_MISS_TTL_HOURS", 24),
+        };
+        if metadata.providers.is_empty() {
+            source.errors.push(String::from("metadata.providers (MOVIE_METADATA_PROVIDERS) can't be empty"));
+        }
+
+        // Availability comes from TMDB so it is only on by default when there is a key
+        let default_availability = match metadata.tmdb_api_key {
+            Some(_) => availability::ProviderKind::Tmdb,
+            None => availability::ProviderKind::Disabled,
+        };
+        let availability = AvailabilityConfig {
+            provider: source.or("availability.provider", "MOVIE_AVAILABILITY_PROVIDER", default_availability),
+            region: source
+                .or("availability.region", "MOVIE_AVAILABILITY_REGION", String::from("US"))
+                .to_uppercase(),
+            ttl: source.hours("availability.ttl_hours", "MOVIE_AVAILABILITY_TTL_HOURS", 24),
+        };
+
+        let needs_omdb = metadata.providers.contains(&metadata::ProviderKind::Omdb);
+        if needs_omdb && metadata.omdb_api_key.is_none() {
+            source.errors.push(String::from("metadata.omdb_api_key (OMDB_API_KEY) must be set to use OMDb"));
+        }
+        let needs_tmdb = metadata.providers.contains(&metadata::ProviderKind::Tmdb)
+            || availability.provider == availability::ProviderKind::Tmdb;
+        if needs_tmdb && metadata.tmdb_api_key.is_none() {
+            source.errors.push(String::from("metadata.tmdb_api_key (TMDB_API_KEY) must be set to use TMDB"));
+        }
+
+        // Zero turns the reminder off
+        let reminder_minutes = source.or("notify.reminder_minutes", "MOVIE_NOTIFY_REMINDER_MINUTES", 120);
+        if reminder_minutes < 0 {
+            source.errors.push(String::from(
+                "notify.reminder_minutes (MOVIE_NOTIFY_REMINDER_MINUTES) can't be negative",
+            ));
+        }
+        let notify = NotifyConfig {
+            ping: source.or("notify.ping", "MOVIE_NOTIFY_PING", true),
+            reminder: match reminder_minutes {
+                0 => None,
+                minutes => Some(Duration::minutes(minutes)),
+            },
+        };
+
+        let features = Features {
+            slash_commands: source.or("features.slash_commands", "MOVIE_SLASH_COMMANDS", true),
+            scheduler: source.or("features.scheduler", "MOVIE_SCHEDULER", true),
+            auto_start_period: source.or("features.auto_start_period", "MOVIE_AUTO_START_PERIOD", false),
+        };
+
+        if !source.errors.is_empty() {
+            return Err(source.errors.join("\n"));
+        }
+
+        // Everything required is present when there are no errors
+        Ok(Config {
+            prefix,
+            discord_token: discord_token.unwrap(),
+            movie_channel: movie_channel.map(ChannelId),
+            notify_role: notify_role.map(RoleId),
+            admin_role: admin_role.map(RoleId),
+            host_role: host_role.map(RoleId),
+            schedule: schedule.unwrap(),
+            voting,
+            metadata,
+            availability,
+            notify,
+            features,
+        })
+    }
+}
@@ -0,0 +1,121 @@
+use std::fmt;
+
+use diesel::r2d2::PoolError;
+use diesel::result::Error as DieselError;
+
+use crate::omdb::OmdbError;
+
+// Everything a command can fail with, along with what to tell the user
+#[derive(Debug)]
+pub enum Error {
+    // Query failures and pool checkouts
+    Db(Box<dyn std::error::Error + Send + Sync>),
+    Discord(serenity::Error),
+    // OMDb, TMDB and the other lookup providers
+    Metadata(Box<dyn std::error::Error + Send + Sync>),
+    Config(String),
+    // Something the user typed that we can't use, shown to them as is
+    UserInput(String),
+    // The user isn't allowed to run the command, shown to them as is
+    Denied(String),
+    // Anything we don't know how to explain
+    Other(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl Error {
+    // Figure out what kind of error a command returned.
+    // Commands use ? on whatever failed so the concrete type is recovered here.
+    pub fn from_boxed(why: Box<dyn std::error::Error + Send + Sync>) -> Error {
+        let why = match why.downcast::<Error>() {
+            Ok(why) => return *why,
+            Err(why) => why,
+        };
+        let why = match why.downcast::<DieselError>() {
+            Ok(why) => return Error::Db(why),
+            Err(why) => why,
+        };
+        let why = match why.downcast::<PoolError>() {
+            Ok(why) => return Error::Db(why),
+            Err(why) => why,
+        };
+        let why = match why.downcast::<serenity::Error>() {
+            Ok(why) => return Error::Discord(*why),
+            Err(why) => why,
+        };
+        let why = match why.downcast::<OmdbError>() {
+            Ok(why) => return Error::Metadata(why),
+            Err(why) => why,
+        };
+        let why = match why.downcast::<reqwest::Error>() {
+            Ok(why) => return Error::Metadata(why),
+            Err(why) => why,
+        };
+        let why = match why.downcast::<serde_json::Error>() {
+            Ok(why) => return Error::Metadata(why),
+            Err(why) => why,
+        };
+
+        Error::Other(why)
+    }
+
+    // What to say in the channel
+    pub fn reply(&self) -> String {
+        match self {
+            Error::Db(_) => String::from("Couldn't reach the movie database, try again in a bit."),
+            Error::Discord(_) => String::from("Discord didn't cooperate, try again in a bit."),
+            Error::Metadata(why) => match why.downcast_ref::<OmdbError>() {
+                Some(OmdbError::RateLimited) => {
+                    String::from("We've hit the OMDb request limit for today, try again tomorrow.")
+                }
+                Some(OmdbError::BadKey) => {
+                    String::from("The OMDb API key isn't working, ask an admin to check it.")
+                }
+                _ => String::from("Couldn't look up that movie right now, try again in a bit."),
+            },
+            Error::Config(why) => format!("The bot is misconfigured: {}. Ask an admin to fix it.", why),
+            Error::UserInput(why) => why.clone(),
+            Error::Denied(why) => why.clone(),
+            Error::Other(_) => String::from("Something went wrong, please try again."),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Db(why) => write!(f, "database error: {}", why),
+            Error::Discord(why) => write!(f, "discord error: {}", why),
+            Error::Metadata(why) => write!(f, "metadata error: {}", why),
+            Error::Config(why) => write!(f, "config error: {}", why),
+            Error::UserInput(why) => write!(f, "bad input: {}", why),
+            Error::Denied(why) => write!(f, "denied: {}", why),
+            Error::Other(why) => write!(f, "{}", why),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<DieselError> for Error {
+    fn from(why: DieselError) -> Self {
+        Error::Db(Box::new(why))
+    }
+}
+
+impl From<PoolError> for Error {
+    fn from(why: PoolError) -> Self {
+        Error::Db(Box::new(why))
+    }
+}
+
+impl From<serenity::Error> for Error {
+    fn from(why: serenity::Error) -> Self {
+        Error::Discord(why)
+    }
+}
+
+impl From<OmdbError> for Error {
+    fn from(why: OmdbError) -> Self {
+        Error::Metadata(Box::new(why))
+    }
+}
@@ -0,0 +1,216 @@
+use chrono::{Datelike, Utc};
+use reqwest::Url;
+use tracing::warn;
+
+use kekw_db::KekPool;
+
+use crate::config::Config;
+use crate::metadata::MetadataProvider;
+use crate::tmdb::TmdbClient;
+use crate::omdb_cache;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Sync + Send>>;
+
+// A movie page on one of the sites people paste links from
+#[derive(Clone, Debug, PartialEq)]
+pub enum MovieLink {
+    // https://www.imdb.com/title/tt0133093/
+    Imdb(String),
+    // https://letterboxd.com/film/the-matrix/
+    Letterboxd(String),
+    // https://www.themoviedb.org/movie/603-the-matrix
+    Tmdb(String),
+    // https://boxd.it/29Iu, a Letterboxd short link
+    Short(Url),
+}
+
+pub fn is_imdb_id(text: &str) -> bool {
+    text.starts_with("tt") && text.len() > 2 && text[2..].chars().all(|c| c.is_ascii_digit())
+}
+
+// Find the first movie link in the text
+pub fn find_movie_link(text: &str) -> Option<MovieLink> {
+    text.split_whitespace().find_map(parse_movie_link)
+}
+
+pub fn parse_movie_link(text: &str) -> Option<MovieLink> {
+    let url = Url::parse(text).ok()?;
+    let host = url.host_str()?.trim_start_matches("www.").trim_start_matches("m.");
+    let segments: Vec<&str> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+
+    match host {
+        "imdb.com" => segments
+            .iter()
+            .find(|s| s.starts_with("tt"))
+            .map(|s| MovieLink::Imdb(s.to_string())),
+        "letterboxd.com" => match segments.as_slice() {
+            ["film", slug, ..] => Some(MovieLink::Letterboxd(slug.to_string())),
+            _ => None,
+        },
+        "boxd.it" => Some(MovieLink::Short(url)),
+        "themoviedb.org" => match segments.as_slice() {
+            ["movie", slug, ..] => Some(MovieLink::Tmdb(slug.to_string())),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// The first movie was shot in the late 1870s, anything outside that up to next year is part of the title
+fn is_release_year(year: i32) -> bool {
+    (1870..=Utc::now().year() + 1).contains(&year)
+}
+
+// Turn a slug like the-thing-1982 into a title and an optional year
+fn slug_to_title(slug: &str) -> (String, Option<i32>) {
+    let mut words: Vec<&str> = slug.split('-').filter(|w| !w.is_empty()).collect();
+
+    let year = match words.last() {
+        Some(last) if words.len() > 1 && last.len() == 4 && last.chars().all(|c| c.is_ascii_digit()) => {
+            last.parse::<i32>().ok().filter(|year| is_release_year(*year))
+        }
+        _ => None,
+    };
+    if year.is_some() {
+        words.pop();
+    }
+
+    (words.join(" "), year)
+}
+
+// Resolve a link to the canonical movie, returning its title and IMDb id
+pub async fn resolve(
+    db_pool: &KekPool,
+    config: &Config,
+    metadata: &dyn MetadataProvider,
+    link: MovieLink,
+) -> Result<Option<(String, String)>> {
+    match link {
+        MovieLink::Imdb(imdb_id) => Ok(omdb_cache::query_by_id(db_pool, config, metadata, &imdb_id)
+            .await?
+            .map(|movie| (movie.title, movie.imdb_id))),
+        MovieLink::Letterboxd(slug) => resolve_slug(db_pool, config, metadata, &slug).await,
+        MovieLink::Tmdb(slug) => {
+            // TMDB slugs are the TMDB id, optionally followed by the title
+            let (tmdb_id, title_slug) = match slug.find('-') {
+                Some(i) => (&slug[..i], &slug[i + 1..]),
+                None => (slug.as_str(), ""),
+            };
+            let tmdb_id = match tmdb_id.parse::<u64>() {
+                Ok(tmdb_id) => tmdb_id,
+                Err(_) => return Ok(None),
+            };
+
+            if config.metadata.tmdb_api_key.is_some() {
+                match TmdbClient::from_config(&config.metadata).movie(tmdb_id).await {
+                    Ok(movie) => return Ok(movie.imdb_id.map(|imdb_id| (movie.title, imdb_id))),
+                    Err(why) => warn!("Couldn't look up TMDB movie {}, searching by title: {:?}", tmdb_id, why),
+                }
+            }
+
+            if title_slug.is_empty() {
+                return Ok(None);
+            }
+            resolve_slug(db_pool, config, metadata, title_slug).await
+        }
+        MovieLink::Short(url) => {
+            // Short links redirect to the full Letterboxd page
+            let full_url = reqwest::get(url).await?.url().to_string();
+            match parse_movie_link(&full_url) {
+                Some(MovieLink::Letterboxd(slug)) => resolve_slug(db_pool, config, metadata, &slug).await,
+                _ => Ok(None),
+            }
+        }
+    }
+}
+
+// Search by the title in the slug, narrowed by its year if it ends in one.
+// Some titles end in a year, like wonder-woman-1984, so the whole slug is tried if that misses.
+async fn resolve_slug(
+    db_pool: &KekPool,
+    config: &Config,
+    metadata: &dyn MetadataProvider,
+    slug: &str,
+) -> Result<Option<(String, String)>> {
+    let (title, year) = slug_to_title(slug);
+
+    let found = resolve_title(db_pool, config, metadata, &title, year).await?;
+    if found.is_some() || year.is_none() {
+        return Ok(found);
+    }
+
+    let full_title: Vec<&str> = slug.split('-').filter(|w| !w.is_empty()).collect();
+    resolve_title(db_pool, config, metadata, &full_title.join(" "), None).await
+}
+
+async fn resolve_title(
+    db_pool: &KekPool,
+    config: &Config,
+    metadata: &dyn MetadataProvider,
+    title: &str,
+    year: Option<i32>,
+) -> Result<Option<(String, String)>> {
+    Ok(omdb_cache::search(db_pool, config, metadata, title, year)
+        .await?
+        .into_iter()
+        .next()
+        .map(|movie| (movie.title, movie.imdb_id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_links_from_each_site() {
+        assert_eq!(
+            parse_movie_link("https://www.imdb.com/title/tt0133093/"),
+            Some(MovieLink::Imdb(String::from("tt0133093")))
+        );
+        assert_eq!(
+            parse_movie_link("https://m.imdb.com/title/tt0133093/?ref_=fn_al_tt_1"),
+            Some(MovieLink::Imdb(String::from("tt0133093")))
+        );
+        assert_eq!(
+            parse_movie_link("https://letterboxd.com/film/the-matrix/"),
+            Some(MovieLink::Letterboxd(String::from("the-matrix")))
+        );
+        assert_eq!(
+            parse_movie_link("https://www.themoviedb.org/movie/603-the-matrix"),
+            Some(MovieLink::Tmdb(String::from("603-the-matrix")))
+        );
+        assert_eq!(
+            parse_movie_link("https://boxd.it/29Iu"),
+            Some(MovieLink::Short(Url::parse("https://boxd.it/29Iu").unwrap()))
+        );
+    }
+
+    #[test]
+    fn ignores_other_links_and_text() {
+        assert_eq!(parse_movie_link("https://letterboxd.com/someone/"), None);
+        assert_eq!(parse_movie_link("https://www.themoviedb.org/tv/1399"), None);
+        assert_eq!(parse_movie_link("https://example.com/film/the-matrix/"), None);
+        assert_eq!(parse_movie_link("the matrix"), None);
+    }
+
+    #[test]
+    fn finds_the_link_in_a_message() {
+        assert_eq!(
+            find_movie_link("how about https://letterboxd.com/film/heat-1995/ this week"),
+            Some(MovieLink::Letterboxd(String::from("heat-1995")))
+        );
+    }
+
+    #[test]
+    fn slug_year_is_split_off() {
+        assert_eq!(slug_to_title("the-thing-1982"), (String::from("the thing"), Some(1982)));
+        assert_eq!(slug_to_title("the-matrix"), (String::from("the matrix"), None));
+    }
+
+    #[test]
+    fn slug_keeps_numbers_that_arent_release_years() {
+        assert_eq!(slug_to_title("blade-runner-2049"), (String::from("blade runner 2049"), None));
+        assert_eq!(slug_to_title("the-year-1234"), (String::from("the year 1234"), None));
+        assert_eq!(slug_to_title("1917"), (String::from("1917"), None));
+    }
+}
@@ -1,8 +1,27 @@
 // Pull in local modules
+mod audit;
+mod availability;
+mod backend;
 mod commands;
+mod config;
+mod error;
+mod links;
+mod metadata;
+mod movie_info;
+mod movie_night;
+mod notify;
 mod omdb;
+mod omdb_cache;
+mod permissions;
+mod settings;
+mod supervisor;
 mod timed;
+mod tmdb;
 mod utils;
+mod voting;
+
+#[cfg(test)]
+mod tests;
 
 // Imports
 use std::{collections::HashSet, env, sync::Arc};
@@ -10,10 +29,14 @@ use std::{collections::HashSet, env, sync::Arc};
 use serenity::{
     async_trait,
     client::bridge::gateway::ShardManager,
-    framework::{standard::macros::group, StandardFramework},
+    framework::{
+        standard::macros::{group, hook},
+        standard::{CommandResult, DispatchError, Reason},
+        StandardFramework,
+    },
     http::Http,
     model::id::GuildId,
-    model::{event::ResumedEvent, gateway::Ready},
+    model::{channel::Message, event::ResumedEvent, gateway::Ready, interactions::Interaction},
     prelude::*,
 };
 
@@ -22,8 +45,11 @@ use tracing_subscriber::{EnvFilter, FmtSubscriber};
 
 use kekw_db::KekPool;
 
+use config::Config;
+use supervisor::Supervisor;
+
 // Serenity(Discord)
-use commands::{math::*, movie::*};
+use commands::{audit::*, cache::*, carryover::*, config::*, math::*, movie::*, notify::*, permissions::*, schedule::*};
 
 struct ShardManagerContainer;
 
@@ -38,59 +64,118 @@ impl TypeMapKey for DBConnectionContainer {
     type Value = KekPool;
 }
 
+// Configuration validated at startup
+pub struct ConfigContainer;
+
+impl TypeMapKey for ConfigContainer {
+    type Value = Arc<Config>;
+}
+
+// The metadata providers, built once so every lookup reuses their HTTP clients
+pub struct MetadataContainer;
+
+impl TypeMapKey for MetadataContainer {
+    type Value = Arc<metadata::FallbackProvider>;
+}
+
+pub struct SupervisorContainer;
+
+impl TypeMapKey for SupervisorContainer {
+    type Value = Arc<Supervisor>;
+}
+
 struct Handler;
 
+// Tell the user why their command failed and log the details
+#[hook]
+async fn after(ctx: &Context, msg: &Message, command_name: &str, command_result: CommandResult) {
+    if let Err(why) = command_result {
+        let why = error::Error::from_boxed(why);
+        error!("Command '{}' failed: {}", command_name, why);
+
+        if let Err(send_why) = msg.reply(&ctx.http, why.reply()).await {
+            error!("Error sending error reply: {:?}", send_why);
+        }
+    }
+}
+
+// Explain why a command wasn't run
+#[hook]
+async fn dispatch_error(ctx: &Context, msg: &Message, error: DispatchError) {
+    let reply = match error {
+        DispatchError::CheckFailed(_, Reason::User(why)) => why,
+        DispatchError::CheckFailed(_, Reason::UserAndLog { user, log }) => {
+            error!("{}", log);
+            user
+        }
+        DispatchError::LackingPermissions(_) => String::from("You need Manage Server to do that."),
+        DispatchError::OnlyForOwners => String::from("Only the bot owner can do that."),
+        DispatchError::OnlyForGuilds => String::from("Movie night commands only work in a server."),
+        why => {
+            info!("Command not dispatched: {:?}", why);
+            return;
+        }
+    };
+
+    if let Err(why) = msg.reply(&ctx.http, reply).await {
+        error!("Error sending dispatch error reply: {:?}", why);
+    }
+}
+
 #[async_trait]
 impl EventHandler for Handler {
     async fn ready(&self, _: Context, ready: Ready) {
         info!("Connected as {}", ready.user.name);
     }
 
-    async fn cache_ready(&self, ctx: Context, _guilds: Vec<GuildId>) {
-        info!("Starting Scheduler thread.");
-
-        tokio::spawn(async move {
-            let ctx = Arc::new(ctx);
-            let ctx1 = Arc::clone(&ctx);
-            loop {
-                use chrono::prelude::*;
-                use chrono::{DateTime, Duration, Utc};
-                use chrono_tz::US::Eastern;
-                pub const WEEK_IN_SECONDS: i64 = 604800;
-                pub fn get_next_movie_selection() -> Duration {
-                    //get a hardcoded past reset date / time (17:00 UTC every tuesday)
-                    let last_selection = Eastern.ymd(2021, 2, 11).and_hms(18, 0, 0);
-                    let now: DateTime<Utc> = Utc::now();
-                    //get total seconds between now and the past reset
-                    //take the mod of that divided by a week in seconds
-                    //subtract that amount from current date / time to find previous reset
-                    Duration::seconds(
-                        WEEK_IN_SECONDS
-                            - ((now - last_selection.with_timezone(&Utc)).num_seconds()
-                                % WEEK_IN_SECONDS),
-                    )
-                }
-
-                let next_movie_selection_duration = get_next_movie_selection();
+    async fn cache_ready(&self, ctx: Context, guilds: Vec<GuildId>) {
+        let (db_pool, config, supervisor) = {
+            let data_read = ctx.data.read().await;
+            (
+                data_read
+                    .get::<DBConnectionContainer>()
+                    .expect("Expected DBConnection in TypeMap.")
+                    .clone(),
+                data_read
+                    .get::<ConfigContainer>()
+                    .expect("Expected Config in TypeMap.")
+                    .clone(),
+                data_read
+                    .get::<SupervisorContainer>()
+                    .expect("Expected Supervisor in TypeMap.")
+                    .clone(),
+            )
+        };
+
+        // Needs the cache to find which guild the configured channel is in
+        if let Err(why) = settings::seed(&ctx, &db_pool, &config).await {
+            error!("Could not store the configured guild settings: {:?}", why);
+        }
 
-                info!(
-                    "Next movie selection scheduled for {}",
-                    Utc::now() + next_movie_selection_duration
-                );
+        // Slash commands are registered per guild so changes show up immediately
+        if config.features.slash_commands {
+            for guild_id in guilds {
+                if let Err(why) = commands::slash::register(&ctx, guild_id).await {
+                    error!("Could not register slash commands in {}: {:?}", guild_id, why);
+                }
+            }
+        }
 
-                let mut interval_timer =
-                    tokio::time::interval(next_movie_selection_duration.to_std().unwrap());
+        if !config.features.scheduler {
+            info!("Scheduler is disabled.");
+            return;
+        }
 
-                // Tick once to clear it??
-                interval_timer.tick().await;
+        // Restarted by the supervisor if it fails, cache_ready can run again after a reconnect
+        // but the supervisor won't start a second copy
+        let ctx = Arc::new(ctx);
+        supervisor
+            .spawn("scheduler", move |shutdown| Box::pin(timed::run_scheduler(ctx.clone(), shutdown)))
+            .await;
+    }
 
-                // Wait for the next interval tick
-                interval_timer.tick().await;
-                timed::jobs::select_movie(&ctx1).await;
-            }
-        })
-        .await
-        .unwrap(); // For async task
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        commands::slash::handle_interaction(&ctx, interaction).await;
     }
 
     async fn resume(&self, _: Context, _: ResumedEvent) {
@@ -106,18 +191,47 @@ struct General;
 #[prefix = "m"]
 #[description = "Submit a movie to Movie Night!"]
 #[default_command(submit)]
+#[checks(MoviePermission)]
 #[commands(
     deletesub,
     getsubs,
     roll,
+    vote,
     startperiod,
     reopenperiod,
     endperiod,
     listperiods,
-    fixdb
+    history,
+    verifyroll,
+    info,
+    fixdb,
+    cacheinfo,
+    schedule,
+    notify,
+    carryover,
+    config,
+    permissions,
+    audit
 )]
 struct Movie;
 
+// Resolves on Ctrl-C, or SIGTERM from docker stop
+#[cfg(unix)]
+async fn shutdown_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => (),
+        _ = terminate.recv() => (),
+    }
+}
+
+#[cfg(not(unix))]
+async fn shutdown_signal() {
+    tokio::signal::ctrl_c().await.expect("Failed to listen for Ctrl-C");
+}
+
 #[tokio::main]
 async fn main() {
     match env::var("PROD") {
@@ -140,19 +254,35 @@ async fn main() {
 
     tracing::subscriber::set_global_default(subscriber).expect("Failed to start the logger");
 
+    // Check every setting before connecting to anything
+    let config = match Config::load() {
+        Ok(config) => Arc::new(config),
+        Err(why) => panic!("Invalid configuration:\n{}", why),
+    };
+
     // Start sqlite connection
     let pool = kekw_db::establish_connection();
 
-    // Setup Discord bot variables
-    let token = env::var("DISCORD_TOKEN").expect("Expected DISCORD_TOKEN to be set");
-
-    env::var("MOVIE_NOTIFY_ROLE_ID").expect("Expected MOVIE_NOTIFY_ROLE_ID to be set");
-    env::var("MOVIE_CHANNEL").expect("Expected MOVIE_CHANNEL to be set");
-
+    info!(
+        "Rolling {} movies with {} voting",
+        config.voting.candidates,
+        config.voting.mode.to_string()
+    );
+    info!(
+        "Showing where to watch in {} using {:?}",
+        config.availability.region, config.availability.provider
+    );
+    info!(
+        "Looking up movies with {:?}, cached for {} hours",
+        config.metadata.providers,
+        config.metadata.cache_ttl.num_hours()
+    );
+
+    let token = config.discord_token.clone();
     let http = Http::new_with_token(&token);
 
     // We will fetch your bot's owners and id
-    let (owners, _bot_id) = match http.get_current_application_info().await {
+    let (owners, bot_id) = match http.get_current_application_info().await {
         Ok(info) => {
             let mut owners = HashSet::new();
             owners.insert(info.owner.id);
@@ -164,11 +294,15 @@ async fn main() {
 
     // Create the framework
     let framework = StandardFramework::new()
-        .configure(|c| c.owners(owners).prefix("!"))
+        .configure(|c| c.owners(owners).prefix(&config.prefix))
+        .after(after)
+        .on_dispatch_error(dispatch_error)
         .group(&GENERAL_GROUP)
         .group(&MOVIE_GROUP);
 
+    // Prefix commands stay available alongside the slash commands
     let mut client = Client::builder(&token)
+        .application_id(bot_id.0)
         .framework(framework)
         .event_handler(Handler)
         .await
@@ -179,9 +313,39 @@ async fn main() {
 
         // Write connection to client data
         data.insert::<DBConnectionContainer>(pool);
+        data.insert::<MetadataContainer>(Arc::new(metadata::from_config(&config.metadata)));
+        data.insert::<ConfigContainer>(config);
+        data.insert::<SupervisorContainer>(Arc::new(Supervisor::new()));
     }
 
+    // Let the background jobs finish what they are doing, then disconnect every shard
+    // so client.start() returns
+    let data = client.data.clone();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        info!("Shutting down");
+
+        let (supervisor, shard_manager) = {
+            let data_read = data.read().await;
+            (
+                data_read
+                    .get::<SupervisorContainer>()
+                    .expect("Expected Supervisor in TypeMap.")
+                    .clone(),
+                data_read
+                    .get::<ShardManagerContainer>()
+                    .expect("Expected ShardManager in TypeMap.")
+                    .clone(),
+            )
+        };
+
+        supervisor.shutdown().await;
+        shard_manager.lock().await.shutdown_all().await;
+    });
+
     if let Err(why) = client.start().await {
         error!("Client error: {:?}", why);
     }
+
+    info!("Shut down");
 }
@@ -0,0 +1,345 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use serenity::futures::future::join_all;
+use tracing::warn;
+
+use crate::config::MetadataConfig;
+use crate::omdb::{self, OmdbClient, SearchQuery};
+use crate::tmdb::{self, TmdbClient};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+// A movie as shown in embeds, whichever provider it came from
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MovieDetails {
+    // Submissions are stored by IMDb id so every provider has to supply one
+    pub imdb_id: String,
+    pub title: String,
+    pub year: Option<i32>,
+    // In minutes
+    pub runtime: Option<u32>,
+    pub genre: Option<String>,
+    pub director: Option<String>,
+    pub plot: Option<String>,
+    pub poster: Option<String>,
+    pub ratings: Vec<Rating>,
+    pub tmdb_id: Option<u64>,
+}
+
+impl MovieDetails {
+    // Title with the year, e.g. The Matrix (1999)
+    pub fn label(&self) -> String {
+        label(&self.title, self.year)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Rating {
+    // IMDb, RT, MC or TMDB
+    pub source: String,
+    // As the source shows it, e.g. 8.7/10 or 88%
+    pub value: String,
+    // Out of 100
+    pub score: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SearchMatch {
+    pub imdb_id: String,
+    pub title: String,
+    pub year: Option<i32>,
+    pub poster: Option<String>,
+}
+
+impl SearchMatch {
+    pub fn label(&self) -> String {
+        label(&self.title, self.year)
+    }
+}
+
+fn label(title: &str, year: Option<i32>) -> String {
+    match year {
+        Some(year) => format!("{} ({})", title, year),
+        None => title.to_string(),
+    }
+}
+
+// Somewhere to look up movie details. Not finding a movie is Ok(None),
+// errors mean the provider couldn't answer and another one could be tried.
+#[async_trait]
+pub trait MetadataProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn movie_by_id(&self, imdb_id: &str) -> Result<Option<MovieDetails>>;
+
+    async fn movie_by_title(&self, title: &str, year: Option<i32>) -> Result<Option<MovieDetails>>;
+
+    async fn search(&self, title: &str, year: Option<i32>) -> Result<Vec<SearchMatch>>;
+}
+
+// OMDb uses N/A for anything it doesn't know
+fn known(value: String) -> Option<String> {
+    match value.trim() {
+        "" | "N/A" => None,
+        _ => Some(value),
+    }
+}
+
+impl From<omdb::Movie> for MovieDetails {
+    fn from(movie: omdb::Movie) -> Self {
+        let ratings = movie
+            .ratings
+            .iter()
+            .filter_map(|rating| {
+                let source = match rating.source.as_str() {
+                    "Internet Movie Database" => "IMDb",
+                    "Rotten Tomatoes" => "RT",
+                    "Metacritic" => "MC",
+                    _ => return None,
+                };
+                Some(Rating {
+                    source: source.to_string(),
+                    value: rating.value.clone(),
+                    score: rating.score(),
+                })
+            })
+            .collect();
+
+        MovieDetails {
+            imdb_id: movie.imdb_id,
+            title: movie.title,
+            year: movie.year,
+            runtime: movie.runtime,
+            genre: known(movie.genre),
+            director: known(movie.director),
+            plot: known(movie.plot),
+            poster: known(movie.poster),
+            ratings,
+            tmdb_id: None,
+        }
+    }
+}
+
+#[async_trait]
+impl MetadataProvider for OmdbClient {
+    fn name(&self) -> &'static str {
+        "omdb"
+    }
+
+    async fn movie_by_id(&self, imdb_id: &str) -> Result<Option<MovieDetails>> {
+        match OmdbClient::movie_by_id(self, imdb_id).await {
+            Ok(movie) => Ok(Some(movie.into())),
+            Err(omdb::OmdbError::NotFound) => Ok(None),
+            Err(why) => Err(why.into()),
+        }
+    }
+
+    async fn movie_by_title(&self, title: &str, year: Option<i32>) -> Result<Option<MovieDetails>> {
+        match OmdbClient::movie_by_title(self, title, year).await {
+            Ok(movie) => Ok(Some(movie.into())),
+            Err(omdb::OmdbError::NotFound) => Ok(None),
+            Err(why) => Err(why.into()),
+        }
+    }
+
+    async fn search(&self, title: &str, year: Option<i32>) -> Result<Vec<SearchMatch>> {
+        let mut query = SearchQuery::movies(title);
+        query.year = year;
+
+        Ok(OmdbClient::search(self, &query)
+            .await?
+            .results
+            .into_iter()
+            .map(|result| SearchMatch {
+                imdb_id: result.imdb_id,
+                title: result.title,
+                year: result.year,
+                poster: known(result.poster),
+            })
+            .collect())
+    }
+}
+
+// Movies without an IMDb id can't be submitted so they are left out
+fn from_tmdb(movie: tmdb::Movie) -> Option<MovieDetails> {
+    let director = movie
+        .credits
+        .crew
+        .iter()
+        .filter(|member| member.job == "Director")
+        .map(|member| member.name.clone())
+        .collect::<Vec<String>>()
+        .join(", ");
+    let genre = movie
+        .genres
+        .iter()
+        .map(|genre| genre.name.clone())
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    let mut ratings = Vec::new();
+    if movie.vote_count > 0 {
+        ratings.push(Rating {
+            source: String::from("TMDB"),
+            value: format!("{:.1}/10", movie.vote_average),
+            score: Some(movie.vote_average * 10.0),
+        });
+    }
+
+    Some(MovieDetails {
+        imdb_id: movie.imdb_id.filter(|id| !id.is_empty())?,
+        title: movie.title,
+        year: tmdb::release_year(&movie.release_date),
+        runtime: movie.runtime.filter(|minutes| *minutes > 0),
+        genre: Some(genre).filter(|genre| !genre.is_empty()),
+        director: Some(director).filter(|director| !director.is_empty()),
+        plot: movie.overview.filter(|plot| !plot.is_empty()),
+        poster: movie.poster_path.map(|path| tmdb::poster_url(&path)),
+        ratings,
+        tmdb_id: Some(movie.id),
+    })
+}
+
+// TMDB search results don't include IMDb ids so only the first few are looked up
+const TMDB_SEARCH_DETAILS: usize = 10;
+
+#[async_trait]
+impl MetadataProvider for TmdbClient {
+    fn name(&self) -> &'static str {
+        "tmdb"
+    }
+
+    async fn movie_by_id(&self, imdb_id: &str) -> Result<Option<MovieDetails>> {
+        match self.find_by_imdb_id(imdb_id).await? {
+            Some(found) => Ok(from_tmdb(self.movie(found.id).await?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn movie_by_title(&self, title: &str, year: Option<i32>) -> Result<Option<MovieDetails>> {
+        match TmdbClient::search(self, title, year, 1).await?.into_iter().next() {
+            Some(found) => Ok(from_tmdb(self.movie(found.id).await?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn search(&self, title: &str, year: Option<i32>) -> Result<Vec<SearchMatch>> {
+        let results = TmdbClient::search(self, title, year, 1).await?;
+
+        let details = join_all(
+            results
+                .iter()
+                .take(TMDB_SEARCH_DETAILS)
+                .map(|result| self.movie(result.id)),
+        )
+        .await;
+
+        Ok(details
+            .into_iter()
+            .filter_map(|movie| movie.ok().and_then(from_tmdb))
+            .map(|movie| SearchMatch {
+                imdb_id: movie.imdb_id,
+                title: movie.title,
+                year: movie.year,
+                poster: movie.poster,
+            })
+            .collect())
+    }
+}
+
+// Tries each provider in order, moving on when one fails
+pub struct FallbackProvider {
+    providers: Vec<Box<dyn MetadataProvider>>,
+}
+
+#[async_trait]
+impl MetadataProvider for FallbackProvider {
+    fn name(&self) -> &'static str {
+        "fallback"
+    }
+
+    async fn movie_by_id(&self, imdb_id: &str) -> Result<Option<MovieDetails>> {
+        let mut last_error = None;
+        for provider in &self.providers {
+            match provider.movie_by_id(imdb_id).await {
+                Ok(movie) => return Ok(movie),
+                Err(why) => {
+                    warn!("{} lookup of {} failed: {}", provider.name(), imdb_id, why);
+                    last_error = Some(why);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| "No metadata providers configured".into()))
+    }
+
+    async fn movie_by_title(&self, title: &str, year: Option<i32>) -> Result<Option<MovieDetails>> {
+        let mut last_error = None;
+        for provider in &self.providers {
+            match provider.movie_by_title(title, year).await {
+                Ok(movie) => return Ok(movie),
+                Err(why) => {
+                    warn!("{} lookup of {} failed: {}", provider.name(), title, why);
+                    last_error = Some(why);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| "No metadata providers configured".into()))
+    }
+
+    async fn search(&self, title: &str, year: Option<i32>) -> Result<Vec<SearchMatch>> {
+        let mut last_error = None;
+        for provider in &self.providers {
+            match provider.search(title, year).await {
+                Ok(matches) => return Ok(matches),
+                Err(why) => {
+                    warn!("{} search for {} failed: {}", provider.name(), title, why);
+                    last_error = Some(why);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| "No metadata providers configured".into()))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProviderKind {
+    Omdb,
+    Tmdb,
+}
+
+impl FromStr for ProviderKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "omdb" => Ok(ProviderKind::Omdb),
+            "tmdb" => Ok(ProviderKind::Tmdb),
+            _ => Err(format!("Unknown metadata provider {}, expected omdb or tmdb", s)),
+        }
+    }
+}
+
+impl ProviderKind {
+    fn client(self, config: &MetadataConfig) -> Box<dyn MetadataProvider> {
+        match self {
+            ProviderKind::Omdb => Box::new(OmdbClient::from_config(config)),
+            ProviderKind::Tmdb => Box::new(TmdbClient::from_config(config)),
+        }
+    }
+}
+
+// The configured providers, tried in order
+pub fn from_config(config: &MetadataConfig) -> FallbackProvider {
+    FallbackProvider {
+        providers: config
+            .providers
+            .iter()
+            .map(|kind| kind.client(config))
+            .collect(),
+    }
+}
@@ -0,0 +1,258 @@
+use serenity::builder::CreateEmbed;
+use tracing::warn;
+
+use kekw_db::KekPool;
+
+use crate::availability::Availability;
+use crate::config::Config;
+use crate::links;
+use crate::metadata::{MetadataProvider, MovieDetails};
+use crate::omdb_cache;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Sync + Send>>;
+
+// Plots longer than this get cut off so a few movies fit in one embed
+const SHORT_PLOT_LENGTH: usize = 200;
+
+// Discord rejects the whole message if an embed goes over any of these
+const EMBED_TITLE_LIMIT: usize = 256;
+const FIELD_NAME_LIMIT: usize = 256;
+const FIELD_VALUE_LIMIT: usize = 1024;
+const EMBED_FIELD_LIMIT: usize = 25;
+const EMBED_TOTAL_LIMIT: usize = 6000;
+// Room kept for the field saying how many more didn't fit
+const MORE_FIELD_SIZE: usize = 32;
+// Services listed for each way of watching before the rest are counted instead
+const SERVICES_SHOWN: usize = 3;
+
+// Find a movie from a link, an IMDb id or a title
+pub async fn find_movie(
+    db_pool: &KekPool,
+    config: &Config,
+    metadata: &dyn MetadataProvider,
+    query: &str,
+) -> Result<Option<MovieDetails>> {
+    let query = query.trim();
+
+    if let Some(link) = links::find_movie_link(query) {
+        return match links::resolve(db_pool, config, metadata, link).await? {
+            Some((_, imdb_id)) => omdb_cache::query_by_id(db_pool, config, metadata, &imdb_id).await,
+            None => Ok(None),
+        };
+    }
+
+    if links::is_imdb_id(query) {
+        return omdb_cache::query_by_id(db_pool, config, metadata, query).await;
+    }
+
+    omdb_cache::query_by_title(db_pool, config, metadata, query).await
+}
+
+// Load the details of a submission, which stores the IMDb id as its link.
+// Older submissions don't have one so they are shown without details.
+pub async fn lookup(
+    db_pool: &KekPool,
+    config: &Config,
+    metadata: &dyn MetadataProvider,
+    imdb_id: &str,
+) -> Option<MovieDetails> {
+    if !links::is_imdb_id(imdb_id) {
+        return None;
+    }
+
+    match omdb_cache::query_by_id(db_pool, config, metadata, imdb_id).await {
+        Ok(movie) => movie,
+        Err(why) => {
+            warn!("Could not load movie info for {}: {:?}", imdb_id, why);
+            None
+        }
+    }
+}
+
+pub fn imdb_url(movie: &MovieDetails) -> String {
+    format!("https://www.imdb.com/title/{}/", movie.imdb_id)
+}
+
+pub fn short_plot(movie: &MovieDetails) -> Option<String> {
+    let plot = movie.plot.as_ref()?;
+
+    if plot.chars().count() <= SHORT_PLOT_LENGTH {
+        return Some(plot.to_string());
+    }
+
+    let cut: String = plot.chars().take(SHORT_PLOT_LENGTH).collect();
+    // Don't leave half a word at the end
+    let cut = match cut.rfind(' ') {
+        Some(i) => &cut[..i],
+        None => &cut[..],
+    };
+
+    Some(format!("{}…", cut.trim_end_matches(|c: char| c.is_ascii_punctuation())))
+}
+
+// Cut the text to at most limit characters, ending with … when it was cut
+pub fn truncate(text: &str, limit: usize) -> String {
+    if text.chars().count() <= limit {
+        return text.to_string();
+    }
+
+    let cut: String = text.chars().take(limit - 1).collect();
+    format!("{}…", cut)
+}
+
+// Shorten the fields to fit in one embed along with its title.
+// Fields that don't fit are replaced with one saying how many more there are.
+pub fn fit_fields(fields: Vec<(String, String)>) -> Vec<(String, String)> {
+    let total = fields.len();
+    let mut fitted = Vec::new();
+    let mut used = EMBED_TITLE_LIMIT;
+
+    for (i, (name, value)) in fields.into_iter().enumerate() {
+        let name = truncate(&name, FIELD_NAME_LIMIT);
+        let value = truncate(&value, FIELD_VALUE_LIMIT);
+        let size = name.chars().count() + value.chars().count();
+
+        // Unless this is the last field, leave room to say how many more there are
+        let last = i + 1 == total;
+        let (needed, slots) = if last {
+            (size, EMBED_FIELD_LIMIT)
+        } else {
+            (size + MORE_FIELD_SIZE, EMBED_FIELD_LIMIT - 1)
+        };
+
+        if used + needed > EMBED_TOTAL_LIMIT || fitted.len() >= slots {
+            fitted.push((String::from("…"), format!("and {} more", total - i)));
+            break;
+        }
+
+        used += size;
+        fitted.push((name, value));
+    }
+
+    fitted
+}
+
+// Shorten every field evenly so all of them fit in one embed, for lists where none can be left out.
+// Reserved is how much of the embed the title and description take.
+pub fn shrink_fields(fields: Vec<(String, String)>, reserved: usize) -> Vec<(String, String)> {
+    let share = EMBED_TOTAL_LIMIT.saturating_sub(reserved) / fields.len().max(1);
+
+    fields
+        .into_iter()
+        .map(|(name, value)| {
+            let name = truncate(&name, FIELD_NAME_LIMIT);
+            let value_limit = FIELD_VALUE_LIMIT.min(share.saturating_sub(name.chars().count()));
+            let value = truncate(&value, value_limit.max(1));
+            (name, value)
+        })
+        .collect()
+}
+
+// Year, runtime, genre and director on one line, e.g. 1999 · 136 min · Action, Sci-Fi · Lana Wachowski
+pub fn summary(movie: &MovieDetails) -> String {
+    vec![
+        movie.year.map(|year| year.to_string()),
+        movie.runtime.map(|minutes| format!("{} min", minutes)),
+        movie.genre.clone(),
+        movie.director.clone(),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<String>>()
+    .join(" · ")
+}
+
+// Scores from every source, e.g. IMDb 8.7/10 · RT 88% · MC 73/100
+pub fn ratings(movie: &MovieDetails) -> String {
+    movie
+        .ratings
+        .iter()
+        .map(|rating| format!("{} {}", rating.source, rating.value))
+        .collect::<Vec<String>>()
+        .join(" · ")
+}
+
+// The first few services, e.g. Netflix, Hulu, Max and 2 more
+fn services_list(services: &[String]) -> String {
+    let shown = services.iter().take(SERVICES_SHOWN).cloned().collect::<Vec<String>>().join(", ");
+
+    match services.len().saturating_sub(SERVICES_SHOWN) {
+        0 => shown,
+        more => format!("{} and {} more", shown, more),
+    }
+}
+
+// Services offering the movie, e.g. Stream: Netflix, Hulu · Rent: Apple TV
+pub fn watch_options(availability: &Availability) -> String {
+    if availability.is_empty() {
+        return format!("Not available in {}", availability.region);
+    }
+
+    vec![
+        ("Stream", &availability.stream),
+        ("Free", &availability.free),
+        ("Rent", &availability.rent),
+        ("Buy", &availability.buy),
+    ]
+    .into_iter()
+    .filter(|(_, services)| !services.is_empty())
+    .map(|(how, services)| format!("{}: {}", how, services_list(services)))
+    .collect::<Vec<String>>()
+    .join(" · ")
+}
+
+// Summary, ratings and plot as a single embed field value
+pub fn field_details(movie: &MovieDetails) -> String {
+    let mut lines = vec![summary(movie), ratings(movie)];
+    if let Some(plot) = short_plot(movie) {
+        lines.push(plot);
+    }
+
+    lines
+        .into_iter()
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// Fill an embed with everything we know about a movie
+pub fn movie_embed<'a>(e: &'a mut CreateEmbed, movie: &MovieDetails) -> &'a mut CreateEmbed {
+    e.title(movie.label());
+    e.url(imdb_url(movie));
+
+    if let Some(poster) = &movie.poster {
+        e.thumbnail(poster);
+    }
+    if let Some(plot) = short_plot(movie) {
+        e.description(plot);
+    }
+    if let Some(minutes) = movie.runtime {
+        e.field("Runtime", format!("{} min", minutes), true);
+    }
+    if let Some(genre) = &movie.genre {
+        e.field("Genre", genre, true);
+    }
+    if let Some(director) = &movie.director {
+        e.field("Director", director, true);
+    }
+
+    let ratings = ratings(movie);
+    if !ratings.is_empty() {
+        e.field("Ratings", ratings, false);
+    }
+
+    e
+}
+
+pub fn availability_field<'a>(e: &'a mut CreateEmbed, availability: &Availability) -> &'a mut CreateEmbed {
+    let mut options = watch_options(availability);
+    if let Some(link) = &availability.link {
+        options = format!("{}\n[All offers]({})", options, link);
+    }
+
+    e.field(
+        format!("Where to watch ({})", availability.region),
+        truncate(&options, FIELD_VALUE_LIMIT),
+        false,
+    )
+}
@@ -0,0 +1,332 @@
+use std::convert::TryFrom;
+
+use serenity::model::id::{ChannelId, GuildId, UserId};
+use tracing::info;
+
+use kekw_db::models::period::Period;
+use kekw_db::models::submission::Submission;
+
+use crate::audit::Entry;
+use crate::backend::{Backend, Embed, Store};
+use crate::config::Config;
+use crate::settings::GuildSettings;
+use crate::timed::jobs;
+use crate::{links, movie_info, voting};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Sync + Send>>;
+
+// Submit a movie for the period, replacing the user's earlier submission.
+// Returns the replaced submission if there was one.
+pub fn submit(
+    store: &dyn Store,
+    period: &Period,
+    user_id: UserId,
+    title: &str,
+    imdb_id: &str,
+) -> Result<Option<Submission>> {
+    match store.user_submission(period, user_id)? {
+        Some(previous) => {
+            let mut updated_moviesub = previous.clone();
+            updated_moviesub.title = String::from(title);
+            updated_moviesub.link = String::from(imdb_id);
+            // A new movie starts over rather than inheriting a carried one's count
+            updated_moviesub.carried_over = 0;
+            store.update_submission(updated_moviesub)?;
+
+            Ok(Some(previous))
+        }
+        None => {
+            store.create_submission(period, user_id, title, imdb_id)?;
+
+            Ok(None)
+        }
+    }
+}
+
+// !m submit, replying in the channel. Links are resolved directly, titles are searched and
+// picked from the matches. Returns the change to audit if anything was submitted.
+pub async fn submit_movie(
+    backend: Backend<'_>,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    user_id: UserId,
+    movie_query: &str,
+) -> Result<Option<Entry>> {
+    let cur_period = match backend.store.open_period(guild_id)? {
+        Some(cur_period) => cur_period,
+        None => {
+            backend
+                .discord
+                .send_message(channel_id, "No current movie submission periods active.", None)
+                .await?;
+            return Ok(None);
+        }
+    };
+
+    let (title, imdb_id) = match links::find_movie_link(movie_query) {
+        Some(link) => match backend.catalog.resolve(link).await? {
+            Some(movie) => movie,
+            None => {
+                backend
+                    .discord
+                    .send_message(channel_id, "Couldn't find the movie for that link.", None)
+                    .await?;
+                return Ok(None);
+            }
+        },
+        None => match pick_movie(backend, channel_id, user_id, movie_query).await? {
+            Some(movie) => movie,
+            None => return Ok(None),
+        },
+    };
+
+    let entry = match backend.store.user_submission(&cur_period, user_id)? {
+        None => Entry::new(user_id, "submit", format!("submission for period {}", cur_period.id)),
+        Some(previous) => {
+            let question = format!(
+                "You've already submitted the movie: {}, would you like to update your submission?",
+                previous.title
+            );
+            let confirmed = backend
+                .discord
+                .confirm(channel_id, user_id, &question, "Submission updated!", "Submission not updated.")
+                .await?;
+            if !confirmed {
+                return Ok(None);
+            }
+
+            Entry::new(user_id, "submit", format!("submission {}", previous.id)).before(previous.title)
+        }
+    };
+
+    let replaced = submit(backend.store, &cur_period, user_id, &title, &imdb_id)?;
+    info!("{} submitted movie {}", user_id, title);
+    if replaced.is_none() {
+        backend
+            .discord
+            .send_message(channel_id, &format!("You've submitted the movie: {}", title), None)
+            .await?;
+    }
+
+    Ok(Some(entry.after(title)))
+}
+
+// Search for the title and let the member pick from the matches.
+// Returns the picked movie's title and IMDb id.
+async fn pick_movie(
+    backend: Backend<'_>,
+    channel_id: ChannelId,
+    user_id: UserId,
+    movie_query: &str,
+) -> Result<Option<(String, String)>> {
+    let matches = backend.catalog.search(movie_query).await?;
+    if matches.is_empty() {
+        backend
+            .discord
+            .send_message(
+                channel_id,
+                &format!("Couldn't find {}, check the spelling and try again.", movie_query),
+                None,
+            )
+            .await?;
+        return Ok(None);
+    }
+
+    let options = matches
+        .iter()
+        .map(|m| (m.label(), format!("https://www.imdb.com/title/{}/", m.imdb_id)))
+        .collect();
+    let thumbnail = matches.iter().find_map(|m| m.poster.clone());
+    let title = format!("Which movie did you mean by {}?", movie_query);
+
+    match backend.discord.choose(channel_id, user_id, &title, options, thumbnail).await? {
+        Some(choice) => Ok(Some((matches[choice].title.clone(), matches[choice].imdb_id.clone()))),
+        None => {
+            backend
+                .discord
+                .send_message(channel_id, "No movie picked, submission cancelled.", None)
+                .await?;
+            Ok(None)
+        }
+    }
+}
+
+// !m getsubs, posting the open period's submissions to the channel
+pub async fn post_submissions(backend: Backend<'_>, settings: &GuildSettings, channel_id: ChannelId) -> Result<()> {
+    let cur_period = match backend.store.open_period(settings.guild_id)? {
+        Some(cur_period) => cur_period,
+        None => {
+            backend
+                .discord
+                .send_message(channel_id, "No current movie submission periods active.", None)
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let fields = submission_fields(backend, settings, &cur_period).await?;
+    backend
+        .discord
+        .send_embed(
+            channel_id,
+            Embed {
+                title: String::from("Current Movie Submissions"),
+                fields,
+                ..Embed::default()
+            },
+        )
+        .await?;
+
+    Ok(())
+}
+
+// !m roll, rolling the open period in the channel the same way the scheduled roll does.
+// The member confirms first, and again before an earlier roll of the period is replaced.
+// Returns the change to audit if the period was rolled.
+pub async fn roll_movies(
+    backend: Backend<'_>,
+    config: &Config,
+    settings: &GuildSettings,
+    channel_id: ChannelId,
+    user_id: UserId,
+) -> Result<Option<Entry>> {
+    let cur_period = match backend.store.open_period(settings.guild_id)? {
+        Some(cur_period) => cur_period,
+        None => {
+            backend
+                .discord
+                .send_message(channel_id, "No current movie submission periods active.", None)
+                .await?;
+            return Ok(None);
+        }
+    };
+
+    if backend.store.submissions(&cur_period)?.len() < voting::MIN_CANDIDATES {
+        backend
+            .discord
+            .send_message(channel_id, "Not enough movies submitted to choose from!", None)
+            .await?;
+        return Ok(None);
+    }
+
+    let confirmed = backend
+        .discord
+        .confirm(
+            channel_id,
+            user_id,
+            "Would you like to roll for movie night?",
+            "Starting roll",
+            "Cancelling roll",
+        )
+        .await?;
+    if !confirmed {
+        return Ok(None);
+    }
+
+    if backend.store.roll(&cur_period)?.is_some() {
+        let confirmed = backend
+            .discord
+            .confirm(
+                channel_id,
+                user_id,
+                "There already exists a roll for this movie submission period, would you like to roll again?",
+                "Rolling again!",
+                "Cancelling roll.",
+            )
+            .await?;
+        if !confirmed {
+            return Ok(None);
+        }
+    }
+
+    if jobs::roll_period(backend, config, settings, channel_id).await?.is_none() {
+        return Ok(None);
+    }
+
+    let cur_roll = backend.store.roll(&cur_period)?.ok_or("The roll wasn't stored")?;
+    let mut rolled: Vec<String> = Vec::new();
+    for selection in backend.store.selections(&cur_roll)? {
+        rolled.push(backend.store.submission(selection.submission_id)?.title);
+    }
+    let seed = cur_roll.seed.unwrap_or_default();
+
+    Ok(Some(
+        Entry::new(user_id, "roll", format!("period {}", cur_period.id))
+            .before("open")
+            .after(format!("voting on {} with seed {}", rolled.join(", "), seed)),
+    ))
+}
+
+// Carry each submission that wasn't picked in the previous period into the new one, unless
+// its submitter opted out or it has already been carried as many times as the guild allows.
+// Returns the carried submissions as they were in the previous period.
+pub fn carry_over(
+    store: &dyn Store,
+    settings: &GuildSettings,
+    previous: &Period,
+    period: &Period,
+) -> Result<Vec<Submission>> {
+    let max_carry_over = match settings.carry_over {
+        Some(max_carry_over) => max_carry_over as i32,
+        None => return Ok(Vec::new()),
+    };
+
+    let winner = store.roll(previous)?.and_then(|roll| roll.winner);
+    let opted_out: Vec<String> = store
+        .carry_over_opt_outs(settings.guild_id)?
+        .iter()
+        .map(|user_id| user_id.to_string())
+        .collect();
+
+    let mut carried = Vec::new();
+    for movie_sub in store.submissions(previous)? {
+        if Some(movie_sub.id) == winner
+            || movie_sub.carried_over >= max_carry_over
+            || opted_out.contains(&movie_sub.dis_user_id)
+        {
+            continue;
+        }
+
+        store.carry_submission(period, &movie_sub)?;
+        carried.push(movie_sub);
+    }
+    info!("Carried {} submission(s) over to period {}.", carried.len(), period.id);
+
+    Ok(carried)
+}
+
+// The fields of the getsubs embed, the submitter's nickname and name followed by the movie,
+// and their roll weight if the guild weights rolls
+pub async fn submission_fields(
+    backend: Backend<'_>,
+    settings: &GuildSettings,
+    period: &Period,
+) -> Result<Vec<(String, String)>> {
+    let movie_subs = backend.store.submissions(period)?;
+    info!("Got {} movie submission(s).", movie_subs.len());
+
+    let weights = voting::submitter_weights(backend.store, settings.guild_id, period, settings.weighting)?;
+
+    let mut fields: Vec<(String, String)> = Vec::new();
+
+    for movie_sub in movie_subs {
+        let user_id = UserId::try_from(movie_sub.dis_user_id.parse::<u64>()?)?;
+        let name = backend.discord.user_name(user_id).await?;
+        let submitter = match backend.discord.nick(settings.guild_id, user_id).await {
+            Some(nick) if !nick.is_empty() => format!("{}({})", nick, name),
+            _ => name,
+        };
+
+        let mut details = match backend.catalog.details(&movie_sub.link).await {
+            Some(movie) => format!("**{}**\n{}", movie_sub.title, movie_info::field_details(&movie)),
+            None => movie_sub.title.clone(),
+        };
+        if !weights.is_empty() {
+            details.push_str(&format!("\nRoll weight: {}", voting::weight(&weights, &movie_sub)));
+        }
+
+        fields.push((submitter, details));
+    }
+
+    Ok(movie_info::fit_fields(fields))
+}
@@ -0,0 +1,123 @@
+use chrono::Duration;
+use serenity::model::id::{ChannelId, MessageId};
+use serenity::prelude::*;
+
+use crate::backend::Discord;
+use crate::config::Config;
+use crate::settings::GuildSettings;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Sync + Send>>;
+
+// Send a message that pings the guild's notify role, unless pings are turned off
+// or the guild hasn't set a role
+pub async fn announce(
+    discord: &dyn Discord,
+    config: &Config,
+    settings: &GuildSettings,
+    channel_id: ChannelId,
+    text: &str,
+) -> Result<MessageId> {
+    match settings.notify_role {
+        // Only the notify role, never @everyone or users named in titles
+        Some(role) if config.notify.ping => {
+            discord
+                .send_message(channel_id, &format!("{} {}", role.mention(), text), Some(role))
+                .await
+        }
+        _ => discord.send_message(channel_id, text, None).await,
+    }
+}
+
+// Durations like 2h, 1h 30m or 45m
+fn describe(remaining: Duration) -> String {
+    let hours = remaining.num_hours();
+    let minutes = remaining.num_minutes() % 60;
+
+    match (hours, minutes) {
+        (0, minutes) => format!("{}m", minutes),
+        (hours, 0) => format!("{}h", hours),
+        (hours, minutes) => format!("{}h {}m", hours, minutes),
+    }
+}
+
+// Posts the hash of the period's roll seed, which is revealed after the roll
+pub async fn period_opened(
+    discord: &dyn Discord,
+    config: &Config,
+    settings: &GuildSettings,
+    channel_id: ChannelId,
+    commitment: &str,
+) -> Result<MessageId> {
+    announce(
+        discord,
+        config,
+        settings,
+        channel_id,
+        &format!(
+            "A new submission period has started! Submit a movie with `{}m <movie>`.\nRoll seed hash: `{}`",
+            config.prefix, commitment
+        ),
+    )
+    .await
+}
+
+// Follows the period opening, so it doesn't ping again
+pub async fn submissions_carried(
+    discord: &dyn Discord,
+    config: &Config,
+    channel_id: ChannelId,
+    titles: &[&str],
+) -> Result<MessageId> {
+    discord
+        .send_message(
+            channel_id,
+            &format!(
+                "Carried over from last period: {}. Submit another movie to replace yours, \
+                 or stop yours being carried over with `{}m carryover off`.",
+                titles.join(", "),
+                config.prefix
+            ),
+            None,
+        )
+        .await
+}
+
+pub async fn submissions_closing(
+    discord: &dyn Discord,
+    config: &Config,
+    settings: &GuildSettings,
+    channel_id: ChannelId,
+    remaining: Duration,
+) -> Result<MessageId> {
+    announce(
+        discord,
+        config,
+        settings,
+        channel_id,
+        &format!(
+            "Submissions close in {}! Submit a movie with `{}m <movie>` if you haven't yet.",
+            describe(remaining),
+            config.prefix
+        ),
+    )
+    .await
+}
+
+pub async fn voting_started(
+    discord: &dyn Discord,
+    config: &Config,
+    settings: &GuildSettings,
+    channel_id: ChannelId,
+) -> Result<MessageId> {
+    announce(discord, config, settings, channel_id, "Voting has started, pick the next movie below!").await
+}
+
+pub async fn winner(
+    discord: &dyn Discord,
+    config: &Config,
+    settings: &GuildSettings,
+    channel_id: ChannelId,
+    title: &str,
+) -> Result<MessageId> {
+    announce(discord, config, settings, channel_id, &format!("{} wins!", title)).await
+}
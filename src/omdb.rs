@@ -1,17 +1,106 @@
-use serde::{Deserialize, Serialize};
-use reqwest::Url;
-use std::env;
+use serde::{Deserialize, Deserializer, Serialize};
+use reqwest::{StatusCode, Url};
+use std::fmt;
 
-static OMDB_ENDPOINT: &'static str = "http://www.omdbapi.com/";
+use crate::config::MetadataConfig;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub enum OmdbError {
+    // No movie matched the id, title or search
+    NotFound,
+    // Over the daily request limit of the API key
+    RateLimited,
+    // Missing or invalid OMDB_API_KEY
+    BadKey,
+    Network(reqwest::Error),
+    // Any other error OMDb answered with
+    Api(String),
+    Parse(serde_json::Error),
+}
+
+impl fmt::Display for OmdbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OmdbError::NotFound => write!(f, "movie not found"),
+            OmdbError::RateLimited => write!(f, "OMDb request limit reached"),
+            OmdbError::BadKey => write!(f, "invalid OMDb API key"),
+            OmdbError::Network(why) => write!(f, "could not reach OMDb: {}", why),
+            OmdbError::Api(why) => write!(f, "OMDb error: {}", why),
+            OmdbError::Parse(why) => write!(f, "unexpected OMDb response: {}", why),
+        }
+    }
+}
+
+impl std::error::Error for OmdbError {}
+
+impl From<reqwest::Error> for OmdbError {
+    fn from(why: reqwest::Error) -> Self {
+        OmdbError::Network(why)
+    }
+}
+
+impl From<serde_json::Error> for OmdbError {
+    fn from(why: serde_json::Error) -> Self {
+        OmdbError::Parse(why)
+    }
+}
+
+type Result<T> = std::result::Result<T, OmdbError>;
+
+// OMDb sends numbers as text like "1999", "136 min", "8.7" or "1,234,567",
+// while cached movies store them as real numbers
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NumberOrText {
+    Number(f64),
+    Text(String),
+}
+
+// The number at the start of the text, ignoring thousands separators.
+// N/A and other text without a number is None.
+fn leading_number(text: &str) -> Option<f64> {
+    let number: String = text
+        .trim()
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '.' || *c == ',')
+        .filter(|c| *c != ',')
+        .collect();
+
+    number.parse::<f64>().ok()
+}
+
+fn de_number<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Option<f64>, D::Error> {
+    Ok(match Option::<NumberOrText>::deserialize(deserializer)? {
+        Some(NumberOrText::Number(number)) => Some(number),
+        Some(NumberOrText::Text(text)) => leading_number(&text),
+        None => None,
+    })
+}
+
+fn de_i32<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Option<i32>, D::Error> {
+    Ok(de_number(deserializer)?.map(|number| number as i32))
+}
+
+fn de_u32<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Option<u32>, D::Error> {
+    Ok(de_number(deserializer)?.map(|number| number as u32))
+}
+
+fn de_f32<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Option<f32>, D::Error> {
+    Ok(de_number(deserializer)?.map(|number| number as f32))
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "PascalCase")]
 pub struct Movie {
     pub title: String,
-    pub year: String,
+    // First year for series that ran several years
+    #[serde(default, deserialize_with = "de_i32")]
+    pub year: Option<i32>,
     pub rated: String,
     pub released: String,
-    pub runtime: String,
+    // In minutes
+    #[serde(default, deserialize_with = "de_u32")]
+    pub runtime: Option<u32>,
     pub genre: String,
     pub director: String,
     pub writer: String,
@@ -21,60 +110,319 @@ pub struct Movie {
     pub country: String,
     pub awards: String,
     pub poster: String,
+    #[serde(default)]
     pub ratings: Vec<MovieRating>,
-    pub metascore: String,
-    #[serde(alias = "imdbRating")]
-    pub imdb_rating: String,
-    #[serde(alias = "imdbVotes")]
-    pub imdb_votes: String,
+    #[serde(default, deserialize_with = "de_u32")]
+    pub metascore: Option<u32>,
+    #[serde(alias = "imdbRating", default, deserialize_with = "de_f32")]
+    pub imdb_rating: Option<f32>,
+    #[serde(alias = "imdbVotes", default, deserialize_with = "de_u32")]
+    pub imdb_votes: Option<u32>,
     #[serde(alias = "imdbID")]
     pub imdb_id: String,
     pub r#type: String,
-    #[serde(alias = "DVD")]
+    #[serde(alias = "DVD", default)]
     pub dvd: String,
+    #[serde(default)]
     pub box_office: String,
+    #[serde(default)]
     pub production: String,
+    #[serde(default)]
     pub website: String,
     pub response: String
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "PascalCase")]
 pub struct MovieRating {
-    source: String,
-    value: String
+    pub source: String,
+    pub value: String
+}
+
+impl MovieRating {
+    // The rating out of 100, from values like 8.7/10, 88% or 73/100
+    pub fn score(&self) -> Option<f32> {
+        let value = self.value.trim();
+
+        if let Some(percent) = value.strip_suffix('%') {
+            return percent.parse::<f32>().ok();
+        }
+
+        let mut parts = value.split('/');
+        let score = parts.next()?.trim().parse::<f32>().ok()?;
+        let out_of = parts.next()?.trim().parse::<f32>().ok()?;
+        if out_of <= 0.0 {
+            return None;
+        }
+
+        Some(score * 100.0 / out_of)
+    }
+}
+
+// What kind of title to search for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MediaType {
+    Movie,
+    Series,
+    Episode,
+}
+
+impl ToString for MediaType {
+    fn to_string(&self) -> String {
+        match self {
+            MediaType::Movie => String::from("movie"),
+            MediaType::Series => String::from("series"),
+            MediaType::Episode => String::from("episode"),
+        }
+    }
+}
+
+// A single match from the search endpoint
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct SearchResult {
+    pub title: String,
+    #[serde(default, deserialize_with = "de_i32")]
+    pub year: Option<i32>,
+    #[serde(alias = "imdbID")]
+    pub imdb_id: String,
+    pub r#type: String,
+    pub poster: String
+}
+
+// Search for titles, OMDb returns ten results per page
+pub struct SearchQuery {
+    pub title: String,
+    pub year: Option<i32>,
+    pub media_type: Option<MediaType>,
+    // Starts at 1
+    pub page: u32,
+}
+
+impl SearchQuery {
+    pub fn movies(title: &str) -> Self {
+        SearchQuery {
+            title: title.to_string(),
+            year: None,
+            media_type: Some(MediaType::Movie),
+            page: 1,
+        }
+    }
+}
+
+pub struct SearchPage {
+    pub results: Vec<SearchResult>,
+    pub page: u32,
+    pub total_results: u32,
+}
+
+impl SearchPage {
+    pub fn has_more(&self) -> bool {
+        self.page * 10 < self.total_results
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SearchResponse {
+    search: Vec<SearchResult>,
+    #[serde(alias = "totalResults", default, deserialize_with = "de_u32")]
+    total_results: Option<u32>,
+}
+
+// What OMDb sends back instead of a result, e.g. {"Response":"False","Error":"Movie not found!"}
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ErrorResponse {
+    response: String,
+    error: String
 }
 
-pub fn build_base_url() -> Url {
-    let mut omdb_url = Url::parse(OMDB_ENDPOINT).unwrap();
-    return omdb_url
+fn classify_error(error: String) -> OmdbError {
+    let lowered = error.to_lowercase();
+
+    if lowered.contains("not found") || lowered.contains("incorrect imdb id") {
+        OmdbError::NotFound
+    } else if lowered.contains("limit") {
+        OmdbError::RateLimited
+    } else if lowered.contains("api key") {
+        OmdbError::BadKey
+    } else {
+        OmdbError::Api(error)
+    }
 }
 
-pub async fn query_by_title(title: String) -> Result<Option<Movie>, Box<dyn std::error::Error + Send + Sync>> {
-    let mut omdb_url = build_base_url();
+pub struct OmdbClient {
+    http: reqwest::Client,
+    base_url: Url,
+    api_key: String,
+}
+
+impl OmdbClient {
+    pub fn new(base_url: Url, api_key: String) -> Self {
+        OmdbClient {
+            http: reqwest::Client::new(),
+            base_url,
+            api_key,
+        }
+    }
+
+    // The key is checked at startup whenever OMDb is one of the providers
+    pub fn from_config(config: &MetadataConfig) -> Self {
+        OmdbClient::new(
+            config.omdb_base_url.clone(),
+            config.omdb_api_key.clone().unwrap_or_default(),
+        )
+    }
+
+    async fn get<T: serde::de::DeserializeOwned>(&self, params: &[(&str, String)]) -> Result<T> {
+        let mut omdb_url = self.base_url.clone();
+        {
+            let mut query = omdb_url.query_pairs_mut();
+            query.append_pair("apikey", &self.api_key);
+            for (name, value) in params {
+                query.append_pair(name, value);
+            }
+        }
+
+        let response = self.http.get(omdb_url).send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+
+        parse_response(status, &body)
+    }
+
+    pub async fn movie_by_id(&self, imdb_id: &str) -> Result<Movie> {
+        self.get(&[("i", imdb_id.to_string()), ("plot", String::from("short"))])
+            .await
+    }
+
+    pub async fn movie_by_title(&self, title: &str, year: Option<i32>) -> Result<Movie> {
+        let mut params = vec![("t", title.to_string()), ("plot", String::from("short"))];
+        if let Some(year) = year {
+            params.push(("y", year.to_string()));
+        }
 
-    omdb_url.set_query(Some(&format!("t={}", title)));
-    
-    let body = reqwest::get(omdb_url).await?.text().await?;
+        self.get(&params).await
+    }
 
-    let movie: Movie = serde_json::from_str(&body)?;
+    // An empty page when nothing matched
+    pub async fn search(&self, query: &SearchQuery) -> Result<SearchPage> {
+        let mut params = vec![("s", query.title.clone()), ("page", query.page.to_string())];
+        if let Some(year) = query.year {
+            params.push(("y", year.to_string()));
+        }
+        if let Some(media_type) = query.media_type {
+            params.push(("type", media_type.to_string()));
+        }
 
-    return Ok(Some(movie));
+        search_page(self.get::<SearchResponse>(&params).await, query.page)
+    }
 }
 
-pub async fn query_by_id(id: String) -> Result<Option<Movie>, Box<dyn std::error::Error + Send + Sync>> {
-    let OMDB_API_KEY = env::var("OMDB_API_KEY").expect("Expected OMDB_API_KEY to be set");
-    let mut omdb_url = build_base_url();
+fn parse_response<T: serde::de::DeserializeOwned>(status: StatusCode, body: &str) -> Result<T> {
+    // Errors come back as JSON, sometimes with a 401 status
+    if let Ok(error) = serde_json::from_str::<ErrorResponse>(body) {
+        if error.response == "False" {
+            return Err(classify_error(error.error));
+        }
+    }
 
-    let omdb_params = format!("apikey={}", OMDB_API_KEY) + "&" + &format!("i={}", id);
+    match status {
+        StatusCode::UNAUTHORIZED => Err(OmdbError::BadKey),
+        StatusCode::TOO_MANY_REQUESTS => Err(OmdbError::RateLimited),
+        _ => Ok(serde_json::from_str(body)?),
+    }
+}
+
+fn search_page(response: Result<SearchResponse>, page: u32) -> Result<SearchPage> {
+    match response {
+        Ok(response) => Ok(SearchPage {
+            total_results: response.total_results.unwrap_or(response.search.len() as u32),
+            results: response.search,
+            page,
+        }),
+        Err(OmdbError::NotFound) => Ok(SearchPage {
+            results: Vec::new(),
+            page,
+            total_results: 0,
+        }),
+        Err(why) => Err(why),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_for(status: StatusCode, body: &str) -> OmdbError {
+        match parse_response::<Movie>(status, body) {
+            Ok(_) => panic!("Expected an error for {}", body),
+            Err(why) => why,
+        }
+    }
 
-    omdb_url.set_query(Some(&omdb_params));
-    
-    let body = reqwest::get(omdb_url.clone()).await?.text().await?;
+    fn search(body: &str, page: u32) -> SearchPage {
+        search_page(parse_response(StatusCode::OK, body), page).unwrap()
+    }
 
-    println!("Url {}, Got body {}", omdb_url, body);
+    #[test]
+    fn maps_error_responses() {
+        assert!(matches!(
+            error_for(StatusCode::OK, r#"{"Response":"False","Error":"Movie not found!"}"#),
+            OmdbError::NotFound
+        ));
+        assert!(matches!(
+            error_for(StatusCode::OK, r#"{"Response":"False","Error":"Incorrect IMDb ID."}"#),
+            OmdbError::NotFound
+        ));
+        assert!(matches!(
+            error_for(StatusCode::UNAUTHORIZED, r#"{"Response":"False","Error":"Request limit reached!"}"#),
+            OmdbError::RateLimited
+        ));
+        assert!(matches!(
+            error_for(StatusCode::UNAUTHORIZED, r#"{"Response":"False","Error":"Invalid API key!"}"#),
+            OmdbError::BadKey
+        ));
+        assert!(matches!(
+            error_for(StatusCode::OK, r#"{"Response":"False","Error":"Something went wrong."}"#),
+            OmdbError::Api(_)
+        ));
+    }
 
-    let movie: Movie = serde_json::from_str(&body)?;
+    #[test]
+    fn maps_error_statuses_without_a_body() {
+        assert!(matches!(error_for(StatusCode::UNAUTHORIZED, ""), OmdbError::BadKey));
+        assert!(matches!(error_for(StatusCode::TOO_MANY_REQUESTS, ""), OmdbError::RateLimited));
+        assert!(matches!(error_for(StatusCode::OK, "<html>"), OmdbError::Parse(_)));
+    }
 
-    return Ok(Some(movie));
-}
\ No newline at end of file
+    #[test]
+    fn parses_search_pages() {
+        let body = r#"{
+            "Search": [
+                {"Title": "The Matrix", "Year": "1999", "imdbID": "tt0133093", "Type": "movie", "Poster": "N/A"},
+                {"Title": "The Matrix Reloaded", "Year": "2003", "imdbID": "tt0234215", "Type": "movie", "Poster": "N/A"}
+            ],
+            "totalResults": "23",
+            "Response": "True"
+        }"#;
+
+        let page = search(body, 1);
+        assert_eq!(page.total_results, 23);
+        assert_eq!(page.results.len(), 2);
+        assert_eq!(page.results[0].imdb_id, "tt0133093");
+        assert_eq!(page.results[1].year, Some(2003));
+        assert!(page.has_more());
+
+        assert!(!search(body, 3).has_more());
+    }
+
+    #[test]
+    fn no_matches_is_an_empty_page() {
+        let page = search(r#"{"Response":"False","Error":"Movie not found!"}"#, 1);
+        assert!(page.results.is_empty());
+        assert_eq!(page.total_results, 0);
+        assert!(!page.has_more());
+    }
+}
@@ -0,0 +1,174 @@
+use chrono::{NaiveDateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tracing::{info, warn};
+
+use kekw_db::models::cache_entry::CacheEntry;
+use kekw_db::{movie_cache, KekPool};
+
+use crate::config::Config;
+use crate::metadata::{MetadataProvider, MovieDetails, SearchMatch};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Sync + Send>>;
+
+const AVAILABILITY_PREFIX: &str = "watch:";
+const SEARCH_PREFIX: &str = "search:";
+
+pub fn availability_key(region: &str, imdb_id: &str) -> String {
+    format!("{}{}:{}", AVAILABILITY_PREFIX, region.to_lowercase(), imdb_id.trim().to_lowercase())
+}
+
+pub fn id_key(imdb_id: &str) -> String {
+    format!("id:{}", imdb_id.trim().to_lowercase())
+}
+
+// Lowercase and drop punctuation so "The Matrix" and "the matrix!" share an entry
+fn normalize(title: &str) -> String {
+    let normalized: Vec<String> = title
+        .split_whitespace()
+        .map(|word| {
+            word.chars()
+                .filter(|c| c.is_alphanumeric())
+                .flat_map(char::to_lowercase)
+                .collect::<String>()
+        })
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    normalized.join(" ")
+}
+
+pub fn title_key(title: &str) -> String {
+    format!("title:{}", normalize(title))
+}
+
+pub fn is_search(entry: &CacheEntry) -> bool {
+    entry.key.starts_with(SEARCH_PREFIX)
+}
+
+pub fn search_key(title: &str, year: Option<i32>) -> String {
+    match year {
+        Some(year) => format!("{}{}:{}", SEARCH_PREFIX, normalize(title), year),
+        None => format!("{}{}", SEARCH_PREFIX, normalize(title)),
+    }
+}
+
+// Movies that weren't found are retried sooner in case they were just added
+pub fn is_expired(config: &Config, entry: &CacheEntry, now: NaiveDateTime) -> bool {
+    let ttl = if entry.key.starts_with(AVAILABILITY_PREFIX) {
+        config.availability.ttl
+    } else if is_search(entry) {
+        // New movies turn up in searches, so results are kept only as long as misses
+        config.metadata.cache_miss_ttl
+    } else {
+        match entry.body {
+            Some(_) => config.metadata.cache_ttl,
+            None => config.metadata.cache_miss_ttl,
+        }
+    };
+
+    entry.fetched_at + ttl < now
+}
+
+// Cached value for a key, None if it isn't cached or has expired.
+// Some(None) means the provider told us it doesn't exist.
+pub fn cached<T: DeserializeOwned>(db_pool: &KekPool, config: &Config, key: &str) -> Option<Option<T>> {
+    let entry = movie_cache::get_entry(db_pool, key).ok()?;
+
+    if is_expired(config, &entry, Utc::now().naive_utc()) {
+        return None;
+    }
+
+    match entry.body {
+        Some(body) => match serde_json::from_str::<T>(&body) {
+            Ok(value) => Some(Some(value)),
+            Err(why) => {
+                warn!("Ignoring unreadable cache entry {}: {:?}", key, why);
+                None
+            }
+        },
+        None => Some(None),
+    }
+}
+
+// The cache only saves requests, so failing to write it isn't an error for the caller
+pub fn store<T: Serialize>(db_pool: &KekPool, key: &str, value: &Option<T>) {
+    let body = match value {
+        Some(value) => match serde_json::to_string(value) {
+            Ok(body) => Some(body),
+            Err(why) => {
+                warn!("Could not serialize cache entry {}: {:?}", key, why);
+                return;
+            }
+        },
+        None => None,
+    };
+
+    if let Err(why) = movie_cache::set_entry(db_pool, key, body, Utc::now().naive_utc()) {
+        warn!("Could not write cache entry {}: {:?}", key, why);
+    }
+}
+
+pub async fn query_by_id(
+    db_pool: &KekPool,
+    config: &Config,
+    metadata: &dyn MetadataProvider,
+    imdb_id: &str,
+) -> Result<Option<MovieDetails>> {
+    let key = id_key(imdb_id);
+
+    if let Some(movie) = cached::<MovieDetails>(db_pool, config, &key) {
+        return Ok(movie);
+    }
+
+    info!("Movie cache miss for {}", key);
+    let movie = metadata.movie_by_id(imdb_id).await?;
+    store(db_pool, &key, &movie);
+
+    Ok(movie)
+}
+
+pub async fn query_by_title(
+    db_pool: &KekPool,
+    config: &Config,
+    metadata: &dyn MetadataProvider,
+    title: &str,
+) -> Result<Option<MovieDetails>> {
+    let key = title_key(title);
+
+    if let Some(movie) = cached::<MovieDetails>(db_pool, config, &key) {
+        return Ok(movie);
+    }
+
+    info!("Movie cache miss for {}", key);
+    let movie = metadata.movie_by_title(title, None).await?;
+    store(db_pool, &key, &movie);
+
+    // Save the id lookup too since we already have the movie
+    if let Some(movie) = &movie {
+        store(db_pool, &id_key(&movie.imdb_id), &Some(movie.clone()));
+    }
+
+    Ok(movie)
+}
+
+// First page of movies matching the title
+pub async fn search(
+    db_pool: &KekPool,
+    config: &Config,
+    metadata: &dyn MetadataProvider,
+    title: &str,
+    year: Option<i32>,
+) -> Result<Vec<SearchMatch>> {
+    let key = search_key(title, year);
+
+    if let Some(matches) = cached::<Vec<SearchMatch>>(db_pool, config, &key) {
+        return Ok(matches.unwrap_or_default());
+    }
+
+    info!("Movie cache miss for {}", key);
+    let matches = metadata.search(title, year).await?;
+    store(db_pool, &key, &Some(matches.clone()));
+
+    Ok(matches)
+}
@@ -0,0 +1,147 @@
+use std::str::FromStr;
+
+use serenity::model::id::{GuildId, UserId};
+use serenity::prelude::*;
+use tracing::info;
+
+use kekw_db::{command_permissions, KekPool};
+
+use crate::error::Error;
+use crate::settings;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Sync + Send>>;
+
+// Who can run a movie command
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Level {
+    Everyone,
+    // Members with the guild's admin role, or Manage Server
+    Admin,
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "everyone" => Ok(Level::Everyone),
+            "admin" => Ok(Level::Admin),
+            _ => Err(format!("Unknown permission level {}, expected everyone or admin", s)),
+        }
+    }
+}
+
+impl ToString for Level {
+    fn to_string(&self) -> String {
+        match self {
+            Level::Everyone => String::from("everyone"),
+            Level::Admin => String::from("admin"),
+        }
+    }
+}
+
+// Every movie command a guild can change the permission of, and who can run it by default.
+// config and permissions always need Manage Server and cacheinfo is for the bot owner.
+// schedule is for showing the schedule, changing it needs setschedule too.
+pub const DEFAULTS: [(&str, Level); 18] = [
+    ("submit", Level::Everyone),
+    ("getsubs", Level::Everyone),
+    ("deletesub", Level::Admin),
+    ("roll", Level::Admin),
+    ("vote", Level::Everyone),
+    ("startperiod", Level::Admin),
+    ("reopenperiod", Level::Admin),
+    ("endperiod", Level::Admin),
+    ("listperiods", Level::Everyone),
+    ("history", Level::Everyone),
+    ("verifyroll", Level::Everyone),
+    ("info", Level::Everyone),
+    ("fixdb", Level::Admin),
+    ("schedule", Level::Everyone),
+    ("setschedule", Level::Admin),
+    ("notify", Level::Everyone),
+    ("carryover", Level::Everyone),
+    ("audit", Level::Admin),
+];
+
+fn default_level(command: &str) -> Option<Level> {
+    DEFAULTS
+        .iter()
+        .find(|(name, _)| *name == command)
+        .map(|(_, level)| *level)
+}
+
+// Who can run each command in the guild, with the guild's changes applied
+pub fn table(db_pool: &KekPool, guild_id: GuildId) -> Result<Vec<(&'static str, Level)>> {
+    let mut table: Vec<(&'static str, Level)> = DEFAULTS.to_vec();
+
+    for row in command_permissions::get_permissions(db_pool, &guild_id.to_string())? {
+        if let (Some(entry), Ok(level)) = (
+            table.iter_mut().find(|(name, _)| *name == row.command),
+            row.level.parse::<Level>(),
+        ) {
+            entry.1 = level;
+        }
+    }
+
+    Ok(table)
+}
+
+pub fn set_level(db_pool: &KekPool, guild_id: GuildId, command: &str, level: Level) -> std::result::Result<(), Error> {
+    if default_level(command).is_none() {
+        return Err(Error::UserInput(format!("`{}` isn't a movie command with a permission to change.", command)));
+    }
+
+    command_permissions::set_permission(db_pool, &guild_id.to_string(), command, &level.to_string())?;
+    info!("{} in guild {} can now be run by {}", command, guild_id, level.to_string());
+
+    Ok(())
+}
+
+// Check that the user can run the command, anything without an entry in the table is open to everyone
+pub async fn authorize(
+    ctx: &Context,
+    db_pool: &KekPool,
+    guild_id: Option<GuildId>,
+    user_id: UserId,
+    command: &str,
+) -> std::result::Result<(), Error> {
+    let guild_id = match guild_id {
+        Some(guild_id) => guild_id,
+        // There's no admin role to check outside a server
+        None if default_level(command) == Some(Level::Admin) => {
+            return Err(Error::Denied(format!("`{}` can only be run in a server.", command)));
+        }
+        None => return Ok(()),
+    };
+
+    let level = table(db_pool, guild_id)
+        .map_err(Error::from_boxed)?
+        .into_iter()
+        .find(|(name, _)| *name == command)
+        .map(|(_, level)| level);
+    if level != Some(Level::Admin) {
+        return Ok(());
+    }
+
+    let guild_settings = settings::load(db_pool, guild_id).map_err(Error::from_boxed)?;
+    let member = guild_id.member(ctx, user_id).await?;
+
+    // Server managers can always run admin commands so a guild can't lock itself out
+    if member.permissions(ctx).await?.manage_guild() {
+        return Ok(());
+    }
+    if let Some(admin_role) = guild_settings.admin_role {
+        if member.roles.contains(&admin_role) {
+            return Ok(());
+        }
+    }
+
+    Err(Error::Denied(match guild_settings.admin_role {
+        Some(_) => format!("Only movie admins can run `{}`.", command),
+        None => format!(
+            "Only server managers can run `{}` until a movie admin role is set with `!m config admin`.",
+            command
+        ),
+    }))
+}
@@ -0,0 +1,181 @@
+use diesel::result::Error::NotFound;
+use serenity::model::id::{ChannelId, GuildId, RoleId};
+use serenity::prelude::*;
+use tracing::{info, warn};
+
+use kekw_db::models::guild_setting::GuildSetting;
+use kekw_db::{guild_settings, periods, schedules, KekPool};
+
+use crate::config::Config;
+use crate::error::Error;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Sync + Send>>;
+
+// Where a guild holds movie night and who gets pinged for it
+#[derive(Clone, Debug)]
+pub struct GuildSettings {
+    pub guild_id: GuildId,
+    pub movie_channel: Option<ChannelId>,
+    pub notify_role: Option<RoleId>,
+    // Can run the admin movie commands, see permissions.rs
+    pub admin_role: Option<RoleId>,
+    // Audit entries are posted here as well as stored
+    pub log_channel: Option<ChannelId>,
+    // How many periods back rolls favour submitters who weren't picked, None rolls evenly
+    pub weighting: Option<u32>,
+    // How many periods in a row an unpicked submission is carried into the next, None doesn't carry them
+    pub carry_over: Option<u32>,
+}
+
+impl GuildSettings {
+    fn empty(guild_id: GuildId) -> Self {
+        GuildSettings {
+            guild_id,
+            movie_channel: None,
+            notify_role: None,
+            admin_role: None,
+            log_channel: None,
+            weighting: None,
+            carry_over: None,
+        }
+    }
+
+    // Where scheduled announcements go
+    pub fn channel(&self) -> std::result::Result<ChannelId, Error> {
+        self.movie_channel.ok_or_else(|| {
+            Error::Config(String::from("no movie channel is set for this server, use `!m config channel`"))
+        })
+    }
+
+    fn from_row(guild_id: GuildId, row: GuildSetting) -> Self {
+        // Discord ids are stored as strings since postgresql doesnt have u64
+        GuildSettings {
+            guild_id,
+            movie_channel: row
+                .movie_channel
+                .and_then(|id| id.parse::<u64>().ok())
+                .map(ChannelId),
+            notify_role: row
+                .notify_role
+                .and_then(|id| id.parse::<u64>().ok())
+                .map(RoleId),
+            admin_role: row
+                .admin_role
+                .and_then(|id| id.parse::<u64>().ok())
+                .map(RoleId),
+            log_channel: row
+                .log_channel
+                .and_then(|id| id.parse::<u64>().ok())
+                .map(ChannelId),
+            weighting: row
+                .weight_periods
+                .filter(|periods| *periods > 0)
+                .map(|periods| periods as u32),
+            carry_over: row
+                .max_carry_over
+                .filter(|count| *count > 0)
+                .map(|count| count as u32),
+        }
+    }
+}
+
+// Settings for a guild, empty if it hasn't been set up yet
+pub fn load(db_pool: &KekPool, guild_id: GuildId) -> Result<GuildSettings> {
+    match guild_settings::get_setting(db_pool, &guild_id.to_string()) {
+        Ok(row) => Ok(GuildSettings::from_row(guild_id, row)),
+        Err(NotFound) => Ok(GuildSettings::empty(guild_id)),
+        Err(why) => Err(why.into()),
+    }
+}
+
+// Every guild with a movie channel, which is every guild the scheduler runs for
+pub fn configured(db_pool: &KekPool) -> Result<Vec<GuildSettings>> {
+    let mut configured = Vec::new();
+
+    for row in guild_settings::get_settings(db_pool)? {
+        let guild_id = match row.guild_id.parse::<u64>() {
+            Ok(guild_id) => GuildId(guild_id),
+            Err(_) => {
+                warn!("Ignoring settings for invalid guild id {}", row.guild_id);
+                continue;
+            }
+        };
+
+        let settings = GuildSettings::from_row(guild_id, row);
+        if settings.movie_channel.is_some() {
+            configured.push(settings);
+        }
+    }
+
+    Ok(configured)
+}
+
+pub fn set_movie_channel(db_pool: &KekPool, guild_id: GuildId, channel_id: ChannelId) -> Result<()> {
+    guild_settings::set_movie_channel(db_pool, &guild_id.to_string(), &channel_id.to_string())?;
+    Ok(())
+}
+
+pub fn set_notify_role(db_pool: &KekPool, guild_id: GuildId, role_id: RoleId) -> Result<()> {
+    guild_settings::set_notify_role(db_pool, &guild_id.to_string(), &role_id.to_string())?;
+    Ok(())
+}
+
+pub fn set_admin_role(db_pool: &KekPool, guild_id: GuildId, role_id: RoleId) -> Result<()> {
+    guild_settings::set_admin_role(db_pool, &guild_id.to_string(), &role_id.to_string())?;
+    Ok(())
+}
+
+pub fn set_log_channel(db_pool: &KekPool, guild_id: GuildId, channel_id: ChannelId) -> Result<()> {
+    guild_settings::set_log_channel(db_pool, &guild_id.to_string(), &channel_id.to_string())?;
+    Ok(())
+}
+
+pub fn set_weighting(db_pool: &KekPool, guild_id: GuildId, periods: Option<u32>) -> Result<()> {
+    guild_settings::set_weight_periods(db_pool, &guild_id.to_string(), periods.map(|periods| periods as i32))?;
+    Ok(())
+}
+
+pub fn set_carry_over(db_pool: &KekPool, guild_id: GuildId, count: Option<u32>) -> Result<()> {
+    guild_settings::set_max_carry_over(db_pool, &guild_id.to_string(), count.map(|count| count as i32))?;
+    Ok(())
+}
+
+// The config file can name a movie channel, notify role and admin role for single server setups.
+// They become that server's settings the first time, and it takes over the periods
+// and schedule stored before movie night was per guild.
+pub async fn seed(ctx: &Context, db_pool: &KekPool, config: &Config) -> Result<()> {
+    let channel_id = match config.movie_channel {
+        Some(channel_id) => channel_id,
+        None => return Ok(()),
+    };
+
+    let guild_id = match ctx.cache.guild_channel(channel_id).await {
+        Some(channel) => channel.guild_id,
+        None => {
+            warn!("Configured movie channel {} is not in a server the bot is in", channel_id);
+            return Ok(());
+        }
+    };
+
+    let current = load(db_pool, guild_id)?;
+    if current.movie_channel.is_none() {
+        set_movie_channel(db_pool, guild_id, channel_id)?;
+        info!("Using configured movie channel {} for guild {}", channel_id, guild_id);
+    }
+    if let (None, Some(role_id)) = (current.notify_role, config.notify_role) {
+        set_notify_role(db_pool, guild_id, role_id)?;
+        info!("Using configured notify role {} for guild {}", role_id, guild_id);
+    }
+    if let (None, Some(role_id)) = (current.admin_role, config.admin_role) {
+        set_admin_role(db_pool, guild_id, role_id)?;
+        info!("Using configured admin role {} for guild {}", role_id, guild_id);
+    }
+
+    let claimed = periods::claim_unscoped(db_pool, &guild_id.to_string())?;
+    if claimed > 0 {
+        info!("Moved {} existing submission period(s) to guild {}", claimed, guild_id);
+    }
+    schedules::claim_unscoped(db_pool, &guild_id.to_string())?;
+
+    Ok(())
+}
@@ -0,0 +1,144 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serenity::futures::future::BoxFuture;
+use tokio::sync::{watch, Mutex};
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Sync + Send>>;
+
+// Restarts wait twice as long after every failure, up to the max
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+// A job that ran this long before failing starts over from the shortest wait
+const HEALTHY_AFTER: Duration = Duration::from_secs(10 * 60);
+// How long shutdown waits for jobs to finish what they are doing
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(30);
+
+// Handed to every job so it can stop between units of work when the bot shuts down
+#[derive(Clone)]
+pub struct Shutdown(watch::Receiver<bool>);
+
+impl Shutdown {
+    pub fn is_requested(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    // Resolves once shutdown has started
+    pub async fn requested(&mut self) {
+        while !self.is_requested() {
+            if self.0.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+// Runs the bot's background jobs by name and keeps them running
+pub struct Supervisor {
+    sender: watch::Sender<bool>,
+    receiver: watch::Receiver<bool>,
+    running: Arc<Mutex<HashSet<&'static str>>>,
+    handles: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        let (sender, receiver) = watch::channel(false);
+
+        Supervisor {
+            sender,
+            receiver,
+            running: Arc::new(Mutex::new(HashSet::new())),
+            handles: Mutex::new(Vec::new()),
+        }
+    }
+
+    // Start a job, restarting it with backoff whenever it fails or panics until shutdown.
+    // Jobs that return Ok are done and aren't restarted. Starting a job that is already
+    // running under the same name does nothing, so this is safe to call on reconnects.
+    pub async fn spawn<F>(&self, name: &'static str, job: F)
+    where
+        F: Fn(Shutdown) -> BoxFuture<'static, Result<()>> + Send + Sync + 'static,
+    {
+        if self.is_shutting_down() {
+            warn!("Not starting job {}, shutting down", name);
+            return;
+        }
+        if !self.running.lock().await.insert(name) {
+            info!("Job {} is already running", name);
+            return;
+        }
+
+        let shutdown = Shutdown(self.receiver.clone());
+        let running = self.running.clone();
+
+        let handle = tokio::spawn(async move {
+            supervise(name, job, shutdown).await;
+            running.lock().await.remove(name);
+        });
+
+        self.handles.lock().await.push(handle);
+        info!("Started job {}", name);
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    // Tell every job to stop and wait for the work they are in the middle of
+    pub async fn shutdown(&self) {
+        info!("Stopping background jobs");
+        if self.sender.send(true).is_err() {
+            return;
+        }
+
+        let handles: Vec<JoinHandle<()>> = self.handles.lock().await.drain(..).collect();
+        for handle in handles {
+            if tokio::time::timeout(SHUTDOWN_GRACE, handle).await.is_err() {
+                warn!("A job didn't stop within {} seconds", SHUTDOWN_GRACE.as_secs());
+            }
+        }
+
+        info!("Background jobs stopped");
+    }
+}
+
+async fn supervise<F>(name: &'static str, job: F, mut shutdown: Shutdown)
+where
+    F: Fn(Shutdown) -> BoxFuture<'static, Result<()>> + Send + Sync + 'static,
+{
+    let mut backoff = MIN_BACKOFF;
+
+    loop {
+        let started = Instant::now();
+
+        // Run the job in its own task so a panic is caught here instead of ending supervision
+        match tokio::spawn(job(shutdown.clone())).await {
+            Ok(Ok(())) => {
+                info!("Job {} finished", name);
+                return;
+            }
+            Ok(Err(why)) => error!("Job {} failed: {:?}", name, why),
+            Err(why) => error!("Job {} panicked: {:?}", name, why),
+        }
+
+        if shutdown.is_requested() {
+            return;
+        }
+
+        if started.elapsed() >= HEALTHY_AFTER {
+            backoff = MIN_BACKOFF;
+        }
+        warn!("Restarting job {} in {} seconds", name, backoff.as_secs());
+
+        tokio::select! {
+            _ = tokio::time::sleep(backoff) => (),
+            _ = shutdown.requested() => return,
+        }
+
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
@@ -0,0 +1,716 @@
+// End to end tests of movie night against the fake Discord and database in backend::fake
+use chrono::Utc;
+use serenity::model::channel::ReactionType;
+use serenity::model::id::{ChannelId, GuildId, MessageId, RoleId, UserId};
+
+use crate::backend::fake::{Fakes, Sent};
+use crate::backend::{Discord, Store};
+use crate::config::Config;
+use crate::movie_night;
+use crate::settings::GuildSettings;
+use crate::timed::jobs::{self, Selected};
+use crate::timed::tie_break::{self, PendingTieBreak};
+use crate::voting::{self, VotingMode, Weights};
+
+const GUILD: GuildId = GuildId(1);
+const CHANNEL: ChannelId = ChannelId(2);
+const NOTIFY_ROLE: RoleId = RoleId(3);
+const HOST_ROLE: RoleId = RoleId(4);
+const ALICE: UserId = UserId(10);
+const BOB: UserId = UserId(11);
+const CAROL: UserId = UserId(12);
+
+// The defaults with anything extra appended
+fn config_with(extra: &str) -> Config {
+    load_config(extra).unwrap()
+}
+
+fn load_config(extra: &str) -> Result<Config, String> {
+    Config::from_toml(&format!(
+        r#"
+        discord_token = "test"
+        host_role = {}
+
+        [metadata]
+        omdb_api_key = "test"
+
+        {}
+        "#,
+        HOST_ROLE, extra
+    ))
+}
+
+fn config() -> Config {
+    config_with("")
+}
+
+fn guild_settings() -> GuildSettings {
+    GuildSettings {
+        guild_id: GUILD,
+        movie_channel: Some(CHANNEL),
+        notify_role: Some(NOTIFY_ROLE),
+        admin_role: None,
+        log_channel: None,
+        weighting: None,
+        carry_over: None,
+    }
+}
+
+// A guild with an open period and Alice and Bob's submissions
+fn submitted() -> Fakes {
+    let fakes = Fakes::new();
+    fakes.discord.add_user(ALICE, "alice", Some("Al"));
+    fakes.discord.add_user(BOB, "bob", None);
+
+    fakes.store.create_period(GUILD, 42).unwrap();
+    let period = fakes.store.open_period(GUILD).unwrap().unwrap();
+    movie_night::submit(&fakes.store, &period, ALICE, "Heat", "tt0113277").unwrap();
+    movie_night::submit(&fakes.store, &period, BOB, "Ronin", "tt0122690").unwrap();
+
+    fakes
+}
+
+// A decided period before the current one where Alice's movie won
+fn picked_last_period(fakes: &Fakes) {
+    fakes.store.create_period(GUILD, 1).unwrap();
+    let period = fakes.store.open_period(GUILD).unwrap().unwrap();
+    movie_night::submit(&fakes.store, &period, ALICE, "Collateral", "tt0369339").unwrap();
+    let period = fakes.store.end_period(period).unwrap();
+
+    let picked = fakes.store.user_submission(&period, ALICE).unwrap().unwrap();
+    let roll = fakes
+        .store
+        .create_roll(&period, &[picked.id], VotingMode::Plurality, 1, None)
+        .unwrap();
+    fakes.store.set_winner(roll, picked.id, Utc::now().naive_utc()).unwrap();
+}
+
+// Roll and return the voting message along with each submission's emoji
+async fn rolled(fakes: &Fakes, config: &Config) -> (MessageId, Vec<(String, ReactionType)>) {
+    let vote_msg_id = jobs::roll_period(fakes.backend(), config, &guild_settings(), CHANNEL)
+        .await
+        .unwrap()
+        .unwrap();
+
+    (vote_msg_id, rolled_choices(fakes))
+}
+
+// Each submission in the last roll along with its emoji, in roll order
+fn rolled_choices(fakes: &Fakes) -> Vec<(String, ReactionType)> {
+    let period = fakes.store.closed_period(GUILD).unwrap().unwrap();
+    let roll = fakes.store.roll(&period).unwrap().unwrap();
+    let mut choices = Vec::new();
+    for selection in fakes.store.selections(&roll).unwrap() {
+        let title = fakes.store.submission(selection.submission_id).unwrap().title;
+        let emoji = selection.emote.unwrap().parse::<ReactionType>().unwrap();
+        choices.push((title, emoji));
+    }
+
+    choices
+}
+
+#[tokio::test]
+async fn submit_replaces_earlier_submission() {
+    let fakes = submitted();
+    let period = fakes.store.open_period(GUILD).unwrap().unwrap();
+
+    let replaced = movie_night::submit(&fakes.store, &period, ALICE, "Thief", "tt0083190").unwrap();
+
+    assert_eq!(replaced.map(|submission| submission.title), Some(String::from("Heat")));
+    let titles: Vec<String> = fakes
+        .store
+        .submissions(&period)
+        .unwrap()
+        .into_iter()
+        .map(|submission| submission.title)
+        .collect();
+    assert_eq!(titles, vec!["Thief", "Ronin"]);
+}
+
+#[tokio::test]
+async fn submit_command_adds_a_linked_movie() {
+    let fakes = submitted();
+    fakes.catalog.add_movie("tt0133093", "The Matrix", 1999);
+
+    let link = "https://www.imdb.com/title/tt0133093/";
+    let entry = movie_night::submit_movie(fakes.backend(), GUILD, CHANNEL, CAROL, link)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(entry.action, "submit");
+    assert_eq!(entry.after, Some(String::from("The Matrix")));
+    let period = fakes.store.open_period(GUILD).unwrap().unwrap();
+    let submission = fakes.store.user_submission(&period, CAROL).unwrap().unwrap();
+    assert_eq!((submission.title.as_str(), submission.link.as_str()), ("The Matrix", "tt0133093"));
+    assert_eq!(
+        fakes.discord.messages().last(),
+        Some(&String::from("You've submitted the movie: The Matrix"))
+    );
+}
+
+#[tokio::test]
+async fn submit_command_asks_before_replacing() {
+    let fakes = submitted();
+    fakes.catalog.add_movie("tt0083190", "Thief", 1981);
+    let period = fakes.store.open_period(GUILD).unwrap().unwrap();
+
+    // Alice picks the only match for her search, then keeps the movie she had
+    fakes.discord.will_choose(0);
+    fakes.discord.will_confirm(false);
+    let kept = movie_night::submit_movie(fakes.backend(), GUILD, CHANNEL, ALICE, "thief").await.unwrap();
+
+    assert!(kept.is_none());
+    assert_eq!(fakes.discord.embeds().last().unwrap().title, "Which movie did you mean by thief?");
+    assert_eq!(fakes.discord.messages().last(), Some(&String::from("Submission not updated.")));
+    assert_eq!(fakes.store.user_submission(&period, ALICE).unwrap().unwrap().title, "Heat");
+
+    fakes.discord.will_choose(0);
+    fakes.discord.will_confirm(true);
+    let entry = movie_night::submit_movie(fakes.backend(), GUILD, CHANNEL, ALICE, "thief")
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(entry.before, Some(String::from("Heat")));
+    assert_eq!(entry.after, Some(String::from("Thief")));
+    assert_eq!(fakes.store.user_submission(&period, ALICE).unwrap().unwrap().title, "Thief");
+}
+
+#[tokio::test]
+async fn submit_command_needs_a_match() {
+    let fakes = submitted();
+
+    let submitted = movie_night::submit_movie(fakes.backend(), GUILD, CHANNEL, CAROL, "Nothing Like It").await.unwrap();
+
+    assert!(submitted.is_none());
+    assert_eq!(
+        fakes.discord.messages().last(),
+        Some(&String::from("Couldn't find Nothing Like It, check the spelling and try again."))
+    );
+}
+
+#[tokio::test]
+async fn getsubs_command_posts_the_open_period() {
+    let fakes = submitted();
+
+    movie_night::post_submissions(fakes.backend(), &guild_settings(), CHANNEL).await.unwrap();
+
+    let embeds = fakes.discord.embeds();
+    assert_eq!(embeds.len(), 1);
+    assert_eq!(embeds[0].title, "Current Movie Submissions");
+    assert_eq!(embeds[0].fields.len(), 2);
+}
+
+#[tokio::test]
+async fn roll_command_rolls_once_confirmed() {
+    let fakes = submitted();
+
+    // Not answering cancels the roll
+    let rolled = movie_night::roll_movies(fakes.backend(), &config(), &guild_settings(), CHANNEL, ALICE)
+        .await
+        .unwrap();
+    assert!(rolled.is_none());
+    assert!(fakes.store.open_period(GUILD).unwrap().is_some());
+    assert_eq!(fakes.discord.messages().last(), Some(&String::from("Cancelling roll")));
+
+    fakes.discord.will_confirm(true);
+    let entry = movie_night::roll_movies(fakes.backend(), &config(), &guild_settings(), CHANNEL, ALICE)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(entry.action, "roll");
+    assert!(entry.after.unwrap().ends_with("with seed 42"));
+    assert!(fakes.store.open_period(GUILD).unwrap().is_none());
+    assert_eq!(fakes.discord.embeds().last().unwrap().title, "Movie Voting!");
+}
+
+#[tokio::test]
+async fn getsubs_lists_submitters() {
+    let fakes = submitted();
+    let period = fakes.store.open_period(GUILD).unwrap().unwrap();
+
+    let fields = movie_night::submission_fields(fakes.backend(), &guild_settings(), &period).await.unwrap();
+
+    assert_eq!(
+        fields,
+        vec![
+            (String::from("Al(alice)"), String::from("Heat")),
+            (String::from("bob"), String::from("Ronin")),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn getsubs_fits_in_one_embed() {
+    let fakes = Fakes::new();
+    fakes.store.create_period(GUILD, 42).unwrap();
+    let period = fakes.store.open_period(GUILD).unwrap().unwrap();
+    for i in 0..30 {
+        let user_id = UserId(100 + i);
+        fakes.discord.add_user(user_id, &format!("user{}", i), None);
+        movie_night::submit(&fakes.store, &period, user_id, &"Long Title ".repeat(200), &format!("tt{}", i)).unwrap();
+    }
+
+    let fields = movie_night::submission_fields(fakes.backend(), &guild_settings(), &period).await.unwrap();
+
+    let size: usize = fields.iter().map(|(name, value)| name.chars().count() + value.chars().count()).sum();
+    assert!(size <= 6000 - 256);
+    assert!(fields.len() <= 25);
+    assert!(fields.iter().all(|(_, value)| value.chars().count() <= 1024));
+    assert_eq!(fields.last().unwrap().1, format!("and {} more", 30 - (fields.len() - 1)));
+}
+
+#[tokio::test]
+async fn vote_message_fits_long_titles() {
+    let fakes = Fakes::new();
+    fakes.discord.add_user(ALICE, "alice", None);
+    fakes.discord.add_user(BOB, "bob", None);
+    fakes.store.create_period(GUILD, 42).unwrap();
+    let period = fakes.store.open_period(GUILD).unwrap().unwrap();
+    movie_night::submit(&fakes.store, &period, ALICE, &"Heat ".repeat(500), "tt0113277").unwrap();
+    movie_night::submit(&fakes.store, &period, BOB, &"Ronin ".repeat(500), "tt0122690").unwrap();
+
+    rolled(&fakes, &config()).await;
+
+    // Both movies are still on the message, just shortened
+    let vote = fakes.discord.embeds().pop().unwrap();
+    assert_eq!(vote.fields.len(), 2);
+    assert!(vote
+        .fields
+        .iter()
+        .all(|(name, value)| name.chars().count() <= 256 && value.chars().count() <= 1024));
+}
+
+#[tokio::test]
+async fn roll_needs_enough_submissions() {
+    let fakes = Fakes::new();
+    fakes.discord.add_user(ALICE, "alice", None);
+    fakes.store.create_period(GUILD, 42).unwrap();
+    let period = fakes.store.open_period(GUILD).unwrap().unwrap();
+    movie_night::submit(&fakes.store, &period, ALICE, "Heat", "tt0113277").unwrap();
+
+    let vote_msg = jobs::roll_period(fakes.backend(), &config(), &guild_settings(), CHANNEL)
+        .await
+        .unwrap();
+
+    assert!(vote_msg.is_none());
+    assert!(fakes.store.open_period(GUILD).unwrap().is_some());
+    assert_eq!(
+        fakes.discord.messages(),
+        vec!["Not enough movies submitted to choose from, submissions are staying open!"]
+    );
+}
+
+#[tokio::test]
+async fn roll_closes_period_and_starts_vote() {
+    let fakes = submitted();
+
+    let (vote_msg_id, choices) = rolled(&fakes, &config()).await;
+
+    assert!(fakes.store.open_period(GUILD).unwrap().is_none());
+    let period = fakes.store.closed_period(GUILD).unwrap().unwrap();
+    assert_eq!(period.vote_message, Some(vote_msg_id.to_string()));
+
+    // The notify role is pinged and nobody else
+    let posted = fakes.discord.posted();
+    assert!(posted.iter().all(|posted| posted.channel_id == CHANNEL));
+    assert!(matches!(
+        &posted[0].sent,
+        Sent::Message { ping: Some(role), .. } if *role == NOTIFY_ROLE
+    ));
+
+    let embeds = fakes.discord.embeds();
+    assert_eq!(embeds.len(), 1);
+    assert_eq!(embeds[0].title, "Movie Voting!");
+    let fields: Vec<&str> = embeds[0].fields.iter().map(|(name, _)| name.as_str()).collect();
+    let titles: Vec<&str> = choices.iter().map(|(title, _)| title.as_str()).collect();
+    assert_eq!(fields, titles);
+}
+
+#[tokio::test]
+async fn select_movie_announces_most_votes_once() {
+    let fakes = submitted();
+    let (vote_msg_id, choices) = rolled(&fakes, &config()).await;
+    let (winner, emoji) = &choices[1];
+    fakes.discord.add_reaction(vote_msg_id, emoji);
+    fakes.discord.add_reaction(vote_msg_id, emoji);
+
+    let announced = jobs::select_winner(fakes.backend(), &config(), &guild_settings()).await.unwrap();
+
+    assert!(matches!(announced, Selected::Announced(_)));
+    assert_eq!(fakes.discord.messages().last(), Some(&format!("<@&{}> {} wins!", NOTIFY_ROLE, winner)));
+
+    let period = fakes.store.closed_period(GUILD).unwrap().unwrap();
+    let roll = fakes.store.roll(&period).unwrap().unwrap();
+    let winner_id = roll.winner.unwrap();
+    assert_eq!(&fakes.store.submission(winner_id).unwrap().title, winner);
+    // The bot's own reactions are counted too
+    let mut votes: Vec<i32> = fakes.store.results(&roll).into_iter().map(|(_, votes)| votes).collect();
+    votes.sort();
+    assert_eq!(votes, vec![1, 3]);
+
+    // Running again, as a retry would, doesn't announce a second time
+    let sent = fakes.discord.posted().len();
+    let announced = jobs::select_winner(fakes.backend(), &config(), &guild_settings()).await.unwrap();
+    assert!(matches!(announced, Selected::AlreadyAnnounced));
+    assert_eq!(fakes.discord.posted().len(), sent);
+}
+
+#[tokio::test]
+async fn failed_winner_announcement_is_sent_on_retry() {
+    let fakes = submitted();
+    let (vote_msg_id, choices) = rolled(&fakes, &config()).await;
+    let (winner, emoji) = &choices[0];
+    fakes.discord.add_reaction(vote_msg_id, emoji);
+
+    fakes.discord.set_failing(true);
+    assert!(jobs::select_winner(fakes.backend(), &config(), &guild_settings()).await.is_err());
+    fakes.discord.set_failing(false);
+
+    let announced = jobs::select_winner(fakes.backend(), &config(), &guild_settings()).await.unwrap();
+    assert!(matches!(announced, Selected::Announced(_)));
+    assert_eq!(fakes.discord.messages().last(), Some(&format!("<@&{}> {} wins!", NOTIFY_ROLE, winner)));
+}
+
+#[tokio::test]
+async fn roll_resumes_when_the_vote_failed_to_post() {
+    let fakes = submitted();
+
+    fakes.discord.set_failing(true);
+    assert!(jobs::roll_period(fakes.backend(), &config(), &guild_settings(), CHANNEL).await.is_err());
+    fakes.discord.set_failing(false);
+    let period = fakes.store.closed_period(GUILD).unwrap().unwrap();
+    let roll_id = fakes.store.roll(&period).unwrap().unwrap().id;
+
+    // The retry puts the vote up on the roll that was already stored
+    let (vote_msg_id, _) = rolled(&fakes, &config()).await;
+
+    let period = fakes.store.closed_period(GUILD).unwrap().unwrap();
+    assert_eq!(period.vote_message, Some(vote_msg_id.to_string()));
+    assert_eq!(fakes.store.roll(&period).unwrap().unwrap().id, roll_id);
+    assert_eq!(fakes.discord.embeds().len(), 1);
+    assert!(fakes.discord.messages().last().unwrap().contains("announced when the period opened"));
+
+    // Once the vote is up there's nothing left to roll
+    let rolled_again = jobs::roll_period(fakes.backend(), &config(), &guild_settings(), CHANNEL).await.unwrap();
+    assert!(rolled_again.is_none());
+}
+
+#[tokio::test]
+async fn roll_finishes_a_vote_that_failed_after_posting() {
+    let fakes = submitted();
+
+    // The ping and the voting message go out, then reacting fails
+    fakes.discord.fail_after(2);
+    assert!(jobs::roll_period(fakes.backend(), &config(), &guild_settings(), CHANNEL).await.is_err());
+    fakes.discord.set_failing(false);
+
+    let retried = jobs::roll_period(fakes.backend(), &config(), &guild_settings(), CHANNEL).await.unwrap();
+    assert!(retried.is_none());
+    assert_eq!(fakes.discord.embeds().len(), 1);
+
+    // The retry reacts to the message that went out and reveals the seed, both only once
+    let choices = rolled_choices(&fakes);
+    let vote_msg_id = fakes.discord.posted()[1].message_id;
+    let emojis: Vec<ReactionType> = choices.into_iter().map(|(_, emoji)| emoji).collect();
+    let counts = fakes.discord.reaction_counts(CHANNEL, vote_msg_id, &emojis).await.unwrap();
+    assert_eq!(counts, vec![1; emojis.len()]);
+
+    jobs::roll_period(fakes.backend(), &config(), &guild_settings(), CHANNEL).await.unwrap();
+    let reveals = fakes
+        .discord
+        .messages()
+        .into_iter()
+        .filter(|message| message.starts_with("Rolled with seed"))
+        .count();
+    assert_eq!(reveals, 1);
+}
+
+#[tokio::test]
+async fn select_movie_breaks_ties() {
+    let fakes = submitted();
+    rolled(&fakes, &config()).await;
+
+    jobs::select_winner(fakes.backend(), &config(), &guild_settings()).await.unwrap();
+
+    // Nobody voted, so the default coin flip picks one of the two
+    let period = fakes.store.closed_period(GUILD).unwrap().unwrap();
+    let roll = fakes.store.roll(&period).unwrap().unwrap();
+    assert!(roll.winner.is_some());
+    assert_eq!(roll.tie_break, Some(String::from("coinflip")));
+    assert!(fakes.discord.messages().iter().any(|message| message.ends_with("tied!")));
+
+    // Anyone can flip again with the recorded seed and get the same movie
+    let seed = roll.tie_break_seed.unwrap().parse::<u64>().unwrap();
+    let tied = fakes.store.submissions(&period).unwrap();
+    assert_eq!(tie_break::flip(&tied, seed).unwrap().id, roll.winner.unwrap());
+}
+
+#[tokio::test]
+async fn ranked_vote_uses_instant_runoff() {
+    let config = config_with("[voting]\nmode = \"ranked\"");
+    let fakes = submitted();
+    let (_, choices) = rolled(&fakes, &config).await;
+
+    let period = fakes.store.closed_period(GUILD).unwrap().unwrap();
+    let roll = fakes.store.roll(&period).unwrap().unwrap();
+    let ids: Vec<i32> = fakes
+        .store
+        .selections(&roll)
+        .unwrap()
+        .iter()
+        .map(|selection| selection.submission_id)
+        .collect();
+    fakes.store.add_ballot(&roll, &[ids[1], ids[0]]);
+    fakes.store.add_ballot(&roll, &[ids[1], ids[0]]);
+    fakes.store.add_ballot(&roll, &[ids[0], ids[1]]);
+
+    jobs::select_winner(fakes.backend(), &config, &guild_settings()).await.unwrap();
+
+    let roll = fakes.store.roll(&period).unwrap().unwrap();
+    assert_eq!(roll.winner, Some(ids[1]));
+    assert!(fakes.discord.messages().last().unwrap().ends_with(&format!("{} wins!", choices[1].0)));
+}
+
+// Select the winner of a tie broken by the configured runoff or host pick, as the queue would
+async fn tie_broken(fakes: &Fakes, config: &Config, reactions: impl Fn(&PendingTieBreak)) -> Selected {
+    let pending = match jobs::select_winner(fakes.backend(), config, &guild_settings()).await.unwrap() {
+        Selected::TieBreak(pending) => pending,
+        selected => panic!("Expected a tie break to start, got {:?}", selected),
+    };
+
+    // Nothing is decided until the queued job closes it
+    let period = fakes.store.closed_period(GUILD).unwrap().unwrap();
+    assert_eq!(fakes.store.roll(&period).unwrap().unwrap().winner, None);
+
+    reactions(&pending);
+    jobs::tie_break_winner(fakes.backend(), config, &guild_settings(), &pending)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn runoff_breaks_ties() {
+    let config = config_with("[voting]\ntie_break = \"runoff\"");
+    let fakes = submitted();
+    rolled(&fakes, &config).await;
+
+    let selected = tie_broken(&fakes, &config, |pending| {
+        let (_, emoji) = &pending.choices[1];
+        fakes.discord.add_reaction(pending.message_id, emoji);
+    })
+    .await;
+    assert!(matches!(selected, Selected::Announced(_)));
+
+    let period = fakes.store.closed_period(GUILD).unwrap().unwrap();
+    let roll = fakes.store.roll(&period).unwrap().unwrap();
+    assert_eq!(roll.tie_break, Some(String::from("runoff")));
+
+    let runoff = fakes.discord.embeds().pop().unwrap();
+    assert_eq!(runoff.title, "Runoff Voting!");
+    let winner = fakes.store.submission(roll.winner.unwrap()).unwrap();
+    assert_eq!(runoff.fields[1].0, winner.title);
+}
+
+#[tokio::test]
+async fn retried_select_keeps_the_runoff_and_votes_it_started() {
+    let config = config_with("[voting]\ntie_break = \"runoff\"");
+    let fakes = submitted();
+    rolled(&fakes, &config).await;
+
+    let selected = jobs::select_winner(fakes.backend(), &config, &guild_settings()).await.unwrap();
+    assert!(matches!(selected, Selected::TieBreak(_)));
+    let sent = fakes.discord.posted().len();
+
+    // Running again, as a retry would, neither starts a second runoff nor stores the votes twice
+    let selected = jobs::select_winner(fakes.backend(), &config, &guild_settings()).await.unwrap();
+    assert!(matches!(selected, Selected::TieBreakStarted));
+    assert_eq!(fakes.discord.posted().len(), sent);
+
+    let period = fakes.store.closed_period(GUILD).unwrap().unwrap();
+    let roll = fakes.store.roll(&period).unwrap().unwrap();
+    assert_eq!(fakes.store.results(&roll).len(), 2);
+}
+
+#[tokio::test]
+async fn host_breaks_ties() {
+    let config = config_with("[voting]\ntie_break = \"host\"");
+    let fakes = submitted();
+    rolled(&fakes, &config).await;
+
+    // The host picks whichever movie got the first fallback letter
+    tie_broken(&fakes, &config, |_| {
+        let picked = "🇦".parse::<ReactionType>().unwrap();
+        fakes.discord.set_role_reaction(Some(picked));
+    })
+    .await;
+
+    let period = fakes.store.closed_period(GUILD).unwrap().unwrap();
+    let roll = fakes.store.roll(&period).unwrap().unwrap();
+    assert_eq!(roll.tie_break, Some(String::from("host")));
+    assert_eq!(roll.tie_break_seed, None);
+
+    let pick = fakes.discord.embeds().pop().unwrap();
+    assert_eq!(pick.title, "Movie Host Pick!");
+    let winner = fakes.store.submission(roll.winner.unwrap()).unwrap();
+    assert_eq!(pick.fields[0].0, winner.title);
+}
+
+#[tokio::test]
+async fn host_missing_the_pick_falls_back_to_a_coin_flip() {
+    let config = config_with("[voting]\ntie_break = \"host\"");
+    let fakes = submitted();
+    rolled(&fakes, &config).await;
+
+    tie_broken(&fakes, &config, |_| ()).await;
+
+    let period = fakes.store.closed_period(GUILD).unwrap().unwrap();
+    let roll = fakes.store.roll(&period).unwrap().unwrap();
+    assert!(roll.winner.is_some());
+    assert_eq!(roll.tie_break, Some(String::from("coinflip")));
+    assert!(fakes
+        .discord
+        .messages()
+        .iter()
+        .any(|message| message == "No movie host picked in time."));
+}
+
+#[tokio::test]
+async fn roll_can_be_verified_from_its_seed() {
+    let fakes = Fakes::new();
+    fakes.discord.add_user(ALICE, "alice", None);
+    fakes.discord.add_user(BOB, "bob", None);
+    fakes.discord.add_user(CAROL, "carol", None);
+
+    jobs::open_period(fakes.backend(), &config(), &guild_settings(), CHANNEL).await.unwrap();
+    let period = fakes.store.open_period(GUILD).unwrap().unwrap();
+    movie_night::submit(&fakes.store, &period, ALICE, "Heat", "tt0113277").unwrap();
+    movie_night::submit(&fakes.store, &period, BOB, "Ronin", "tt0122690").unwrap();
+    movie_night::submit(&fakes.store, &period, CAROL, "Thief", "tt0083190").unwrap();
+    rolled(&fakes, &config()).await;
+
+    let period = fakes.store.closed_period(GUILD).unwrap().unwrap();
+    let verification = voting::verify_roll(&fakes.store, &period).unwrap().unwrap();
+    assert!(verification.committed);
+    assert!(verification.matches());
+    assert_eq!(verification.stored.len(), 2);
+
+    // The hash posted when the period opened is of the seed revealed after the roll
+    let messages = fakes.discord.messages();
+    assert!(messages[0].contains(&voting::commitment(verification.seed)));
+    assert!(messages.last().unwrap().contains(&format!("seed {}", verification.seed)));
+}
+
+#[tokio::test]
+async fn weighted_roll_favours_submitters_not_picked_recently() {
+    let fakes = Fakes::new();
+    fakes.discord.add_user(ALICE, "alice", None);
+    fakes.discord.add_user(BOB, "bob", None);
+    fakes.discord.add_user(CAROL, "carol", None);
+    picked_last_period(&fakes);
+
+    fakes.store.create_period(GUILD, 42).unwrap();
+    let period = fakes.store.open_period(GUILD).unwrap().unwrap();
+    movie_night::submit(&fakes.store, &period, ALICE, "Heat", "tt0113277").unwrap();
+    movie_night::submit(&fakes.store, &period, BOB, "Ronin", "tt0122690").unwrap();
+    movie_night::submit(&fakes.store, &period, CAROL, "Thief", "tt0083190").unwrap();
+
+    let settings = GuildSettings {
+        weighting: Some(2),
+        ..guild_settings()
+    };
+
+    // Alice won last period, Bob and Carol haven't won in the two periods looked back over
+    let fields = movie_night::submission_fields(fakes.backend(), &settings, &period).await.unwrap();
+    let shown: Vec<&str> = fields.iter().map(|(_, details)| details.as_str()).collect();
+    assert_eq!(
+        shown,
+        vec!["Heat\nRoll weight: 1", "Ronin\nRoll weight: 3", "Thief\nRoll weight: 3"]
+    );
+
+    // The weights are kept with the roll, so turning weighting off afterwards doesn't change the check
+    jobs::roll_period(fakes.backend(), &config(), &settings, CHANNEL).await.unwrap();
+    let period = fakes.store.closed_period(GUILD).unwrap().unwrap();
+    let verification = voting::verify_roll(&fakes.store, &period).unwrap().unwrap();
+    assert_eq!(verification.weighting, Some(2));
+    assert!(verification.matches());
+}
+
+#[tokio::test]
+async fn new_period_carries_over_unpicked_submissions() {
+    let fakes = Fakes::new();
+    fakes.store.create_period(GUILD, 42).unwrap();
+    let period = fakes.store.open_period(GUILD).unwrap().unwrap();
+    movie_night::submit(&fakes.store, &period, ALICE, "Heat", "tt0113277").unwrap();
+    movie_night::submit(&fakes.store, &period, BOB, "Ronin", "tt0122690").unwrap();
+    movie_night::submit(&fakes.store, &period, CAROL, "Thief", "tt0083190").unwrap();
+
+    // Alice's movie wins and Carol doesn't want hers carried over
+    let period = fakes.store.end_period(period).unwrap();
+    let heat = fakes.store.user_submission(&period, ALICE).unwrap().unwrap();
+    let roll = fakes
+        .store
+        .create_roll(&period, &[heat.id], VotingMode::Plurality, 42, None)
+        .unwrap();
+    fakes.store.set_winner(roll, heat.id, Utc::now().naive_utc()).unwrap();
+    fakes.store.set_carry_over_opt_out(GUILD, CAROL, true).unwrap();
+
+    let settings = GuildSettings {
+        carry_over: Some(1),
+        ..guild_settings()
+    };
+    assert!(jobs::open_period(fakes.backend(), &config(), &settings, CHANNEL).await.unwrap());
+
+    let period = fakes.store.open_period(GUILD).unwrap().unwrap();
+    let carried = fakes.store.submissions(&period).unwrap();
+    assert_eq!(carried.len(), 1);
+    assert_eq!(carried[0].title, "Ronin");
+    assert_eq!(carried[0].carried_over, 1);
+    assert!(fakes
+        .discord
+        .messages()
+        .last()
+        .unwrap()
+        .starts_with("Carried over from last period: Ronin."));
+
+    // Ronin has now been carried over as many times as the guild allows
+    fakes.store.end_period(period).unwrap();
+    jobs::open_period(fakes.backend(), &config(), &settings, CHANNEL).await.unwrap();
+    let period = fakes.store.open_period(GUILD).unwrap().unwrap();
+    assert!(fakes.store.submissions(&period).unwrap().is_empty());
+}
+
+#[test]
+fn seeded_picks_ignore_submission_order() {
+    let fakes = submitted();
+    let period = fakes.store.open_period(GUILD).unwrap().unwrap();
+    let movie_subs = fakes.store.submissions(&period).unwrap();
+    let reversed: Vec<_> = movie_subs.iter().rev().cloned().collect();
+
+    let picks = |movie_subs: &[_]| -> Vec<i32> {
+        voting::pick_selections(movie_subs, 1, 7, &Weights::new())
+            .iter()
+            .map(|movie_sub| movie_sub.id)
+            .collect()
+    };
+    assert_eq!(picks(&movie_subs), picks(&reversed));
+}
+
+#[test]
+fn config_rejects_durations_that_arent_positive() {
+    for extra in &[
+        "[voting]\nhours = 0",
+        "[voting]\nrunoff_minutes = 0",
+        "[voting]\nhost_minutes = -5",
+        // Straight after the [metadata] table
+        "cache_ttl_hours = -1",
+        "[availability]\nttl_hours = 0",
+        "[notify]\nreminder_minutes = -30",
+    ] {
+        assert!(load_config(extra).is_err(), "{} was accepted", extra);
+    }
+
+    assert!(config_with("[notify]\nreminder_minutes = 0").notify.reminder.is_none());
+}
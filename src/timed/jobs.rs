@@ -1,78 +1,580 @@
+use std::collections::HashMap;
 use std::str::FromStr;
-use std::env;
+use std::sync::Arc;
+
+use chrono::{Duration, Utc};
 
 use serenity::client::Context;
-use serenity::model::id::ChannelId;
-use serenity::model::channel::Message;
-use serenity::model::id::MessageId;
 use serenity::model::channel::ReactionType;
+use serenity::model::id::{ChannelId, MessageId};
 use tracing::info;
 
-use kekw_db::periods::get_most_recent_closed_period;
-use kekw_db::rolls::get_roll_by_period;
-use kekw_db::submissions::get_submission_by_id;
+use kekw_db::models::period::Period;
+use kekw_db::models::roll::Roll;
+use kekw_db::models::submission::Submission;
+use kekw_db::KekPool;
 
-use crate::DBConnectionContainer;
+use crate::backend::{Backend, Live};
+use crate::config::Config;
+use crate::metadata::FallbackProvider;
+use crate::{movie_night, notify};
+use crate::settings::GuildSettings;
+use crate::timed::tie_break::{self, Outcome, PendingTieBreak};
+use crate::voting::{self, VotingMode};
+use crate::{ConfigContainer, DBConnectionContainer, MetadataContainer};
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Sync + Send>>;
 
-fn roll_movies() {
-    
-}
-
-pub async fn select_movie(ctx: &Context) -> Result<Message>{
-    info!("Selecting Movie!");
-
-    let db_pool = {
-        let data_read = ctx.data.read().await;
+// The database, config and metadata providers jobs run with
+async fn job_data(ctx: &Context) -> (KekPool, Arc<Config>, Arc<FallbackProvider>) {
+    let data_read = ctx.data.read().await;
+    (
         data_read
             .get::<DBConnectionContainer>()
             .expect("Expected DBConnection in TypeMap.")
-            .clone()
+            .clone(),
+        data_read
+            .get::<ConfigContainer>()
+            .expect("Expected Config in TypeMap.")
+            .clone(),
+        data_read
+            .get::<MetadataContainer>()
+            .expect("Expected Metadata in TypeMap.")
+            .clone(),
+    )
+}
+
+// Start a new submission period and let the notify role know, carrying over
+// the last period's unpicked submissions if the guild does that.
+// Returns false if a period is already open.
+pub async fn open_period(
+    backend: Backend<'_>,
+    config: &Config,
+    settings: &GuildSettings,
+    channel_id: ChannelId,
+) -> Result<bool> {
+    if backend.store.open_period(settings.guild_id)?.is_some() {
+        return Ok(false);
+    }
+
+    let previous = backend.store.closed_period(settings.guild_id)?;
+
+    // Commit to the roll's seed before anything is submitted
+    let roll_seed = voting::new_seed();
+    backend.store.create_period(settings.guild_id, roll_seed)?;
+    info!("Started new submission period in guild {}.", settings.guild_id);
+    notify::period_opened(backend.discord, config, settings, channel_id, &voting::commitment(roll_seed)).await?;
+
+    if let Some(previous) = previous {
+        let period = backend
+            .store
+            .open_period(settings.guild_id)?
+            .ok_or("The new submission period wasn't stored")?;
+        let carried = movie_night::carry_over(backend.store, settings, &previous, &period)?;
+        if !carried.is_empty() {
+            let titles: Vec<&str> = carried.iter().map(|movie_sub| movie_sub.title.as_str()).collect();
+            notify::submissions_carried(backend.discord, config, channel_id, &titles).await?;
+        }
+    }
+
+    Ok(true)
+}
+
+// Remind the notify role that the roll is coming up, if submissions are still open
+pub async fn remind_submissions(ctx: &Context, settings: &GuildSettings, remaining: Duration) -> Result<()> {
+    let (db_pool, config, metadata) = job_data(ctx).await;
+    let live = Live::new(ctx, &db_pool, &config, &metadata);
+    let backend = live.backend();
+
+    match backend.store.open_period(settings.guild_id)? {
+        Some(_) => {
+            notify::submissions_closing(backend.discord, &config, settings, settings.channel()?, remaining).await?;
+        }
+        None => info!("No open submission period to remind about."),
+    }
+
+    Ok(())
+}
+
+// Close the current submission period and start voting on random submissions
+pub async fn roll_movies(ctx: &Context, settings: &GuildSettings) -> Result<Option<MessageId>> {
+    info!("Rolling Movies in guild {}!", settings.guild_id);
+
+    let (db_pool, config, metadata) = job_data(ctx).await;
+    let live = Live::new(ctx, &db_pool, &config, &metadata);
+
+    roll_period(live.backend(), &config, settings, settings.channel()?).await
+}
+
+// Close the open submission period and post the voting message to the channel
+pub async fn roll_period(
+    backend: Backend<'_>,
+    config: &Config,
+    settings: &GuildSettings,
+    movie_channel: ChannelId,
+) -> Result<Option<MessageId>> {
+    let cur_period = match backend.store.open_period(settings.guild_id)? {
+        Some(cur_period) => cur_period,
+        None => return resume_roll(backend, config, settings, movie_channel).await,
+    };
+
+    let movie_subs = backend.store.submissions(&cur_period)?;
+    info!("Got {} movie submission(s).", movie_subs.len());
+
+    if movie_subs.len() < voting::MIN_CANDIDATES {
+        backend
+            .discord
+            .send_message(
+                movie_channel,
+                "Not enough movies submitted to choose from, submissions are staying open!",
+                None,
+            )
+            .await?;
+        return Ok(None);
+    }
+
+    // End current submission period
+    let cur_period = backend.store.end_period(cur_period)?;
+
+    // Replace any roll left over from a manual roll
+    let rerolled = match backend.store.roll(&cur_period)? {
+        Some(cur_roll) => {
+            backend.store.delete_roll(cur_roll.id)?;
+            true
+        }
+        None => false,
     };
 
+    let mode = config.voting.mode;
+    let (seed, _) = voting::roll_seed(&cur_period, rerolled);
+    let weights = voting::submitter_weights(backend.store, settings.guild_id, &cur_period, settings.weighting)?;
+    let choice_movies = voting::pick_selections(&movie_subs, config.voting.candidates, seed, &weights);
+    let choice_ids: Vec<i32> = choice_movies.iter().map(|movie_sub| movie_sub.id).collect();
+
+    // Insert roll into roll table
+    let cur_roll = backend
+        .store
+        .create_roll(&cur_period, &choice_ids, mode, seed, settings.weighting)?;
+
+    post_vote(backend, config, settings, movie_channel, cur_period, &cur_roll).await
+}
+
+// Finish the latest roll if an earlier attempt stored it but failed before it was all posted.
+// Returns the voting message only if it was posted now.
+async fn resume_roll(
+    backend: Backend<'_>,
+    config: &Config,
+    settings: &GuildSettings,
+    movie_channel: ChannelId,
+) -> Result<Option<MessageId>> {
+    let period = backend.store.closed_period(settings.guild_id)?;
+    let roll = match period.as_ref() {
+        Some(period) => backend.store.roll(period)?,
+        None => None,
+    };
+
+    // Nothing to roll if a period isn't open and the last roll was decided
+    let (period, roll) = match (period, roll) {
+        (Some(period), Some(roll)) if roll.winner.is_none() => (period, roll),
+        _ => {
+            info!("No open submission period to roll.");
+            return Ok(None);
+        }
+    };
+
+    let vote_msg_id = match period.vote_message.as_ref() {
+        Some(vote_msg_id) => MessageId(vote_msg_id.parse::<u64>()?),
+        None => {
+            info!("Resuming the roll of period {}.", period.id);
+            return post_vote(backend, config, settings, movie_channel, period, &roll).await;
+        }
+    };
+
+    // The vote is up, but the reactions or the seed may not be
+    let mode = roll.voting_mode.parse::<VotingMode>()?;
+    let mut emojis: Vec<ReactionType> = Vec::new();
+    if mode == VotingMode::Plurality {
+        for selection in backend.store.selections(&roll)? {
+            let emote = selection.emote.as_ref().ok_or("Roll selection has no voting emote")?;
+            emojis.push(ReactionType::from_str(emote)?);
+        }
+    }
+    voting::react_choices(backend.discord, movie_channel, vote_msg_id, &emojis, mode).await?;
+    reveal(backend, config, movie_channel, &period, &roll).await?;
+
+    Ok(None)
+}
+
+// Put up the vote on a stored roll's selections and reveal the seed they were rolled with
+async fn post_vote(
+    backend: Backend<'_>,
+    config: &Config,
+    settings: &GuildSettings,
+    movie_channel: ChannelId,
+    period: Period,
+    roll: &Roll,
+) -> Result<Option<MessageId>> {
+    let roll_selections = backend.store.selections(roll)?;
+    let mut choice_movies: Vec<Submission> = Vec::new();
+    for selection in &roll_selections {
+        choice_movies.push(backend.store.submission(selection.submission_id)?);
+    }
+    let mode = roll.voting_mode.parse::<VotingMode>()?;
+
+    let emojis = voting::pick_vote_emoji(backend.discord, config, settings.guild_id, choice_movies.len()).await?;
+    let choices = voting::build_choices(backend, choice_movies, emojis).await?;
+
+    let vote_msg_id = voting::start_vote(
+        backend,
+        config,
+        settings,
+        movie_channel,
+        period.clone(),
+        roll_selections,
+        &choices,
+        mode,
+    )
+    .await?;
+    reveal(backend, config, movie_channel, &period, roll).await?;
+
+    Ok(Some(vote_msg_id))
+}
+
+// Reveal the seed the roll was made with, once per roll
+async fn reveal(
+    backend: Backend<'_>,
+    config: &Config,
+    movie_channel: ChannelId,
+    period: &Period,
+    roll: &Roll,
+) -> Result<()> {
+    // Rolls from before seeds were stored have nothing to reveal
+    let seed = match roll.seed.as_ref() {
+        Some(seed) => seed,
+        None => return Ok(()),
+    };
+
+    let key = format!("seed:{}", roll.id);
+    if !backend.store.claim(&key)? {
+        return Ok(());
+    }
+
+    let committed = period.roll_seed.as_ref() == Some(seed);
+    if let Err(why) =
+        voting::reveal_seed(backend.discord, config, movie_channel, period, seed.parse::<u64>()?, committed).await
+    {
+        backend.store.release(&key)?;
+        return Err(why);
+    }
+
+    Ok(())
+}
+
+// Eliminate the candidates with the fewest first preferences until one has a majority.
+// Returns every remaining candidate if they can't be separated.
+fn instant_runoff(candidates: &[i32], ballots: &[Vec<i32>]) -> Vec<i32> {
+    let mut remaining: Vec<i32> = candidates.to_vec();
+
+    loop {
+        let mut tallies: HashMap<i32, usize> = remaining.iter().map(|c| (*c, 0)).collect();
+        let mut active_ballots = 0;
+
+        for ballot in ballots {
+            // Ballots count towards their highest ranked remaining candidate
+            if let Some(top) = ballot.iter().find(|c| remaining.contains(*c)) {
+                *tallies.get_mut(top).unwrap() += 1;
+                active_ballots += 1;
+            }
+        }
+
+        if let Some((winner, _)) = tallies
+            .iter()
+            .find(|(_, votes)| **votes * 2 > active_ballots)
+        {
+            return vec![*winner];
+        }
+
+        let fewest = *tallies.values().min().unwrap_or(&0);
+        let eliminated: Vec<i32> = remaining
+            .iter()
+            .filter(|c| tallies[*c] == fewest)
+            .cloned()
+            .collect();
+
+        if eliminated.len() == remaining.len() {
+            return remaining;
+        }
+
+        remaining.retain(|c| !eliminated.contains(c));
+
+        if remaining.len() == 1 {
+            return remaining;
+        }
+    }
+}
+
+// Parse the comma separated submission ids stored in a ballot
+fn parse_ranking(ranking: &str) -> Vec<i32> {
+    ranking
+        .split(',')
+        .filter_map(|id| id.trim().parse::<i32>().ok())
+        .collect()
+}
+
+// How far selecting the winner got
+#[derive(Debug)]
+pub enum Selected {
+    Announced(MessageId),
+    // An earlier attempt already announced the winner
+    AlreadyAnnounced,
+    // Waiting on a runoff or the movie host, queue finishing it for when it closes
+    TieBreak(PendingTieBreak),
+    // An earlier attempt started the runoff or host pick and queued finishing it
+    TieBreakStarted,
+}
+
+// Tally the votes of the guild's running roll and announce the winner.
+// Safe to retry, nothing is announced twice.
+pub async fn select_movie(ctx: &Context, settings: &GuildSettings) -> Result<Selected> {
+    info!("Selecting Movie in guild {}!", settings.guild_id);
+
+    let (db_pool, config, metadata) = job_data(ctx).await;
+    let live = Live::new(ctx, &db_pool, &config, &metadata);
+
+    select_winner(live.backend(), &config, settings).await
+}
+
+pub async fn select_winner(
+    backend: Backend<'_>,
+    config: &Config,
+    settings: &GuildSettings,
+) -> Result<Selected> {
     // Get the most recently close period
-    let period = get_most_recent_closed_period(&db_pool)?;
+    let period = backend
+        .store
+        .closed_period(settings.guild_id)?
+        .ok_or("No closed submission period to select from")?;
 
     // Get the rolls associated with that period
-    let roll = get_roll_by_period(&db_pool, &period)?;
+    let roll = backend.store.roll(&period)?.ok_or("The closed period has no roll")?;
+
+    let movie_channel = settings.channel()?;
+
+    let winner = match roll.winner {
+        // Picked by an earlier attempt that failed before announcing
+        Some(winner_id) => backend.store.submission(winner_id)?,
+        None => match decide_winner(backend, config, settings, movie_channel, &period, &roll).await? {
+            Outcome::Decided(winner) => {
+                backend.store.set_winner(roll, winner.id, Utc::now().naive_utc())?;
+                winner
+            }
+            Outcome::Pending(pending) => return Ok(Selected::TieBreak(pending)),
+            Outcome::Started => return Ok(Selected::TieBreakStarted),
+        },
+    };
+
+    announce(backend, config, settings, movie_channel, &period, &winner).await
+}
+
+// Count the reactions on a runoff or host pick that has closed and announce the winner.
+// Safe to retry like select_movie.
+pub async fn finish_tie_break(
+    ctx: &Context,
+    settings: &GuildSettings,
+    pending: &PendingTieBreak,
+) -> Result<Selected> {
+    info!("Finishing the {} in guild {}!", pending.method.to_string(), settings.guild_id);
+
+    let (db_pool, config, metadata) = job_data(ctx).await;
+    let live = Live::new(ctx, &db_pool, &config, &metadata);
+
+    tie_break_winner(live.backend(), &config, settings, pending).await
+}
+
+pub async fn tie_break_winner(
+    backend: Backend<'_>,
+    config: &Config,
+    settings: &GuildSettings,
+    pending: &PendingTieBreak,
+) -> Result<Selected> {
+    let period = backend
+        .store
+        .closed_period(settings.guild_id)?
+        .ok_or("No closed submission period to select from")?;
+    let roll = backend.store.roll(&period)?.ok_or("The closed period has no roll")?;
+
+    let movie_channel = settings.channel()?;
+
+    let winner = match roll.winner {
+        Some(winner_id) => backend.store.submission(winner_id)?,
+        None => {
+            let winner =
+                tie_break::finish_tie_break(backend, config, settings.guild_id, movie_channel, &roll, pending).await?;
+            backend.store.set_winner(roll, winner.id, Utc::now().naive_utc())?;
+            winner
+        }
+    };
 
-    // Get the emotes from that roll selection
-    let selection_1_emote = ReactionType::from_str(&roll.selection_1_emote.unwrap()).unwrap();
-    let selection_2_emote = ReactionType::from_str(&roll.selection_2_emote.unwrap()).unwrap();
+    announce(backend, config, settings, movie_channel, &period, &winner).await
+}
 
-    let movie_channel_id = env::var("MOVIE_CHANNEL").unwrap().parse::<u64>().expect("MOVIE_CHANNEL not a correct Discord Channel ID!");
-    let movie_channel = ChannelId(movie_channel_id);
+// Announce the period's winner and open the next period if that's turned on
+async fn announce(
+    backend: Backend<'_>,
+    config: &Config,
+    settings: &GuildSettings,
+    movie_channel: ChannelId,
+    period: &Period,
+    winner: &Submission,
+) -> Result<Selected> {
+    // Only the first attempt to get this far announces, so a retry or a catch-up
+    // after a restart never announces the same period twice
+    let key = format!("winner:{}", period.id);
+    if !backend.store.claim(&key)? {
+        info!("Winner for period {} was already announced.", period.id);
+        start_next_period(backend, config, settings, movie_channel, period).await?;
+        return Ok(Selected::AlreadyAnnounced);
+    }
 
-    // Load the reactions from the voting message
-    let vote_msg: Message = movie_channel.message(&ctx.http, MessageId::from(period.vote_message.unwrap().parse::<u64>().unwrap())).await.unwrap();
+    // Give the claim back if the announcement didn't go out, so the retry sends it
+    let winner_msg_id = match notify::winner(backend.discord, config, settings, movie_channel, &winner.title).await {
+        Ok(winner_msg_id) => winner_msg_id,
+        Err(why) => {
+            backend.store.release(&key)?;
+            return Err(why);
+        }
+    };
+
+    start_next_period(backend, config, settings, movie_channel, period).await?;
+
+    Ok(Selected::Announced(winner_msg_id))
+}
+
+// Open the period after a decided one if that's turned on. Kept apart from the announcement
+// so a retry still opens it when the announcement went out but opening failed.
+async fn start_next_period(
+    backend: Backend<'_>,
+    config: &Config,
+    settings: &GuildSettings,
+    movie_channel: ChannelId,
+    decided: &Period,
+) -> Result<()> {
+    if !config.features.auto_start_period {
+        return Ok(());
+    }
+
+    let key = format!("next:{}", decided.id);
+    if !backend.store.claim(&key)? {
+        return Ok(());
+    }
+
+    if let Err(why) = open_period(backend, config, settings, movie_channel).await {
+        backend.store.release(&key)?;
+        return Err(why);
+    }
+
+    Ok(())
+}
 
-    let mut selection_1_count: u64 = 0;
-    let mut selection_2_count: u64 = 0;
+// Count the votes, store them with the roll and break any tie
+async fn decide_winner(
+    backend: Backend<'_>,
+    config: &Config,
+    settings: &GuildSettings,
+    movie_channel: ChannelId,
+    period: &Period,
+    roll: &Roll,
+) -> Result<Outcome> {
+    // Get the selections from that roll, in the order they were shown
+    let roll_selections = backend.store.selections(roll)?;
 
-    for reaction in &vote_msg.reactions {
-        if reaction.reaction_type == selection_1_emote {
-            selection_1_count = reaction.count;
-        } else if reaction.reaction_type == selection_2_emote {
-            selection_2_count = reaction.count;
+    let mut candidates: Vec<Submission> = Vec::new();
+    for selection in &roll_selections {
+        candidates.push(backend.store.submission(selection.submission_id)?);
+    }
+    let candidate_ids: Vec<i32> = candidates.iter().map(|c| c.id).collect();
+
+    let (counts, leaders): (Vec<u64>, Vec<i32>) = match roll.voting_mode.parse::<VotingMode>()? {
+        VotingMode::Plurality => {
+            // Get the emotes from that roll selection
+            let mut emotes: Vec<ReactionType> = Vec::new();
+            for selection in &roll_selections {
+                let emote = selection.emote.as_ref().ok_or("Roll selection has no voting emote")?;
+                emotes.push(ReactionType::from_str(emote)?);
+            }
+
+            // Count the reactions on the voting message
+            let vote_msg_id = period.vote_message.as_ref().ok_or("Period has no voting message")?.parse::<u64>()?;
+            let counts = backend
+                .discord
+                .reaction_counts(movie_channel, MessageId::from(vote_msg_id), &emotes)
+                .await?;
+
+            let most_votes = counts.iter().max().cloned().unwrap_or(0);
+            let leaders = candidate_ids
+                .iter()
+                .zip(&counts)
+                .filter(|(_, count)| **count == most_votes)
+                .map(|(id, _)| *id)
+                .collect();
+
+            (counts, leaders)
+        }
+        VotingMode::Ranked => {
+            let rankings: Vec<Vec<i32>> = backend
+                .store
+                .rankings(roll)?
+                .iter()
+                .map(|ranking| parse_ranking(ranking))
+                .collect();
+
+            info!("Tallying {} ranked ballot(s).", rankings.len());
+
+            // Record first preferences as the votes for each movie
+            let counts = candidate_ids
+                .iter()
+                .map(|id| rankings.iter().filter(|r| r.first() == Some(id)).count() as u64)
+                .collect();
+
+            (counts, instant_runoff(&candidate_ids, &rankings))
         }
+    };
+
+    // Keep the votes with the roll so history stays correct, a retry overwrites them
+    for (candidate_id, count) in candidate_ids.iter().zip(&counts) {
+        backend.store.set_result(roll, *candidate_id, *count as i32)?;
     }
 
-    let submission_1 = get_submission_by_id(&db_pool, roll.selection_1)?;
-    let submission_2 = get_submission_by_id(&db_pool, roll.selection_2)?;
+    let mut tied: Vec<Submission> = candidates
+        .into_iter()
+        .filter(|c| leaders.contains(&c.id))
+        .collect();
+
+    // Break ties with the configured policy
+    if tied.len() == 1 {
+        return Ok(Outcome::Decided(tied.remove(0)));
+    }
 
-    // Compare votes and set message!
-    let mut message_str = String::from("");
+    // Only the first attempt to get this far starts breaking the tie
+    let key = format!("tie:{}", roll.id);
+    if !backend.store.claim(&key)? {
+        info!("The tie for period {} is already being broken.", period.id);
+        return tie_break::resume_tie_break(roll, &tied);
+    }
 
-    if selection_1_count > selection_2_count {
-        message_str = format!("{} wins!", submission_1.title);
-    } else if selection_2_count > selection_1_count {
-        message_str = format!("{} wins!", submission_2.title);
-    } else if selection_1_count == selection_2_count {
-        message_str = format!("{} and {} tied!", submission_1.title, submission_2.title);
-    } else {
-        message_str = String::from("Something went wrong.....");
+    // Give the claim back if the tie break didn't start, so the retry starts it
+    let tied_titles: Vec<String> = tied.iter().map(|c| c.title.clone()).collect();
+    let started = match backend
+        .discord
+        .send_message(movie_channel, &format!("{} tied!", tied_titles.join(" and ")), None)
+        .await
+    {
+        Ok(_) => tie_break::start_tie_break(backend, config, settings.guild_id, movie_channel, roll, tied).await,
+        Err(why) => Err(why),
+    };
+    if started.is_err() {
+        backend.store.release(&key)?;
     }
 
-    Ok(movie_channel.say(&ctx.http, message_str).await.unwrap())
-}
\ No newline at end of file
+    started
+}
@@ -1 +1,63 @@
-pub mod jobs;
\ No newline at end of file
+pub mod jobs;
+pub mod queue;
+pub mod schedule;
+pub mod tie_break;
+
+use std::sync::Arc;
+
+use chrono::Utc;
+use serenity::client::Context;
+use tracing::{error, info};
+
+use crate::supervisor::Shutdown;
+use crate::{settings, ConfigContainer, DBConnectionContainer};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Sync + Send>>;
+
+// How often the scheduler wakes up to check the stored schedule
+const SCHEDULER_TICK_SECONDS: u64 = 60;
+
+// Runs under the supervisor, failing restarts it and the queue picks up where it left off.
+// Returns once shutdown starts, after finishing the tick in progress.
+pub async fn run_scheduler(ctx: Arc<Context>, mut shutdown: Shutdown) -> Result<()> {
+    let (db_pool, config) = {
+        let data_read = ctx.data.read().await;
+        (
+            data_read
+                .get::<DBConnectionContainer>()
+                .expect("Expected DBConnection in TypeMap.")
+                .clone(),
+            data_read
+                .get::<ConfigContainer>()
+                .expect("Expected Config in TypeMap.")
+                .clone(),
+        )
+    };
+
+    queue::recover(&db_pool)?;
+
+    // The first tick is immediate, so anything that came due while the bot was down runs now
+    let mut interval_timer =
+        tokio::time::interval(std::time::Duration::from_secs(SCHEDULER_TICK_SECONDS));
+
+    loop {
+        // Wait for the next interval tick
+        tokio::select! {
+            _ = interval_timer.tick() => (),
+            _ = shutdown.requested() => {
+                info!("Scheduler stopped.");
+                return Ok(());
+            }
+        }
+        let now = Utc::now();
+
+        // Reload every tick so guilds set up with !m config are picked up without a restart
+        for guild in settings::configured(&db_pool)? {
+            if let Err(why) = queue::plan(&db_pool, &config, guild.guild_id, now) {
+                error!("Failed to queue jobs for guild {}: {:?}", guild.guild_id, why);
+            }
+        }
+
+        queue::run_due(&ctx, &db_pool, &config, now).await?;
+    }
+}
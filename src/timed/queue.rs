@@ -0,0 +1,271 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use serenity::client::Context;
+use serenity::model::channel::ReactionType;
+use serenity::model::id::{GuildId, MessageId};
+use tracing::{error, info, warn};
+
+use kekw_db::models::scheduled_job::ScheduledJob;
+use kekw_db::{scheduled_jobs, KekPool};
+
+use crate::config::Config;
+use crate::settings;
+use crate::timed::jobs::{self, Selected};
+use crate::timed::schedule;
+use crate::timed::tie_break::{PendingTieBreak, TieBreak};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Sync + Send>>;
+
+// Failed jobs are retried this many times in total before they are given up on
+const MAX_ATTEMPTS: i32 = 5;
+
+// Job statuses stored in the queue
+const DONE: &str = "done";
+const SKIPPED: &str = "skipped";
+const FAILED: &str = "failed";
+
+// What a queued job does when it comes due
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JobKind {
+    // Remind the notify role that submissions are closing
+    Remind,
+    // Close submissions and start the vote
+    Roll,
+    // Count the votes and announce the winner
+    Select,
+    // Close a runoff or host pick started by Select and announce the winner
+    TieBreak,
+}
+
+impl FromStr for JobKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "remind" => Ok(JobKind::Remind),
+            "roll" => Ok(JobKind::Roll),
+            "select" => Ok(JobKind::Select),
+            "tiebreak" => Ok(JobKind::TieBreak),
+            _ => Err(format!("Unknown job kind {}", s)),
+        }
+    }
+}
+
+impl ToString for JobKind {
+    fn to_string(&self) -> String {
+        match self {
+            JobKind::Remind => String::from("remind"),
+            JobKind::Roll => String::from("roll"),
+            JobKind::Select => String::from("select"),
+            JobKind::TieBreak => String::from("tiebreak"),
+        }
+    }
+}
+
+// How long before the selection each kind of job runs, None if it isn't planned from the schedule
+fn lead(config: &Config, kind: JobKind) -> Option<Duration> {
+    match kind {
+        JobKind::Select => Some(Duration::zero()),
+        JobKind::TieBreak => None,
+        JobKind::Roll => Some(config.voting.window),
+        JobKind::Remind => config.notify.reminder.map(|reminder| config.voting.window + reminder),
+    }
+}
+
+// Each run of the schedule has one job of each kind
+fn job_key(guild_id: GuildId, kind: JobKind, selection: DateTime<Utc>) -> String {
+    format!("{}:{}:{}", guild_id, kind.to_string(), selection.timestamp())
+}
+
+// Queue the next reminder, roll and selection for the guild.
+// Each run of the schedule has its own key, so queueing it again does nothing.
+pub fn plan(db_pool: &KekPool, config: &Config, guild_id: GuildId, now: DateTime<Utc>) -> Result<()> {
+    let movie_schedule = schedule::load(db_pool, guild_id, &config.schedule);
+
+    for kind in [JobKind::Remind, JobKind::Roll, JobKind::Select].iter() {
+        let lead = match lead(config, *kind) {
+            Some(lead) => lead,
+            None => continue,
+        };
+        let selection = movie_schedule.next_after(now + lead);
+        let due = selection - lead;
+
+        let key = job_key(guild_id, *kind, selection);
+        // Retries move the due time, so keep which selection the job is for
+        let payload = serde_json::json!({
+            "guild_id": guild_id.to_string(),
+            "selection": selection.timestamp(),
+        })
+        .to_string();
+
+        if scheduled_jobs::enqueue(db_pool, &kind.to_string(), due.naive_utc(), &payload, &key)? {
+            info!("Queued {} in guild {} for {}", kind.to_string(), guild_id, due);
+        }
+    }
+
+    Ok(())
+}
+
+// Queue finishing a tie break for when it closes, keyed by the selection it breaks the tie for
+fn queue_tie_break(
+    db_pool: &KekPool,
+    guild_id: GuildId,
+    selection: DateTime<Utc>,
+    pending: &PendingTieBreak,
+) -> Result<()> {
+    let kind = JobKind::TieBreak;
+    let key = job_key(guild_id, kind, selection);
+    let choices: Vec<serde_json::Value> = pending
+        .choices
+        .iter()
+        .map(|(submission_id, emoji)| serde_json::json!([submission_id, emoji.to_string()]))
+        .collect();
+    let payload = serde_json::json!({
+        "guild_id": guild_id.to_string(),
+        "selection": selection.timestamp(),
+        "method": pending.method.to_string(),
+        "message_id": pending.message_id.to_string(),
+        "choices": choices,
+        "closes": pending.closes.timestamp(),
+    })
+    .to_string();
+
+    if scheduled_jobs::enqueue(db_pool, &kind.to_string(), pending.closes.naive_utc(), &payload, &key)? {
+        info!("Queued {} in guild {} for {}", kind.to_string(), guild_id, pending.closes);
+    }
+
+    Ok(())
+}
+
+fn parse_tie_break(payload: &serde_json::Value) -> Result<PendingTieBreak> {
+    let method = payload["method"].as_str().ok_or("Job has no tie break")?.parse::<TieBreak>()?;
+    let message_id = payload["message_id"]
+        .as_str()
+        .ok_or("Job has no tie break message")?
+        .parse::<u64>()
+        .map(MessageId)?;
+
+    let mut choices = Vec::new();
+    for choice in payload["choices"].as_array().ok_or("Job has no tied submissions")? {
+        let submission_id = choice[0].as_i64().ok_or("Tied submission has no id")? as i32;
+        let emoji = choice[1].as_str().ok_or("Tied submission has no emoji")?.parse::<ReactionType>()?;
+        choices.push((submission_id, emoji));
+    }
+
+    let closes = payload["closes"].as_i64().ok_or("Job has no closing time")?;
+    let closes = DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(closes, 0), Utc);
+
+    Ok(PendingTieBreak {
+        method,
+        message_id,
+        choices,
+        closes,
+    })
+}
+
+// Jobs left running by a crash or a restart are run again
+pub fn recover(db_pool: &KekPool) -> Result<()> {
+    let reset = scheduled_jobs::reset_running(db_pool)?;
+    if reset > 0 {
+        warn!("Requeued {} job(s) that were interrupted", reset);
+    }
+
+    Ok(())
+}
+
+// Run every job that has come due, oldest first, including ones missed while the bot was down
+pub async fn run_due(ctx: &Context, db_pool: &KekPool, config: &Config, now: DateTime<Utc>) -> Result<()> {
+    // Fetched one at a time since running a job can push back another that was due
+    while let Some(job) = scheduled_jobs::get_due_jobs(db_pool, now.naive_utc())?.into_iter().next() {
+        let job = scheduled_jobs::start_job(db_pool, job.id)?;
+
+        match run(ctx, db_pool, config, &job, now).await {
+            Ok(status) => scheduled_jobs::finish_job(db_pool, job.id, status)?,
+            Err(why) if job.attempts < MAX_ATTEMPTS => {
+                // Wait longer after every failed attempt
+                let retry = now + Duration::minutes(2_i64.pow(job.attempts as u32));
+                error!(
+                    "Job {} ({}) failed on attempt {}, retrying at {}: {:?}",
+                    job.id, job.kind, job.attempts, retry, why
+                );
+                scheduled_jobs::retry_job(db_pool, job.id, retry.naive_utc())?;
+            }
+            Err(why) => {
+                error!("Job {} ({}) failed {} times, giving up: {:?}", job.id, job.kind, job.attempts, why);
+                scheduled_jobs::finish_job(db_pool, job.id, FAILED)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Run one job, returning the status to store for it
+async fn run(
+    ctx: &Context,
+    db_pool: &KekPool,
+    config: &Config,
+    job: &ScheduledJob,
+    now: DateTime<Utc>,
+) -> Result<&'static str> {
+    let kind = job.kind.parse::<JobKind>()?;
+    let payload: serde_json::Value = serde_json::from_str(&job.payload)?;
+    let guild_id = payload["guild_id"]
+        .as_str()
+        .ok_or("Job has no guild")?
+        .parse::<u64>()
+        .map(GuildId)?;
+    let selection = payload["selection"].as_i64().ok_or("Job has no selection time")?;
+    let selection = DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(selection, 0), Utc);
+
+    let guild_settings = settings::load(db_pool, guild_id)?;
+    if guild_settings.movie_channel.is_none() {
+        info!("Skipping {} in guild {}, it has no movie channel", kind.to_string(), guild_id);
+        return Ok(SKIPPED);
+    }
+
+    // The schedule may have changed since the job was queued, the new times are queued separately.
+    // A tie break has already started, so it finishes either way.
+    let movie_schedule = schedule::load(db_pool, guild_id, &config.schedule);
+    if kind != JobKind::TieBreak && movie_schedule.next_after(selection - Duration::seconds(1)) != selection {
+        info!("Skipping {} in guild {}, the schedule changed", kind.to_string(), guild_id);
+        return Ok(SKIPPED);
+    }
+
+    match kind {
+        JobKind::Remind => {
+            // Submissions close when the roll runs
+            let closes = selection - config.voting.window;
+
+            // Caught up too late to remind anyone
+            if now >= closes {
+                return Ok(SKIPPED);
+            }
+            jobs::remind_submissions(ctx, &guild_settings, closes - now).await?;
+        }
+        JobKind::Roll => {
+            jobs::roll_movies(ctx, &guild_settings).await?;
+
+            // Caught up late after the bot was down, so the vote still gets its full time
+            let select_due = now + config.voting.window;
+            if select_due > selection {
+                let key = job_key(guild_id, JobKind::Select, selection);
+                if scheduled_jobs::reschedule(db_pool, &key, select_due.naive_utc())? > 0 {
+                    info!("Pushed back select in guild {} to {}", guild_id, select_due);
+                }
+            }
+        }
+        JobKind::Select => {
+            // A runoff or host pick is left open rather than holding up every other guild's jobs
+            if let Selected::TieBreak(pending) = jobs::select_movie(ctx, &guild_settings).await? {
+                queue_tie_break(db_pool, guild_id, selection, &pending)?;
+            }
+        }
+        JobKind::TieBreak => {
+            jobs::finish_tie_break(ctx, &guild_settings, &parse_tie_break(&payload)?).await?;
+        }
+    }
+
+    Ok(DONE)
+}
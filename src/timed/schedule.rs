@@ -0,0 +1,163 @@
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::prelude::*;
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+use tracing::error;
+
+use serenity::model::id::GuildId;
+
+use kekw_db::{schedules, KekPool};
+
+static TIME_FORMAT: &'static str = "%H:%M";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Cadence {
+    Weekly,
+    Biweekly,
+    // The first occurrence of the day within each month
+    MonthlyFirst,
+}
+
+impl FromStr for Cadence {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "weekly" => Ok(Cadence::Weekly),
+            "biweekly" => Ok(Cadence::Biweekly),
+            "monthly" => Ok(Cadence::MonthlyFirst),
+            _ => Err(format!(
+                "Unknown cadence {}, expected weekly, biweekly or monthly",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Cadence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Cadence::Weekly => write!(f, "weekly"),
+            Cadence::Biweekly => write!(f, "biweekly"),
+            Cadence::MonthlyFirst => write!(f, "monthly"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schedule {
+    pub day: Weekday,
+    pub time: NaiveTime,
+    pub timezone: Tz,
+    pub cadence: Cadence,
+    // Biweekly schedules run on the weeks an even number of weeks from this date
+    pub anchor: NaiveDate,
+}
+
+impl Schedule {
+    pub fn parse(
+        day: &str,
+        time: &str,
+        timezone: &str,
+        cadence: &str,
+        anchor: NaiveDate,
+    ) -> Result<Schedule, String> {
+        let day = day
+            .parse::<Weekday>()
+            .map_err(|_| format!("Unknown day of week {}", day))?;
+        let time = NaiveTime::parse_from_str(time, TIME_FORMAT)
+            .map_err(|_| format!("Time {} is not in HH:MM format", time))?;
+        let timezone = timezone.parse::<Tz>()?;
+        let cadence = cadence.parse::<Cadence>()?;
+
+        Ok(Schedule {
+            day,
+            time,
+            timezone,
+            cadence,
+            anchor,
+        })
+    }
+
+    fn runs_on(&self, date: NaiveDate) -> bool {
+        if date.weekday() != self.day {
+            return false;
+        }
+
+        match self.cadence {
+            Cadence::Weekly => true,
+            Cadence::Biweekly => (date - self.anchor).num_days().div_euclid(7) % 2 == 0,
+            Cadence::MonthlyFirst => date.day() <= 7,
+        }
+    }
+
+    // Get the first movie selection strictly after the supplied time
+    pub fn next_after(&self, after: DateTime<Utc>) -> DateTime<Utc> {
+        let mut date = after.with_timezone(&self.timezone).date().naive_local();
+
+        loop {
+            if self.runs_on(date) {
+                // Skip times that don't exist in the time zone (DST gaps)
+                if let Some(selection) = self
+                    .timezone
+                    .from_local_datetime(&date.and_time(self.time))
+                    .earliest()
+                {
+                    let selection = selection.with_timezone(&Utc);
+                    if selection > after {
+                        return selection;
+                    }
+                }
+            }
+
+            date = date.succ();
+        }
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} on {} at {} ({})",
+            self.cadence,
+            self.day,
+            self.time.format(TIME_FORMAT),
+            self.timezone.name()
+        )
+    }
+}
+
+// Load the guild's stored schedule, otherwise use the configured default
+pub fn load(db_pool: &KekPool, guild_id: GuildId, default: &Schedule) -> Schedule {
+    if let Ok(stored) = schedules::get_schedule(db_pool, &guild_id.to_string()) {
+        match Schedule::parse(
+            &stored.day,
+            &stored.time,
+            &stored.timezone,
+            &stored.cadence,
+            stored.anchor,
+        ) {
+            Ok(schedule) => return schedule,
+            Err(why) => error!("Stored schedule is invalid, using default: {}", why),
+        }
+    }
+
+    default.clone()
+}
+
+pub fn save(db_pool: &KekPool, guild_id: GuildId, schedule: &Schedule) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    schedules::set_schedule(
+        db_pool,
+        &guild_id.to_string(),
+        &schedule.day.to_string(),
+        &schedule.time.format(TIME_FORMAT).to_string(),
+        schedule.timezone.name(),
+        &schedule.cadence.to_string(),
+        schedule.anchor,
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,282 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Duration, Utc};
+use serenity::model::channel::ReactionType;
+use serenity::model::id::{ChannelId, GuildId, MessageId, RoleId};
+use serenity::prelude::Mentionable;
+use tracing::info;
+
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+
+use kekw_db::models::roll::Roll;
+use kekw_db::models::submission::Submission;
+
+use crate::backend::Backend;
+use crate::config::Config;
+use crate::error::Error;
+use crate::voting::{self, VotingMode};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Sync + Send>>;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TieBreak {
+    // Vote again on a fresh message with only the tied movies
+    Runoff,
+    // Pick randomly, recording the seed used
+    CoinFlip,
+    // Let someone with the movie host role pick
+    Host,
+}
+
+impl FromStr for TieBreak {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "runoff" => Ok(TieBreak::Runoff),
+            "coinflip" => Ok(TieBreak::CoinFlip),
+            "host" => Ok(TieBreak::Host),
+            _ => Err(format!(
+                "Unknown tie break {}, expected runoff, coinflip or host",
+                s
+            )),
+        }
+    }
+}
+
+impl ToString for TieBreak {
+    fn to_string(&self) -> String {
+        match self {
+            TieBreak::Runoff => String::from("runoff"),
+            TieBreak::CoinFlip => String::from("coinflip"),
+            TieBreak::Host => String::from("host"),
+        }
+    }
+}
+
+// A runoff or host pick waiting for reactions, finished by a queued job once it closes
+#[derive(Clone, Debug, PartialEq)]
+pub struct PendingTieBreak {
+    pub method: TieBreak,
+    pub message_id: MessageId,
+    // The tied submission ids along with the emoji for each, in the order shown
+    pub choices: Vec<(i32, ReactionType)>,
+    pub closes: DateTime<Utc>,
+}
+
+// What starting a tie break got to
+#[derive(Debug)]
+pub enum Outcome {
+    Decided(Submission),
+    Pending(PendingTieBreak),
+    // An earlier attempt posted the runoff or host pick, its queued job finishes it
+    Started,
+}
+
+// Pick a winner from the tied submissions using the configured policy.
+// A runoff or host pick only posts its message here, the winner comes from finish_tie_break.
+pub async fn start_tie_break(
+    backend: Backend<'_>,
+    config: &Config,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    roll: &Roll,
+    tied: Vec<Submission>,
+) -> Result<Outcome> {
+    let pending = match config.voting.tie_break {
+        TieBreak::Runoff => runoff(backend, config, guild_id, channel_id, &tied).await?,
+        TieBreak::Host => host_pick(backend, config, guild_id, channel_id, &tied).await?,
+        TieBreak::CoinFlip => return coin_flip(backend, channel_id, roll, tied).await.map(Outcome::Decided),
+    };
+
+    Ok(Outcome::Pending(pending))
+}
+
+// Where an earlier attempt at breaking the tie got to. A coin flip is done again
+// from its recorded seed, so it picks the same movie it did the first time.
+pub fn resume_tie_break(roll: &Roll, tied: &[Submission]) -> Result<Outcome> {
+    let flipped = roll.tie_break.as_ref().map(|method| method.parse::<TieBreak>()) == Some(Ok(TieBreak::CoinFlip));
+
+    match roll.tie_break_seed.as_ref() {
+        Some(seed) if flipped => Ok(Outcome::Decided(flip(tied, seed.parse::<u64>()?)?)),
+        _ => Ok(Outcome::Started),
+    }
+}
+
+// Count the reactions on a closed runoff or host pick.
+// Anything that couldn't be decided falls back to a coin flip.
+pub async fn finish_tie_break(
+    backend: Backend<'_>,
+    config: &Config,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    roll: &Roll,
+    pending: &PendingTieBreak,
+) -> Result<Submission> {
+    let mut tied: Vec<Submission> = Vec::new();
+    for (submission_id, _) in &pending.choices {
+        tied.push(backend.store.submission(*submission_id)?);
+    }
+    let emojis: Vec<ReactionType> = pending.choices.iter().map(|(_, emoji)| emoji.clone()).collect();
+
+    let counts = match pending.method {
+        TieBreak::Runoff => {
+            backend
+                .discord
+                .reaction_counts(channel_id, pending.message_id, &emojis)
+                .await?
+        }
+        // Only count reactions from members with the host role
+        TieBreak::Host => {
+            backend
+                .discord
+                .role_reaction_counts(guild_id, channel_id, pending.message_id, host_role(config)?, &emojis)
+                .await?
+        }
+        TieBreak::CoinFlip => return coin_flip(backend, channel_id, roll, tied).await,
+    };
+
+    let max_count = counts.iter().max().cloned().unwrap_or(0);
+    let leaders: Vec<usize> = (0..counts.len()).filter(|i| counts[*i] == max_count).collect();
+
+    if max_count > 0 && leaders.len() == 1 {
+        backend.store.set_tie_break(roll.clone(), pending.method, None)?;
+        return Ok(tied.remove(leaders[0]));
+    }
+
+    let reason = match pending.method {
+        TieBreak::Host => "No movie host picked in time.",
+        _ => "The runoff tied too!",
+    };
+    backend.discord.send_message(channel_id, reason, None).await?;
+
+    coin_flip(backend, channel_id, roll, tied).await
+}
+
+// Pick one of the tied submissions using the seed. Like voting::pick_selections this
+// gives the same pick everywhere, so the flip can be checked from the recorded seed.
+pub fn flip(tied: &[Submission], seed: u64) -> Result<Submission> {
+    let mut sorted: Vec<&Submission> = tied.iter().collect();
+    sorted.sort_by_key(|movie_sub| movie_sub.id);
+
+    let mut rng = ChaCha20Rng::seed_from_u64(seed);
+
+    Ok((*sorted.choose(&mut rng).ok_or("No tied submissions")?).clone())
+}
+
+async fn coin_flip(
+    backend: Backend<'_>,
+    channel_id: ChannelId,
+    roll: &Roll,
+    tied: Vec<Submission>,
+) -> Result<Submission> {
+    let seed = voting::new_seed();
+    let winner = flip(&tied, seed)?;
+
+    backend.store.set_tie_break(roll.clone(), TieBreak::CoinFlip, Some(seed))?;
+
+    info!("Coin flip with seed {} picked {}", seed, winner.title);
+    backend
+        .discord
+        .send_message(
+            channel_id,
+            &format!("Flipped a coin (seed {}) and picked {}!", seed, winner.title),
+            None,
+        )
+        .await?;
+
+    Ok(winner)
+}
+
+// Checked at startup when the host tie break is configured
+fn host_role(config: &Config) -> Result<RoleId> {
+    config
+        .host_role
+        .ok_or_else(|| Error::Config(String::from("a host role must be set for the host tie break")).into())
+}
+
+async fn runoff_choices(
+    backend: Backend<'_>,
+    config: &Config,
+    guild_id: GuildId,
+    tied: &[Submission],
+) -> Result<Vec<voting::Choice>> {
+    let emojis = voting::pick_vote_emoji(backend.discord, config, guild_id, tied.len()).await?;
+
+    voting::build_choices(backend, tied.to_vec(), emojis).await
+}
+
+// Post the tied movies for a runoff or host pick, open for as long as the config gives it
+async fn post_choices(
+    backend: Backend<'_>,
+    config: &Config,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    tied: &[Submission],
+    method: TieBreak,
+) -> Result<PendingTieBreak> {
+    let (title, minutes) = match method {
+        TieBreak::Host => ("Movie Host Pick!", config.voting.host_minutes),
+        _ => ("Runoff Voting!", config.voting.runoff_minutes),
+    };
+
+    let choices = runoff_choices(backend, config, guild_id, tied).await?;
+    let message_id =
+        voting::send_vote_message(backend.discord, config, channel_id, title, &choices, VotingMode::Plurality).await?;
+
+    Ok(PendingTieBreak {
+        method,
+        message_id,
+        choices: choices.iter().map(|c| (c.submission.id, c.emoji.clone())).collect(),
+        closes: Utc::now() + Duration::minutes(minutes as i64),
+    })
+}
+
+async fn runoff(
+    backend: Backend<'_>,
+    config: &Config,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    tied: &[Submission],
+) -> Result<PendingTieBreak> {
+    let pending = post_choices(backend, config, guild_id, channel_id, tied, TieBreak::Runoff).await?;
+
+    backend
+        .discord
+        .send_message(
+            channel_id,
+            &format!("Runoff voting closes in {} minutes!", config.voting.runoff_minutes),
+            None,
+        )
+        .await?;
+
+    Ok(pending)
+}
+
+async fn host_pick(
+    backend: Backend<'_>,
+    config: &Config,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    tied: &[Submission],
+) -> Result<PendingTieBreak> {
+    let host_role = host_role(config)?;
+    let pending = post_choices(backend, config, guild_id, channel_id, tied, TieBreak::Host).await?;
+
+    backend
+        .discord
+        .send_message(
+            channel_id,
+            &format!(
+                "{}, please pick the movie by reacting within {} minutes!",
+                host_role.mention(),
+                config.voting.host_minutes
+            ),
+            Some(host_role),
+        )
+        .await?;
+
+    Ok(pending)
+}
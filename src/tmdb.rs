@@ -0,0 +1,186 @@
+use serde::{Deserialize, Serialize};
+use reqwest::Url;
+use std::collections::HashMap;
+
+use crate::config::MetadataConfig;
+
+static TMDB_IMAGE_ENDPOINT: &'static str = "https://image.tmdb.org/t/p/w342";
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Genre {
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CrewMember {
+    pub name: String,
+    pub job: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Credits {
+    #[serde(default)]
+    pub crew: Vec<CrewMember>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Movie {
+    pub id: u64,
+    pub title: String,
+    pub imdb_id: Option<String>,
+    // YYYY-MM-DD, empty when unknown
+    #[serde(default)]
+    pub release_date: String,
+    // In minutes
+    pub runtime: Option<u32>,
+    #[serde(default)]
+    pub genres: Vec<Genre>,
+    pub overview: Option<String>,
+    pub poster_path: Option<String>,
+    #[serde(default)]
+    pub vote_average: f32,
+    #[serde(default)]
+    pub vote_count: u32,
+    #[serde(default)]
+    pub credits: Credits,
+}
+
+// A single match from the search endpoint
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SearchResult {
+    pub id: u64,
+    pub title: String,
+    #[serde(default)]
+    pub release_date: String,
+    pub poster_path: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SearchResponse {
+    results: Vec<SearchResult>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FindResponse {
+    movie_results: Vec<SearchResult>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct WatchProvider {
+    pub provider_name: String,
+}
+
+// Where a movie can be watched in one region, grouped by how
+#[derive(Serialize, Deserialize, Clone)]
+pub struct WatchRegion {
+    // TMDB page listing the offers
+    pub link: Option<String>,
+    #[serde(default)]
+    pub flatrate: Vec<WatchProvider>,
+    #[serde(default)]
+    pub free: Vec<WatchProvider>,
+    #[serde(default)]
+    pub ads: Vec<WatchProvider>,
+    #[serde(default)]
+    pub rent: Vec<WatchProvider>,
+    #[serde(default)]
+    pub buy: Vec<WatchProvider>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WatchProvidersResponse {
+    // Keyed by ISO 3166-1 region code
+    #[serde(default)]
+    results: HashMap<String, WatchRegion>,
+}
+
+// The year from a YYYY-MM-DD release date
+pub fn release_year(release_date: &str) -> Option<i32> {
+    release_date.get(..4)?.parse::<i32>().ok()
+}
+
+pub fn poster_url(poster_path: &str) -> String {
+    format!("{}{}", TMDB_IMAGE_ENDPOINT, poster_path)
+}
+
+pub struct TmdbClient {
+    http: reqwest::Client,
+    base_url: Url,
+    api_key: String,
+}
+
+impl TmdbClient {
+    pub fn new(base_url: Url, api_key: String) -> Self {
+        TmdbClient {
+            http: reqwest::Client::new(),
+            base_url,
+            api_key,
+        }
+    }
+
+    // The key is checked at startup whenever TMDB is used
+    pub fn from_config(config: &MetadataConfig) -> Self {
+        TmdbClient::new(
+            config.tmdb_base_url.clone(),
+            config.tmdb_api_key.clone().unwrap_or_default(),
+        )
+    }
+
+    async fn get<T: serde::de::DeserializeOwned>(&self, path: &str, params: &[(&str, String)]) -> Result<T> {
+        let mut tmdb_url = self.base_url.join(path)?;
+        {
+            let mut query = tmdb_url.query_pairs_mut();
+            query.append_pair("api_key", &self.api_key);
+            for (name, value) in params {
+                query.append_pair(name, value);
+            }
+        }
+
+        let response = self.http.get(tmdb_url).send().await?.error_for_status()?;
+        let body = response.text().await?;
+
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    // Full details including the director
+    pub async fn movie(&self, tmdb_id: u64) -> Result<Movie> {
+        self.get(
+            &format!("movie/{}", tmdb_id),
+            &[("append_to_response", String::from("credits"))],
+        )
+        .await
+    }
+
+    pub async fn find_by_imdb_id(&self, imdb_id: &str) -> Result<Option<SearchResult>> {
+        let found: FindResponse = self
+            .get(
+                &format!("find/{}", imdb_id),
+                &[("external_source", String::from("imdb_id"))],
+            )
+            .await?;
+
+        Ok(found.movie_results.into_iter().next())
+    }
+
+    // Streaming, rental and purchase offers keyed by region
+    pub async fn watch_providers(&self, tmdb_id: u64) -> Result<HashMap<String, WatchRegion>> {
+        let providers: WatchProvidersResponse = self
+            .get(&format!("movie/{}/watch/providers", tmdb_id), &[])
+            .await?;
+
+        Ok(providers.results)
+    }
+
+    pub async fn search(&self, title: &str, year: Option<i32>, page: u32) -> Result<Vec<SearchResult>> {
+        let mut params = vec![("query", title.to_string()), ("page", page.to_string())];
+        if let Some(year) = year {
+            params.push(("year", year.to_string()));
+        }
+
+        let search: SearchResponse = self.get("search/movie", &params).await?;
+
+        Ok(search.results)
+    }
+}
@@ -1,7 +1,67 @@
 use serenity::prelude::*;
 
 use serenity::model::channel::ReactionType;
-use serenity::model::id::{ChannelId, UserId};
+use serenity::model::id::{ChannelId, GuildId, UserId};
+
+use crate::error::Error;
+
+// Number emoji used to pick from a list, in order
+static CHOICE_EMOJI: [&'static str; 5] = ["1️⃣", "2️⃣", "3️⃣", "4️⃣", "5️⃣"];
+
+// Ask the author to pick one of the options by reacting with its number.
+// Returns None when nothing was picked in time.
+pub async fn ask_choice(
+    ctx: &Context,
+    author_id: UserId,
+    channel_id: ChannelId,
+    title: String,
+    options: Vec<(String, String)>,
+    thumbnail: Option<String>,
+) -> Result<Option<usize>, Box<dyn std::error::Error + Send + Sync>> {
+    use std::convert::TryFrom;
+
+    let options: Vec<(String, String)> = options.into_iter().take(CHOICE_EMOJI.len()).collect();
+    let reactions: Vec<ReactionType> = CHOICE_EMOJI
+        .iter()
+        .take(options.len())
+        .map(|e| ReactionType::try_from(*e).unwrap())
+        .collect();
+
+    let choice_msg = channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title(title);
+                e.description("React with the number of your choice within 60 seconds.");
+                if let Some(thumbnail) = thumbnail {
+                    e.thumbnail(thumbnail);
+                }
+                for (i, (name, value)) in options.iter().enumerate() {
+                    e.field(format!("{} {}", CHOICE_EMOJI[i], name), value, false);
+                }
+                e
+            });
+            m.reactions(reactions.clone());
+            m
+        })
+        .await?;
+
+    let choice = match &choice_msg
+        .await_reaction(&ctx)
+        .timeout(std::time::Duration::from_secs(60))
+        .author_id(author_id)
+        .await
+    {
+        Some(reaction) => {
+            let emoji = &reaction.as_inner_ref().emoji;
+            reactions.iter().position(|r| r == emoji)
+        }
+        None => None,
+    };
+
+    choice_msg.delete(&ctx.http).await?;
+
+    Ok(choice)
+}
 
 pub enum Confirmation {
     Yes,
@@ -29,8 +89,7 @@ pub async fn ask_confirmation<'a>(
             m.reactions(reactions);
             m
         })
-        .await
-        .unwrap();
+        .await?;
 
     if let Some(reaction) = &update_sub_msg
         .await_reaction(&ctx)
@@ -74,3 +133,8 @@ pub async fn ask_confirmation<'a>(
         Ok(Confirmation::InvalidConfirmation)
     }
 }
+
+// Movie night is run per server, so most commands can't be used in DMs
+pub fn require_guild(guild_id: Option<GuildId>) -> Result<GuildId, Error> {
+    guild_id.ok_or_else(|| Error::UserInput(String::from("Movie night commands only work in a server.")))
+}
@@ -0,0 +1,441 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+use serenity::model::channel::ReactionType;
+use serenity::model::id::{ChannelId, GuildId, MessageId, UserId};
+
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::SmallRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use sha2::{Digest, Sha256};
+
+use kekw_db::models::period::Period;
+use kekw_db::models::selection::Selection;
+use kekw_db::models::submission::Submission;
+
+use crate::availability::Availability;
+use crate::backend::{Backend, Discord, Embed, Store};
+use crate::config::Config;
+use crate::{movie_info, notify};
+use crate::metadata::MovieDetails;
+use crate::settings::GuildSettings;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Sync + Send>>;
+
+pub const MIN_CANDIDATES: usize = 2;
+pub const MAX_CANDIDATES: usize = 10;
+
+// Used when the guild doesn't have enough emoji to vote with
+static FALLBACK_EMOJI: [&'static str; MAX_CANDIDATES] = [
+    "🇦", "🇧", "🇨", "🇩", "🇪", "🇫", "🇬", "🇭", "🇮", "🇯",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VotingMode {
+    // Most reactions on the voting message wins
+    Plurality,
+    // Ballots ranking every movie are sent over DM and tallied with instant runoff
+    Ranked,
+}
+
+impl FromStr for VotingMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "plurality" => Ok(VotingMode::Plurality),
+            "ranked" => Ok(VotingMode::Ranked),
+            _ => Err(format!(
+                "Unknown voting mode {}, expected plurality or ranked",
+                s
+            )),
+        }
+    }
+}
+
+impl ToString for VotingMode {
+    fn to_string(&self) -> String {
+        match self {
+            VotingMode::Plurality => String::from("plurality"),
+            VotingMode::Ranked => String::from("ranked"),
+        }
+    }
+}
+
+// A rolled submission along with what is needed to display it
+pub struct Choice {
+    pub submission: Submission,
+    pub submitter: String,
+    pub emoji: ReactionType,
+    // Movie details, missing if the lookup failed
+    pub movie: Option<MovieDetails>,
+    pub availability: Option<Availability>,
+}
+
+impl Choice {
+    // Who submitted it followed by whatever details we have
+    fn details(&self, submitted: String) -> String {
+        let mut details = match &self.movie {
+            Some(movie) => format!("{}\n{}", submitted, movie_info::field_details(movie)),
+            None => submitted,
+        };
+        if let Some(availability) = &self.availability {
+            details = format!("{}\n{}", details, movie_info::watch_options(availability));
+        }
+
+        details
+    }
+}
+
+// A new seed for a period's roll, kept secret until the roll
+pub fn new_seed() -> u64 {
+    SmallRng::from_entropy().gen()
+}
+
+// The hash of the seed announced when the period opens. Once it's posted the seed
+// can't be swapped for one that rolls differently without the hash changing.
+pub fn commitment(seed: u64) -> String {
+    format!("{:x}", Sha256::digest(seed.to_string().as_bytes()))
+}
+
+// The seed to roll the period with, and whether it was committed to when the period opened.
+// Rolling again would repeat the same picks, so rerolls and older periods get a new seed.
+pub fn roll_seed(period: &Period, rerolled: bool) -> (u64, bool) {
+    match period.roll_seed.as_ref().and_then(|seed| seed.parse::<u64>().ok()) {
+        Some(seed) if !rerolled => (seed, true),
+        _ => (new_seed(), false),
+    }
+}
+
+// How likely each submitter's movie is to be rolled, by discord id.
+// Empty when the guild rolls every submission evenly.
+pub type Weights = HashMap<String, u32>;
+
+pub fn weight(weights: &Weights, movie_sub: &Submission) -> u32 {
+    weights.get(&movie_sub.dis_user_id).copied().unwrap_or(1)
+}
+
+// Each submitter's weight is one more than the number of decided periods since their movie
+// was last picked, counting back at most lookback periods. Only periods before this one count,
+// so the weights stay the same for checking the roll later.
+pub fn submitter_weights(
+    store: &dyn Store,
+    guild_id: GuildId,
+    period: &Period,
+    lookback: Option<u32>,
+) -> Result<Weights> {
+    let mut weights = Weights::new();
+    let lookback = match lookback {
+        Some(lookback) => lookback,
+        None => return Ok(weights),
+    };
+
+    let mut earlier: Vec<Period> = store
+        .periods(guild_id)?
+        .into_iter()
+        .filter(|earlier| earlier.id < period.id)
+        .collect();
+    earlier.sort_by_key(|earlier| std::cmp::Reverse(earlier.id));
+
+    // Most recent first
+    let mut winners: Vec<String> = Vec::new();
+    for earlier_period in earlier {
+        if winners.len() >= lookback as usize {
+            break;
+        }
+        if let Some(winner) = store.roll(&earlier_period)?.and_then(|roll| roll.winner) {
+            winners.push(store.submission(winner)?.dis_user_id);
+        }
+    }
+
+    for movie_sub in store.submissions(period)? {
+        let since_picked = winners
+            .iter()
+            .position(|winner| *winner == movie_sub.dis_user_id)
+            .unwrap_or(lookback as usize);
+        weights.insert(movie_sub.dis_user_id, since_picked as u32 + 1);
+    }
+
+    Ok(weights)
+}
+
+// Pick up to count different submissions using the seed. ChaCha20 gives the same
+// numbers everywhere, and sorting by id first means the same seed and submissions
+// always give the same picks, so anyone can check a roll.
+pub fn pick_selections(movie_subs: &[Submission], count: usize, seed: u64, weights: &Weights) -> Vec<Submission> {
+    let mut sorted: Vec<&Submission> = movie_subs.iter().collect();
+    sorted.sort_by_key(|movie_sub| movie_sub.id);
+
+    let mut rng = ChaCha20Rng::seed_from_u64(seed);
+
+    // Even rolls pick the same way they did before weighting so older rolls still check out
+    if weights.is_empty() {
+        return sorted
+            .choose_multiple(&mut rng, count)
+            .map(|movie_sub| (*movie_sub).clone())
+            .collect();
+    }
+
+    let mut picked = Vec::new();
+    while picked.len() < count && !sorted.is_empty() {
+        let index = WeightedIndex::new(sorted.iter().map(|movie_sub| weight(weights, movie_sub)))
+            .expect("Roll weights are at least 1")
+            .sample(&mut rng);
+        picked.push(sorted.remove(index).clone());
+    }
+
+    picked
+}
+
+// Post the seed once the roll is out, along with how to check it
+pub async fn reveal_seed(
+    discord: &dyn Discord,
+    config: &Config,
+    channel_id: ChannelId,
+    period: &Period,
+    seed: u64,
+    committed: bool,
+) -> Result<MessageId> {
+    let committed = if committed {
+        "announced when the period opened"
+    } else {
+        "not announced in advance since this is a reroll"
+    };
+
+    discord
+        .send_message(
+            channel_id,
+            &format!(
+                "Rolled with seed {}, its hash `{}` was {}. Check the roll with `{}m verifyroll {}`.",
+                seed,
+                commitment(seed),
+                committed,
+                config.prefix,
+                period.id
+            ),
+            None,
+        )
+        .await
+}
+
+// The outcome of rolling a period again from its stored seed
+pub struct Verification {
+    pub seed: u64,
+    // The seed is the one committed to when the period opened
+    pub committed: bool,
+    // The periods the roll looked back over, None if it was even
+    pub weighting: Option<u32>,
+    pub recomputed: Vec<Submission>,
+    pub stored: Vec<Submission>,
+}
+
+impl Verification {
+    pub fn matches(&self) -> bool {
+        let recomputed: Vec<i32> = self.recomputed.iter().map(|movie_sub| movie_sub.id).collect();
+        let stored: Vec<i32> = self.stored.iter().map(|movie_sub| movie_sub.id).collect();
+
+        recomputed == stored
+    }
+}
+
+// Recompute the period's roll from the seed stored with it.
+// None if the period wasn't rolled, or was rolled before seeds were stored.
+pub fn verify_roll(store: &dyn Store, period: &Period) -> Result<Option<Verification>> {
+    let roll = match store.roll(period)? {
+        Some(roll) => roll,
+        None => return Ok(None),
+    };
+    let seed = match roll.seed.as_ref() {
+        Some(seed) => seed.parse::<u64>()?,
+        None => return Ok(None),
+    };
+
+    let mut stored: Vec<Submission> = Vec::new();
+    for selection in store.selections(&roll)? {
+        stored.push(store.submission(selection.submission_id)?);
+    }
+
+    let movie_subs = store.submissions(period)?;
+    let weighting = roll.weighting.map(|periods| periods as u32);
+    let guild_id = GuildId(period.guild_id.parse::<u64>()?);
+    let weights = submitter_weights(store, guild_id, period, weighting)?;
+
+    Ok(Some(Verification {
+        seed,
+        committed: period.roll_seed.as_ref() == Some(&seed.to_string()),
+        weighting,
+        recomputed: pick_selections(&movie_subs, stored.len(), seed, &weights),
+        stored,
+    }))
+}
+
+// Lookup the submitter by discord id
+pub async fn submitter_name(discord: &dyn Discord, movie_sub: &Submission) -> Result<String> {
+    let user_id = UserId::try_from(movie_sub.dis_user_id.parse::<u64>()?)?;
+
+    discord.user_name(user_id).await
+}
+
+// Pick voting emoji from the configured guild emoji names or unicode emoji,
+// falling back to every emoji in the guild.
+pub async fn pick_vote_emoji(
+    discord: &dyn Discord,
+    config: &Config,
+    guild_id: GuildId,
+    count: usize,
+) -> Result<Vec<ReactionType>> {
+    let guild_emojis = discord.guild_emojis(guild_id).await?;
+
+    let mut pool: Vec<ReactionType> = Vec::new();
+
+    if config.voting.emoji.is_empty() {
+        pool = guild_emojis.into_iter().map(|(_, emoji)| emoji).collect();
+    } else {
+        for name in config.voting.emoji.iter().map(|n| n.trim()) {
+            if let Some((_, emoji)) = guild_emojis.iter().find(|(emoji_name, _)| emoji_name == name) {
+                pool.push(emoji.clone());
+            } else if let Ok(emoji) = ReactionType::try_from(name) {
+                pool.push(emoji);
+            }
+        }
+    }
+
+    if pool.len() < count {
+        // Keep the letters in order so they match the order of the choices
+        return Ok(FALLBACK_EMOJI
+            .iter()
+            .take(count)
+            .map(|e| ReactionType::try_from(*e).unwrap())
+            .collect());
+    }
+
+    let mut rng = SmallRng::from_entropy();
+
+    Ok(pool.choose_multiple(&mut rng, count).cloned().collect())
+}
+
+// Build the choices shown in voting messages, in selection order
+pub async fn build_choices(
+    backend: Backend<'_>,
+    movie_subs: Vec<Submission>,
+    emojis: Vec<ReactionType>,
+) -> Result<Vec<Choice>> {
+    let mut choices: Vec<Choice> = Vec::new();
+
+    for (movie_sub, emoji) in movie_subs.into_iter().zip(emojis) {
+        choices.push(Choice {
+            submitter: submitter_name(backend.discord, &movie_sub).await?,
+            movie: backend.catalog.details(&movie_sub.link).await,
+            availability: backend.catalog.availability(&movie_sub.link).await,
+            submission: movie_sub,
+            emoji,
+        });
+    }
+
+    Ok(choices)
+}
+
+// Send the voting message, reacting with each choice's emoji so people can click them
+pub async fn send_vote_message(
+    discord: &dyn Discord,
+    config: &Config,
+    channel_id: ChannelId,
+    title: &str,
+    choices: &[Choice],
+    mode: VotingMode,
+) -> Result<MessageId> {
+    let vote_msg_id = discord.send_embed(channel_id, vote_embed(config, title, choices, mode)).await?;
+    react_choices(discord, channel_id, vote_msg_id, &emojis(choices), mode).await?;
+
+    Ok(vote_msg_id)
+}
+
+fn emojis(choices: &[Choice]) -> Vec<ReactionType> {
+    choices.iter().map(|choice| choice.emoji.clone()).collect()
+}
+
+// Ranked votes come in by DM, so only plurality votes get reactions.
+// Reacting again with the same emoji does nothing, so this is safe to retry.
+pub async fn react_choices(
+    discord: &dyn Discord,
+    channel_id: ChannelId,
+    vote_msg_id: MessageId,
+    emojis: &[ReactionType],
+    mode: VotingMode,
+) -> Result<()> {
+    if mode == VotingMode::Plurality {
+        for emoji in emojis {
+            discord.react(channel_id, vote_msg_id, emoji.clone()).await?;
+        }
+    }
+
+    Ok(())
+}
+
+// How to cast a ranked ballot, with the bot's prefix
+pub fn ranked_example(config: &Config) -> String {
+    format!("`{}m vote 2 1 3`", config.prefix)
+}
+
+fn vote_embed(config: &Config, title: &str, choices: &[Choice], mode: VotingMode) -> Embed {
+    let mut embed = Embed {
+        title: String::from(title),
+        ..Embed::default()
+    };
+    if mode == VotingMode::Ranked {
+        embed.description = Some(format!(
+            "Rank the movies by sending me a DM with their numbers, favourite first. For example {}",
+            ranked_example(config)
+        ));
+    }
+    for (i, choice) in choices.iter().enumerate() {
+        embed.fields.push(match mode {
+            VotingMode::Plurality => (
+                choice.submission.title.clone(),
+                choice.details(format!("submitted by {} use {}", choice.submitter, choice.emoji)),
+            ),
+            VotingMode::Ranked => (
+                format!("{}. {}", i + 1, choice.submission.title),
+                choice.details(format!("submitted by {}", choice.submitter)),
+            ),
+        });
+    }
+
+    // Every choice has to stay on the message, so they are all shortened to fit
+    let reserved = embed.title.chars().count() + embed.description.as_ref().map_or(0, |d| d.chars().count());
+    embed.fields = movie_info::shrink_fields(embed.fields, reserved);
+
+    embed
+}
+
+// Send the voting message for a roll and store it along with each selection's emote
+pub async fn start_vote(
+    backend: Backend<'_>,
+    config: &Config,
+    settings: &GuildSettings,
+    channel_id: ChannelId,
+    cur_period: Period,
+    roll_selections: Vec<Selection>,
+    choices: &[Choice],
+    mode: VotingMode,
+) -> Result<MessageId> {
+    notify::voting_started(backend.discord, config, settings, channel_id).await?;
+    let vote_msg_id = backend
+        .discord
+        .send_embed(channel_id, vote_embed(config, "Movie Voting!", choices, mode))
+        .await?;
+
+    // Stored before reacting, so a retry finishes this message rather than sending another
+    backend.store.set_vote_message(cur_period, vote_msg_id)?;
+
+    for (selection, choice) in roll_selections.into_iter().zip(choices) {
+        backend.store.set_emote(selection, &choice.emoji)?;
+    }
+
+    react_choices(backend.discord, channel_id, vote_msg_id, &emojis(choices), mode).await?;
+
+    Ok(vote_msg_id)
+}